@@ -252,11 +252,15 @@ export default function Collections() {
                         <th className="px-3 py-2">Sample Value</th>
                         <th className="px-3 py-2">Types</th>
                         <th className="px-3 py-2 text-right">Count in Sample</th>
+                        <th className="px-3 py-2 text-right">Null / Missing</th>
                       </tr>
                     </thead>
                     <tbody className="divide-y divide-gray-200">
                       {Object.entries(stats).map(([field, info]) => {
-                        const types = Array.isArray(info?.types) ? info.types.join(', ') : '—';
+                        const percentages = info?.typePercentages || {};
+                        const types = Array.isArray(info?.types)
+                          ? info.types.map((t) => (percentages[t] !== undefined ? `${t} (${percentages[t]}%)` : t)).join(', ')
+                          : '—';
                         const sampleValue = info?.sample ?? null;
                         const renderSample = sampleValue === null || sampleValue === undefined
                           ? '—'
@@ -272,6 +276,9 @@ export default function Collections() {
                             </td>
                             <td className="px-3 py-2 text-gray-600">{types}</td>
                             <td className="px-3 py-2 text-right text-gray-800 font-medium">{count}</td>
+                            <td className="px-3 py-2 text-right text-gray-600">
+                              {info?.nullPercentage ?? 0}% / {info?.missingPercentage ?? 0}%
+                            </td>
                           </tr>
                         );
                       })}
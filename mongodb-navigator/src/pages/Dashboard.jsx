@@ -118,6 +118,19 @@ export default function Dashboard() {
         )}
       </div>
 
+      {stats?.schemaDrift?.length > 0 && (
+        <div className="rounded-lg border border-amber-300 bg-amber-50 px-4 py-3 text-sm text-amber-800">
+          <p className="font-medium">Schema drift detected</p>
+          <ul className="mt-1 list-disc list-inside">
+            {stats.schemaDrift.map((d) => (
+              <li key={`${d.database}.${d.collection}`}>
+                {d.database}.{d.collection}: {d.changes} change{d.changes === 1 ? '' : 's'} since the previous snapshot ({new Date(d.detectedAt).toLocaleString()})
+              </li>
+            ))}
+          </ul>
+        </div>
+      )}
+
       {/* Stats Grid */}
       <div className="grid grid-cols-1 md:grid-cols-2 lg:grid-cols-4 gap-6">
         <Card>
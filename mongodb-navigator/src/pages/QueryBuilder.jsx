@@ -11,6 +11,27 @@ import {
 
 const API_BASE = 'http://127.0.0.1:6969';
 
+// Schema type names ($jsonSchema bsonType or upload-format type) to the builder's field types.
+const SCHEMA_TYPES = {
+  string: 'string',
+  int: 'number',
+  long: 'number',
+  double: 'number',
+  decimal: 'number',
+  number: 'number',
+  integer: 'number',
+  date: 'date',
+  bool: 'boolean',
+  boolean: 'boolean',
+  object: 'object',
+};
+
+const uiType = (spec) => {
+  const declared = spec?.bsonType ?? spec?.type;
+  const names = Array.isArray(declared) ? declared.filter((t) => t !== 'null') : [declared];
+  return names.map((t) => SCHEMA_TYPES[t]).find(Boolean);
+};
+
 export default function QueryBuilder() {
   const [databases, setDatabases] = useState([]);
   const [databasesLoading, setDatabasesLoading] = useState(false);
@@ -36,6 +57,8 @@ export default function QueryBuilder() {
   const [aiMeta, setAiMeta] = useState(null);
   const [aiError, setAiError] = useState(null);
 
+  const [schemaFields, setSchemaFields] = useState([]);
+
   const fieldTypes = ['string', 'number', 'date', 'boolean', 'object'];
   const operators = {
     string: ['equals', 'contains', 'startsWith', 'endsWith', 'regex'],
@@ -112,6 +135,37 @@ export default function QueryBuilder() {
     return () => controller.abort();
   }, [selectedDatabase]);
 
+  useEffect(() => {
+    if (!selectedDatabase || !selectedCollection) {
+      setSchemaFields([]);
+      return;
+    }
+
+    // Flatten the registry schema into dotted paths for field suggestions.
+    const flatten = (properties, prefix, out) => {
+      Object.entries(properties || {}).forEach(([name, spec]) => {
+        const path = prefix ? `${prefix}.${name}` : name;
+        if (spec?.properties) {
+          flatten(spec.properties, path, out);
+        } else {
+          out.push({ path, type: uiType(spec), enum: spec?.enum });
+        }
+      });
+      return out;
+    };
+
+    const controller = new AbortController();
+    fetch(`${API_BASE}/schemas/${selectedDatabase}/${selectedCollection}`, { signal: controller.signal })
+      .then((res) => (res.ok ? res.json() : null))
+      .then((data) => setSchemaFields(data ? flatten(data.schema?.properties, '', []) : []))
+      .catch((err) => {
+        if (err.name === 'AbortError') return;
+        console.error('load schema', err);
+        setSchemaFields([]);
+      });
+    return () => controller.abort();
+  }, [selectedDatabase, selectedCollection]);
+
   useEffect(() => {
     setAiQuery(null);
     setAiMeta(null);
@@ -133,9 +187,18 @@ export default function QueryBuilder() {
   const updateQueryField = (index, key, value) => {
     const updated = [...queryFields];
     updated[index] = { ...updated[index], [key]: value };
+    if (key === 'field') {
+      const known = schemaFields.find((f) => f.path === value);
+      if (known && fieldTypes.includes(known.type)) {
+        updated[index].type = known.type;
+        updated[index].operator = 'equals';
+      }
+    }
     setQueryFields(updated);
   };
 
+  const enumFor = (path) => schemaFields.find((f) => f.path === path)?.enum;
+
   const manualQuery = useMemo(() => {
     const query = {};
     queryFields.forEach(field => {
@@ -361,6 +424,11 @@ export default function QueryBuilder() {
               </CardTitle>
             </CardHeader>
             <CardContent>
+              <datalist id="schema-fields">
+                {schemaFields.map((f) => (
+                  <option key={f.path} value={f.path} />
+                ))}
+              </datalist>
               <div className="space-y-4">
                 {queryFields.map((field, index) => (
                   <div key={index} className="grid grid-cols-12 gap-3 items-end">
@@ -373,6 +441,7 @@ export default function QueryBuilder() {
                         value={field.field}
                         onChange={(e) => updateQueryField(index, 'field', e.target.value)}
                         placeholder="e.g., name"
+                        list="schema-fields"
                         className="w-full border border-gray-300 rounded-lg px-3 py-2 focus:ring-2 focus:ring-indigo-500 focus:border-indigo-500"
                       />
                     </div>
@@ -408,13 +477,26 @@ export default function QueryBuilder() {
                       <label className="block text-sm font-medium text-gray-700 mb-1">
                         Value
                       </label>
-                      <input
-                        type={field.type === 'number' ? 'number' : 'text'}
-                        value={field.value}
-                        onChange={(e) => updateQueryField(index, 'value', e.target.value)}
-                        placeholder="Enter value"
-                        className="w-full border border-gray-300 rounded-lg px-3 py-2 focus:ring-2 focus:ring-indigo-500 focus:border-indigo-500"
-                      />
+                      {enumFor(field.field) ? (
+                        <select
+                          value={field.value}
+                          onChange={(e) => updateQueryField(index, 'value', e.target.value)}
+                          className="w-full border border-gray-300 rounded-lg px-3 py-2 focus:ring-2 focus:ring-indigo-500 focus:border-indigo-500"
+                        >
+                          <option value="">Select value</option>
+                          {enumFor(field.field).map((option) => (
+                            <option key={String(option)} value={option}>{String(option)}</option>
+                          ))}
+                        </select>
+                      ) : (
+                        <input
+                          type={field.type === 'number' ? 'number' : 'text'}
+                          value={field.value}
+                          onChange={(e) => updateQueryField(index, 'value', e.target.value)}
+                          placeholder="Enter value"
+                          className="w-full border border-gray-300 rounded-lg px-3 py-2 focus:ring-2 focus:ring-indigo-500 focus:border-indigo-500"
+                        />
+                      )}
                     </div>
                     <div className="col-span-1">
                       {queryFields.length > 1 && (
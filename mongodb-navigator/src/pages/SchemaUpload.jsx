@@ -1,12 +1,81 @@
-import { useState, useRef } from 'react';
+import { useEffect, useState, useRef } from 'react';
 import { Card, CardHeader, CardTitle, CardContent } from '../components/ui/Card';
 import { Button } from '../components/ui/Button';
 import { DocumentArrowUpIcon, DocumentCheckIcon } from '@heroicons/react/24/outline';
 
+const API_BASE = 'http://127.0.0.1:6969';
+
 export default function SchemaUpload() {
   const [schema, setSchema] = useState(null);
   const [isDragOver, setIsDragOver] = useState(false);
   const fileInputRef = useRef(null);
+  const [databases, setDatabases] = useState([]);
+  const [selectedDatabase, setSelectedDatabase] = useState('');
+  const [savedSchemas, setSavedSchemas] = useState([]);
+  const [saving, setSaving] = useState(false);
+  const [saveError, setSaveError] = useState(null);
+
+  useEffect(() => {
+    fetch(`${API_BASE}/databases`)
+      .then((r) => r.json())
+      .then((data) => {
+        const names = (data || []).map((db) => db.name || db);
+        setDatabases(names);
+        setSelectedDatabase((current) => current || names[0] || '');
+      })
+      .catch((e) => console.error('fetch databases', e));
+  }, []);
+
+  const loadSavedSchemas = async (db) => {
+    if (!db) {
+      setSavedSchemas([]);
+      return;
+    }
+    try {
+      const res = await fetch(`${API_BASE}/schemas/${db}`);
+      if (!res.ok) throw new Error(await res.text());
+      const data = await res.json();
+      setSavedSchemas(data.schemas || []);
+    } catch (e) {
+      console.error('fetch schemas', e);
+      setSavedSchemas([]);
+    }
+  };
+
+  useEffect(() => {
+    loadSavedSchemas(selectedDatabase);
+  }, [selectedDatabase]);
+
+  const saveSchema = async () => {
+    if (!schema || !selectedDatabase) return;
+    setSaving(true);
+    setSaveError(null);
+    try {
+      const res = await fetch(`${API_BASE}/schemas/${selectedDatabase}`, {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify(schema),
+      });
+      if (!res.ok) throw new Error(await res.text());
+      await loadSavedSchemas(selectedDatabase);
+    } catch (e) {
+      console.error('save schema', e);
+      setSaveError(e.message || 'Failed to save schema');
+    } finally {
+      setSaving(false);
+    }
+  };
+
+  const deleteSchema = async (collection) => {
+    if (!window.confirm(`Delete all versions of the ${collection} schema?`)) return;
+    try {
+      const res = await fetch(`${API_BASE}/schemas/${selectedDatabase}/${collection}`, { method: 'DELETE' });
+      if (!res.ok) throw new Error(await res.text());
+      await loadSavedSchemas(selectedDatabase);
+    } catch (e) {
+      console.error('delete schema', e);
+    }
+  };
 
   const handleDragOver = (e) => {
     e.preventDefault();
@@ -136,14 +205,22 @@ export default function SchemaUpload() {
           <CardContent>
             {schema ? (
               <div>
-                <div className="mb-4">
-                  <Button size="sm" variant="outline" className="mr-2">
-                    Save Schema
-                  </Button>
-                  <Button size="sm" variant="outline">
-                    Generate Forms
+                <div className="mb-4 flex items-center gap-2">
+                  <select
+                    value={selectedDatabase}
+                    onChange={(e) => setSelectedDatabase(e.target.value)}
+                    className="border border-gray-300 rounded-lg px-3 py-1 text-sm"
+                  >
+                    <option value="" disabled>Select a database</option>
+                    {databases.map((db) => (
+                      <option key={db} value={db}>{db}</option>
+                    ))}
+                  </select>
+                  <Button size="sm" variant="outline" onClick={saveSchema} disabled={saving || !selectedDatabase}>
+                    {saving ? 'Saving…' : 'Save Schema'}
                   </Button>
                 </div>
+                {saveError && <p className="text-sm text-red-500 mb-2">{saveError}</p>}
                 <div className="bg-gray-50 p-4 rounded-lg max-h-96 overflow-y-auto">
                   <pre className="text-sm font-mono whitespace-pre-wrap">
                     {JSON.stringify(schema, null, 2)}
@@ -166,9 +243,27 @@ export default function SchemaUpload() {
           <CardTitle>Saved Schemas</CardTitle>
         </CardHeader>
         <CardContent>
-          <div className="text-center text-gray-500 py-8">
-            <p>No saved schemas yet. Upload your first schema to get started!</p>
-          </div>
+          {savedSchemas.length === 0 ? (
+            <div className="text-center text-gray-500 py-8">
+              <p>No saved schemas yet. Upload your first schema to get started!</p>
+            </div>
+          ) : (
+            <div className="divide-y divide-gray-200">
+              {savedSchemas.map((saved) => (
+                <div key={saved.collection} className="flex items-center justify-between py-3">
+                  <div>
+                    <div className="font-medium text-gray-900">{saved.collection}</div>
+                    <div className="text-sm text-gray-500">
+                      v{saved.version} • updated {new Date(saved.updatedAt).toLocaleString()}
+                    </div>
+                  </div>
+                  <Button size="sm" variant="outline" onClick={() => deleteSchema(saved.collection)}>
+                    Delete
+                  </Button>
+                </div>
+              ))}
+            </div>
+          )}
         </CardContent>
       </Card>
     </div>
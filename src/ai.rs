@@ -216,18 +216,19 @@ use gemini_client_api::gemini::{
     types::sessions::Session,
 };
 use mongodb::{
-    bson::{doc, Bson, Document},
+    bson::{doc, Document},
     Client,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value as JsonValue};
+
+use crate::sampling::bson_type_name;
 use std::{
     collections::{HashMap, HashSet},
     env,
-    str::FromStr,
 };
 
-const DEFAULT_SAMPLE_SIZE: i64 = 200;
+pub const DEFAULT_SAMPLE_SIZE: i64 = 200;
 
 #[derive(Deserialize)]
 pub struct AiRequest {
@@ -251,32 +252,6 @@ struct FieldInfo {
     sample: Option<JsonValue>,
 }
 
-fn bson_type_name(value: &Bson) -> String {
-    match value {
-        Bson::Double(_) => "double",
-        Bson::String(_) => "string",
-        Bson::Array(_) => "array",
-        Bson::Document(_) => "document",
-        Bson::Boolean(_) => "bool",
-        Bson::Null => "null",
-        Bson::Int32(_) => "int32",
-        Bson::Int64(_) => "int64",
-        Bson::DateTime(_) => "datetime",
-        Bson::Decimal128(_) => "decimal",
-        Bson::ObjectId(_) => "objectId",
-        Bson::Binary(_) => "binary",
-        Bson::Timestamp(_) => "timestamp",
-        Bson::RegularExpression(_) => "regex",
-        Bson::JavaScriptCode(_) | Bson::JavaScriptCodeWithScope(_) => "javascript",
-        Bson::Symbol(_) => "symbol",
-        Bson::Undefined => "undefined",
-        Bson::MaxKey => "maxKey",
-        Bson::MinKey => "minKey",
-        _ => "other",
-    }
-    .to_string()
-}
-
 async fn describe_collection(
     client: &Client,
     database: &str,
@@ -294,7 +269,7 @@ async fn describe_collection(
         for (key, value) in doc.iter() {
             let entry = fields.entry(key.clone()).or_default();
             entry.count += 1;
-            entry.types.insert(bson_type_name(value));
+            entry.types.insert(bson_type_name(value).to_string());
             if entry.sample.is_none() {
                 let json_value = serde_json::to_value(value.clone()).unwrap_or(JsonValue::Null);
                 entry.sample = Some(json_value);
@@ -303,7 +278,7 @@ async fn describe_collection(
     }
 
     let mut sorted_fields: Vec<(String, FieldInfo)> = fields.into_iter().collect();
-    sorted_fields.sort_by(|a, b| b.1.count.cmp(&a.1.count));
+    sorted_fields.sort_by_key(|entry| std::cmp::Reverse(entry.1.count));
 
     let mut field_map = serde_json::Map::new();
     for (name, info) in sorted_fields {
@@ -329,14 +304,14 @@ async fn describe_collection(
 
 fn extract_json_from_text(s: &str) -> &str {
     let s = s.trim();
-    if let Some(start) = s.find("```") {
-        if let Some(end_rel) = s[start + 3..].find("```") {
-            let inner = &s[start + 3..start + 3 + end_rel];
-            if let Some(nl) = inner.find('\n') {
-                return inner[nl + 1..].trim();
-            }
-            return inner.trim();
+    if let Some(start) = s.find("```")
+        && let Some(end_rel) = s[start + 3..].find("```")
+    {
+        let inner = &s[start + 3..start + 3 + end_rel];
+        if let Some(nl) = inner.find('\n') {
+            return inner[nl + 1..].trim();
         }
+        return inner.trim();
     }
     s
 }
@@ -400,10 +375,10 @@ async fn call_gemini(prompt: &str) -> Result<(JsonValue, JsonValue), Box<dyn std
                 return Ok((final_filter, raw_json));
             }
 
-            if let serde_json::Value::Object(map) = &raw_json {
-                if looks_like_filter(map) {
-                    return Ok((raw_json.clone(), raw_json));
-                }
+            if let serde_json::Value::Object(map) = &raw_json
+                && looks_like_filter(map)
+            {
+                return Ok((raw_json.clone(), raw_json));
             }
         }
         Err(err) => {
@@ -0,0 +1,1189 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use actix_web::{post, web, HttpResponse};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, oid::ObjectId, Bson, Document},
+    action::Action,
+    error::{ErrorKind, PartialBulkWriteResult, WriteError, WriteFailure},
+    options::{
+        Collation, DeleteManyModel, DeleteOneModel, FindOptions, Hint, InsertOneModel, ReplaceOneModel, UpdateManyModel,
+        UpdateModifications, UpdateOneModel, WriteModel,
+    },
+    results::{UpdateResult, VerboseBulkWriteResult},
+    Client, Collection, Namespace,
+};
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+use tokio::sync::Mutex;
+
+use crate::concurrency::{bump_update, bumped_replacement};
+use crate::revisions::RevisionStore;
+
+const DEFAULT_PREVIEW_SAMPLE: i64 = 10;
+// How long a dry run's confirmation token stays valid.
+const DRY_RUN_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BulkKind {
+    Update,
+    Delete,
+}
+
+pub struct DryRun {
+    database: String,
+    collection: String,
+    kind: BulkKind,
+    filter: Document,
+    update: Option<UpdateModifications>,
+    matched: u64,
+    created: Instant,
+}
+
+/// Dry runs waiting for confirmation, keyed by their token. Tokens are single-use and expire
+/// after `DRY_RUN_TTL`; the confirmed run executes exactly the filter and update that were previewed.
+pub struct DryRunStore {
+    runs: Mutex<HashMap<String, DryRun>>,
+}
+
+impl DryRunStore {
+    pub fn new() -> Self {
+        Self {
+            runs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn insert(&self, run: DryRun) -> String {
+        let token = ObjectId::new().to_hex();
+        let mut runs = self.runs.lock().await;
+        runs.retain(|_, r| r.created.elapsed() < DRY_RUN_TTL);
+        runs.insert(token.clone(), run);
+        token
+    }
+
+    async fn take(&self, token: &str) -> Option<DryRun> {
+        let mut runs = self.runs.lock().await;
+        runs.remove(token).filter(|r| r.created.elapsed() < DRY_RUN_TTL)
+    }
+}
+
+fn literal(value: &Bson) -> Bson {
+    Bson::Document(doc! { "$literal": value.clone() })
+}
+
+fn field_ref(path: &str) -> String {
+    format!("${}", path)
+}
+
+fn array_or_empty(path: &str) -> Bson {
+    Bson::Document(doc! { "$ifNull": [ field_ref(path), [] ] })
+}
+
+// `{ $push: { tags: { $each: [...] } } }` and `{ $push: { tags: "x" } }` both become a list of values.
+fn each_values(value: &Bson) -> Vec<Bson> {
+    match value {
+        Bson::Document(d) if d.contains_key("$each") => match d.get("$each") {
+            Some(Bson::Array(items)) => items.clone(),
+            _ => Vec::new(),
+        },
+        other => vec![other.clone()],
+    }
+}
+
+fn integer(value: &Bson) -> Option<i64> {
+    match value {
+        Bson::Int32(v) => Some(*v as i64),
+        Bson::Int64(v) => Some(*v),
+        Bson::Double(v) if v.fract() == 0.0 => Some(*v as i64),
+        _ => None,
+    }
+}
+
+/// `$push` as the server applies it: insert at `$position`, then `$sort`, then `$slice`.
+fn push_expression(path: &str, value: &Bson) -> Result<Bson, String> {
+    let values: Vec<Bson> = each_values(value).iter().map(literal).collect();
+    let modifiers = match value {
+        Bson::Document(d) if d.contains_key("$each") => d.clone(),
+        _ => Document::new(),
+    };
+    let current = array_or_empty(path);
+    let mut pushed = match modifiers.get("$position") {
+        None => Bson::Document(doc! { "$concatArrays": [ current, values ] }),
+        Some(p) => {
+            let p = integer(p).ok_or_else(|| format!("$position for {} must be an integer", path))?;
+            // a negative position counts from the end, stopping at the front
+            let at = if p >= 0 {
+                Bson::Int64(p)
+            } else {
+                Bson::Document(doc! { "$max": [ { "$add": [ { "$size": current.clone() }, p ] }, 0 ] })
+            };
+            // $slice needs a positive count, so empty heads and tails are spelled out
+            let head = doc! { "$cond": [ { "$lte": [ at.clone(), 0 ] }, [], { "$slice": [ current.clone(), at.clone() ] } ] };
+            let tail = doc! { "$slice": [ current.clone(), at, { "$max": [ { "$size": current }, 1 ] } ] };
+            Bson::Document(doc! { "$concatArrays": [ head, values, tail ] })
+        }
+    };
+    if let Some(order) = modifiers.get("$sort") {
+        pushed = Bson::Document(doc! { "$sortArray": { "input": pushed, "sortBy": order.clone() } });
+    }
+    if let Some(n) = modifiers.get("$slice") {
+        pushed = match integer(n).ok_or_else(|| format!("$slice for {} must be an integer", path))? {
+            0 => Bson::Array(Vec::new()),
+            n => Bson::Document(doc! { "$slice": [ pushed, n ] }),
+        };
+    }
+    if let Some(other) = modifiers.keys().find(|k| !["$each", "$position", "$sort", "$slice"].contains(&k.as_str())) {
+        return Err(format!("$push modifier {} cannot be previewed", other));
+    }
+    Ok(pushed)
+}
+
+/// Rewrites an update (operator document or pipeline) into aggregation stages, so a dry run can
+/// show what matched documents would look like afterwards without writing anything.
+/// Positional paths, array indexes in paths and `$pull` conditions have no aggregation equivalent
+/// and are rejected.
+pub fn update_to_pipeline(update: &UpdateModifications) -> Result<Vec<Document>, String> {
+    let ops = match update {
+        UpdateModifications::Pipeline(stages) => return Ok(stages.clone()),
+        UpdateModifications::Document(d) => d,
+        _ => return Err("unsupported update".to_string()),
+    };
+
+    let mut stages = Vec::new();
+    for (op, spec) in ops {
+        let fields = match spec {
+            Bson::Document(d) => d,
+            _ => return Err(format!("{} expects a document", op)),
+        };
+        if let Some(path) = fields.keys().find(|k| k.split('.').any(|s| s.starts_with('$'))) {
+            return Err(format!("positional path {} cannot be previewed", path));
+        }
+        // in a pipeline `items.0.qty` sets a field named "0" instead of indexing the array
+        if let Some(path) = fields.keys().find(|k| k.split('.').any(|s| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()))) {
+            return Err(format!("array index path {} cannot be previewed", path));
+        }
+
+        let mut set = Document::new();
+        let mut unset: Vec<Bson> = Vec::new();
+        for (path, value) in fields {
+            let current = field_ref(path);
+            match op.as_str() {
+                "$set" => {
+                    set.insert(path, literal(value));
+                }
+                "$unset" => unset.push(Bson::String(path.clone())),
+                "$inc" => {
+                    set.insert(path, doc! { "$add": [ { "$ifNull": [ &current, 0 ] }, value.clone() ] });
+                }
+                "$mul" => {
+                    set.insert(path, doc! { "$multiply": [ { "$ifNull": [ &current, 0 ] }, value.clone() ] });
+                }
+                "$min" => {
+                    set.insert(path, doc! { "$min": [ &current, literal(value) ] });
+                }
+                "$max" => {
+                    set.insert(path, doc! { "$max": [ &current, literal(value) ] });
+                }
+                "$rename" => {
+                    let target = value.as_str().ok_or_else(|| format!("$rename target for {} must be a string", path))?;
+                    stages.push(doc! { "$set": { target: { "$ifNull": [ &current, "$$REMOVE" ] } } });
+                    unset.push(Bson::String(path.clone()));
+                }
+                "$currentDate" => {
+                    let timestamp = matches!(value, Bson::Document(d) if d.get_str("$type") == Ok("timestamp"));
+                    set.insert(path, if timestamp { "$$CLUSTER_TIME" } else { "$$NOW" });
+                }
+                "$push" => {
+                    set.insert(path, push_expression(path, value)?);
+                }
+                "$addToSet" => {
+                    // appends the values not present yet, keeping the existing order and any
+                    // duplicates already in the array ($setUnion would dedupe and reorder them)
+                    let mut values: Vec<Bson> = Vec::new();
+                    for v in each_values(value) {
+                        if !values.contains(&v) {
+                            values.push(v);
+                        }
+                    }
+                    let values: Vec<Bson> = values.iter().map(literal).collect();
+                    let added = doc! { "$filter": {
+                        "input": values,
+                        "cond": { "$not": [ { "$in": [ "$$this", array_or_empty(path) ] } ] },
+                    } };
+                    set.insert(path, doc! { "$concatArrays": [ array_or_empty(path), added ] });
+                }
+                "$pull" => {
+                    if matches!(value, Bson::Document(d) if d.keys().any(|k| k.starts_with('$'))) {
+                        return Err(format!("$pull with a condition on {} cannot be previewed", path));
+                    }
+                    set.insert(path, doc! { "$filter": { "input": array_or_empty(path), "cond": { "$ne": [ "$$this", literal(value) ] } } });
+                }
+                "$pullAll" => {
+                    set.insert(path, doc! { "$filter": {
+                        "input": array_or_empty(path),
+                        "cond": { "$not": [ { "$in": [ "$$this", literal(value) ] } ] },
+                    } });
+                }
+                "$pop" => {
+                    let size = doc! { "$size": array_or_empty(path) };
+                    let start = if value.as_i32() == Some(-1) || value.as_i64() == Some(-1) || value.as_f64() == Some(-1.0) { 1 } else { 0 };
+                    // $slice rejects a count of 0, so arrays of zero or one element become [] directly
+                    let kept = doc! { "$cond": [
+                        { "$lte": [ size.clone(), 1 ] },
+                        [],
+                        { "$slice": [ array_or_empty(path), start, { "$subtract": [ size, 1 ] } ] },
+                    ] };
+                    set.insert(path, kept);
+                }
+                // only applies when an upsert inserts, which updateMany previews never do
+                "$setOnInsert" => {}
+                other => return Err(format!("update operator {} cannot be previewed", other)),
+            }
+        }
+        if !set.is_empty() {
+            stages.push(doc! { "$set": set });
+        }
+        if !unset.is_empty() {
+            stages.push(doc! { "$unset": unset });
+        }
+    }
+    Ok(stages)
+}
+
+fn parse_filter(filter: Option<JsonValue>) -> Result<Document, String> {
+    match filter.map(|f| bson::to_bson(&f)) {
+        None => Ok(Document::new()),
+        Some(Ok(Bson::Document(d))) => Ok(d),
+        Some(Ok(_)) => Err("filter must be an object".to_string()),
+        Some(Err(e)) => Err(format!("invalid filter: {}", e)),
+    }
+}
+
+/// An operator document (`{"$set": ...}`) or an aggregation pipeline; replacement documents are
+/// rejected since updateMany cannot replace.
+fn parse_update(update: Option<JsonValue>) -> Result<UpdateModifications, String> {
+    match update.map(|u| bson::to_bson(&u)) {
+        None => Err("update is required".to_string()),
+        Some(Ok(Bson::Document(d))) => {
+            if d.is_empty() || d.keys().any(|k| !k.starts_with('$')) {
+                return Err("update must only contain update operators".to_string());
+            }
+            Ok(UpdateModifications::Document(d))
+        }
+        Some(Ok(Bson::Array(stages))) => {
+            let mut pipeline = Vec::new();
+            for stage in stages {
+                match stage {
+                    Bson::Document(d) => pipeline.push(d),
+                    _ => return Err("pipeline stages must be objects".to_string()),
+                }
+            }
+            Ok(UpdateModifications::Pipeline(pipeline))
+        }
+        Some(Ok(_)) => Err("update must be an object or a pipeline".to_string()),
+        Some(Err(e)) => Err(format!("invalid update: {}", e)),
+    }
+}
+
+fn to_json(doc: &Document) -> JsonValue {
+    serde_json::to_value(doc).unwrap_or(JsonValue::Null)
+}
+
+async fn preview_documents(coll: &Collection<Document>, filter: &Document, sample: i64) -> mongodb::error::Result<Vec<Document>> {
+    coll.find(filter.clone())
+        .sort(doc! { "_id": 1 })
+        .limit(sample.max(1))
+        .await?
+        .try_collect()
+        .await
+}
+
+#[derive(Deserialize)]
+pub struct DryRunRequest {
+    filter: Option<JsonValue>,
+    update: Option<JsonValue>,
+    sample: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmRequest {
+    pub token: String,
+}
+
+#[post("/documents/{db_name}/{coll_name}/update-many/dry-run")]
+pub async fn update_many_dry_run(
+    path: web::Path<(String, String)>,
+    body: web::Json<DryRunRequest>,
+    data: web::Data<Client>,
+    dry_runs: web::Data<DryRunStore>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let req = body.into_inner();
+    let filter = match parse_filter(req.filter) {
+        Ok(f) => f,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let update = match parse_update(req.update) {
+        Ok(u) => u,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let stages = match update_to_pipeline(&update) {
+        Ok(s) => s,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let coll = data.database(&db_name).collection::<Document>(&coll_name);
+
+    let matched = match coll.count_documents(filter.clone()).await {
+        Ok(n) => n,
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("count failed: {}", e))),
+    };
+    let before = match preview_documents(&coll, &filter, req.sample.unwrap_or(DEFAULT_PREVIEW_SAMPLE)).await {
+        Ok(docs) => docs,
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("preview failed: {}", e))),
+    };
+
+    let ids: Vec<Bson> = before.iter().filter_map(|d| d.get("_id").cloned()).collect();
+    let mut pipeline = vec![doc! { "$match": { "_id": { "$in": ids } } }];
+    pipeline.extend(stages);
+    let after: Vec<Document> = match coll.aggregate(pipeline).await {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(docs) => docs,
+            Err(e) => return Ok(HttpResponse::BadRequest().body(format!("update preview failed: {}", e))),
+        },
+        Err(e) => return Ok(HttpResponse::BadRequest().body(format!("update preview failed: {}", e))),
+    };
+
+    let samples: Vec<JsonValue> = before
+        .iter()
+        .map(|b| {
+            let updated = after.iter().find(|a| a.get("_id") == b.get("_id"));
+            json!({ "before": to_json(b), "after": updated.map(to_json).unwrap_or(JsonValue::Null) })
+        })
+        .collect();
+
+    let token = dry_runs
+        .insert(DryRun {
+            database: db_name,
+            collection: coll_name,
+            kind: BulkKind::Update,
+            filter,
+            update: Some(update),
+            matched,
+            created: Instant::now(),
+        })
+        .await;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "matched": matched,
+        "samples": samples,
+        "token": token,
+        "expiresInSecs": DRY_RUN_TTL.as_secs(),
+    })))
+}
+
+#[post("/documents/{db_name}/{coll_name}/delete-many/dry-run")]
+pub async fn delete_many_dry_run(
+    path: web::Path<(String, String)>,
+    body: web::Json<DryRunRequest>,
+    data: web::Data<Client>,
+    dry_runs: web::Data<DryRunStore>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let req = body.into_inner();
+    let filter = match parse_filter(req.filter) {
+        Ok(f) => f,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let coll = data.database(&db_name).collection::<Document>(&coll_name);
+
+    let matched = match coll.count_documents(filter.clone()).await {
+        Ok(n) => n,
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("count failed: {}", e))),
+    };
+    let before = match preview_documents(&coll, &filter, req.sample.unwrap_or(DEFAULT_PREVIEW_SAMPLE)).await {
+        Ok(docs) => docs,
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("preview failed: {}", e))),
+    };
+    let samples: Vec<JsonValue> = before
+        .iter()
+        .map(|b| json!({ "before": to_json(b), "after": JsonValue::Null }))
+        .collect();
+
+    let token = dry_runs
+        .insert(DryRun {
+            database: db_name,
+            collection: coll_name,
+            kind: BulkKind::Delete,
+            filter,
+            update: None,
+            matched,
+            created: Instant::now(),
+        })
+        .await;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "matched": matched,
+        "samples": samples,
+        "token": token,
+        "expiresInSecs": DRY_RUN_TTL.as_secs(),
+    })))
+}
+
+pub async fn confirmed_run(
+    dry_runs: &DryRunStore,
+    token: &str,
+    db_name: &str,
+    coll_name: &str,
+    kind: BulkKind,
+) -> Result<DryRun, HttpResponse> {
+    match dry_runs.take(token).await {
+        Some(run) if run.database == db_name && run.collection == coll_name && run.kind == kind => Ok(run),
+        Some(_) => Err(HttpResponse::BadRequest().body("token belongs to a different dry run")),
+        None => Err(HttpResponse::Conflict().body("unknown or expired token; run the dry run again")),
+    }
+}
+
+/// Executes a confirmed dry run: records revisions for everything it matches, then writes.
+pub async fn execute(client: &Client, revisions: &RevisionStore, run: DryRun) -> Result<JsonValue, String> {
+    let coll = client.database(&run.database).collection::<Document>(&run.collection);
+    let operation = match run.kind {
+        BulkKind::Update => "updateMany",
+        BulkKind::Delete => "delete",
+    };
+    if let Err(e) = revisions.record_matching(&coll, run.filter.clone(), None, operation).await {
+        eprintln!("revision recording error: {}", e);
+        return Err(format!("failed to record revisions: {}", e));
+    }
+
+    match run.kind {
+        BulkKind::Update => {
+            let update = bump_update(run.update.unwrap_or_else(|| UpdateModifications::Document(Document::new())));
+            match coll.update_many(run.filter, update).await {
+                Ok(r) => Ok(json!({
+                    "previewMatched": run.matched,
+                    "matched": r.matched_count,
+                    "modified": r.modified_count,
+                })),
+                Err(e) => {
+                    eprintln!("updateMany error: {}", e);
+                    Err(format!("updateMany failed: {}", e))
+                }
+            }
+        }
+        BulkKind::Delete => match coll.delete_many(run.filter).await {
+            Ok(r) => Ok(json!({
+                "previewMatched": run.matched,
+                "deleted": r.deleted_count,
+            })),
+            Err(e) => {
+                eprintln!("deleteMany error: {}", e);
+                Err(format!("deleteMany failed: {}", e))
+            }
+        },
+    }
+}
+
+#[post("/documents/{db_name}/{coll_name}/update-many")]
+pub async fn update_many(
+    path: web::Path<(String, String)>,
+    body: web::Json<ConfirmRequest>,
+    data: web::Data<Client>,
+    dry_runs: web::Data<DryRunStore>,
+    revisions: web::Data<RevisionStore>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let run = match confirmed_run(&dry_runs, &body.token, &db_name, &coll_name, BulkKind::Update).await {
+        Ok(run) => run,
+        Err(resp) => return Ok(resp),
+    };
+    match execute(&data, &revisions, run).await {
+        Ok(result) => Ok(HttpResponse::Ok().json(result)),
+        Err(e) => Ok(HttpResponse::InternalServerError().body(e)),
+    }
+}
+
+#[post("/documents/{db_name}/{coll_name}/delete-many")]
+pub async fn delete_many(
+    path: web::Path<(String, String)>,
+    body: web::Json<ConfirmRequest>,
+    data: web::Data<Client>,
+    dry_runs: web::Data<DryRunStore>,
+    revisions: web::Data<RevisionStore>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let run = match confirmed_run(&dry_runs, &body.token, &db_name, &coll_name, BulkKind::Delete).await {
+        Ok(run) => run,
+        Err(resp) => return Ok(resp),
+    };
+    match execute(&data, &revisions, run).await {
+        Ok(result) => Ok(HttpResponse::Ok().json(result)),
+        Err(e) => Ok(HttpResponse::InternalServerError().body(e)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct BulkWriteRequest {
+    // defaults to true: stop at the first failing operation
+    ordered: Option<bool>,
+    // shell bulkWrite syntax, e.g. {"updateOne": {"filter": {...}, "update": {...}, "upsert": true}}
+    operations: Vec<JsonValue>,
+}
+
+struct BulkOperation {
+    name: String,
+    model: WriteModel,
+    touches: Option<Touches>,
+}
+
+impl BulkOperation {
+    // updateMany/deleteMany, which may have changed some documents even when they fail
+    fn many(&self) -> bool {
+        self.touches.as_ref().is_some_and(|t| t.limit.is_none())
+    }
+}
+
+/// The documents an operation may change, selected as the write selects them, so the ones
+/// recorded as revisions before it are the ones it goes on to change.
+#[derive(Debug)]
+struct Touches {
+    filter: Document,
+    // 1 for the *One operations, None for updateMany/deleteMany
+    limit: Option<i64>,
+    sort: Option<Document>,
+    hint: Option<Bson>,
+    collation: Option<Document>,
+}
+
+impl Touches {
+    fn find_options(&self) -> FindOptions {
+        FindOptions::builder()
+            .limit(self.limit)
+            .sort(self.sort.clone())
+            .hint(hint(self.hint.clone()))
+            .collation(collation(self.collation.clone()))
+            .build()
+    }
+}
+
+// maxWireVersion of MongoDB 8.0, the first server with the `bulkWrite` command
+const BULK_WRITE_WIRE_VERSION: i32 = 25;
+
+pub async fn supports_bulk_write(client: &Client) -> mongodb::error::Result<bool> {
+    let hello = client.database("admin").run_command(doc! { "hello": 1 }).await?;
+    Ok(hello.get_i32("maxWireVersion").unwrap_or(0) >= BULK_WRITE_WIRE_VERSION)
+}
+
+fn op_document(body: &JsonValue, key: &str) -> Result<Document, String> {
+    match body.get(key).map(bson::to_bson) {
+        Some(Ok(Bson::Document(d))) => Ok(d),
+        Some(Ok(_)) => Err(format!("{} must be an object", key)),
+        Some(Err(e)) => Err(format!("invalid {}: {}", key, e)),
+        None => Err(format!("{} is required", key)),
+    }
+}
+
+fn op_optional_document(body: &JsonValue, key: &str) -> Result<Option<Document>, String> {
+    match body.get(key) {
+        None | Some(JsonValue::Null) => Ok(None),
+        Some(_) => op_document(body, key).map(Some),
+    }
+}
+
+fn op_hint(body: &JsonValue) -> Result<Option<Bson>, String> {
+    match body.get("hint").map(bson::to_bson) {
+        None | Some(Ok(Bson::Null)) => Ok(None),
+        Some(Ok(h @ (Bson::String(_) | Bson::Document(_)))) => Ok(Some(h)),
+        Some(_) => Err("hint must be an index name or key pattern".to_string()),
+    }
+}
+
+fn op_collation(body: &JsonValue) -> Result<Option<Document>, String> {
+    let collation = op_optional_document(body, "collation")?;
+    if let Some(c) = &collation {
+        bson::from_document::<Collation>(c.clone()).map_err(|e| format!("invalid collation: {}", e))?;
+    }
+    Ok(collation)
+}
+
+fn hint(raw: Option<Bson>) -> Option<Hint> {
+    match raw? {
+        Bson::String(name) => Some(Hint::Name(name)),
+        Bson::Document(keys) => Some(Hint::Keys(keys)),
+        _ => None,
+    }
+}
+
+// collations are checked by op_collation when the operation is parsed
+fn collation(raw: Option<Document>) -> Option<Collation> {
+    raw.and_then(|c| bson::from_document(c).ok())
+}
+
+fn op_array_filters(body: &JsonValue) -> Result<Option<Vec<Bson>>, String> {
+    match body.get("arrayFilters").map(bson::to_bson) {
+        None => Ok(None),
+        Some(Ok(Bson::Array(items))) => Ok(Some(items)),
+        Some(_) => Err("arrayFilters must be an array".to_string()),
+    }
+}
+
+fn parse_operation(ns: &Namespace, op: &JsonValue) -> Result<BulkOperation, String> {
+    let (name, body) = match op.as_object() {
+        Some(map) if map.len() == 1 => map.iter().next().map(|(k, v)| (k.clone(), v)).unwrap_or_default(),
+        _ => return Err("each operation must be an object with a single operation name".to_string()),
+    };
+    let upsert = body.get("upsert").and_then(JsonValue::as_bool);
+    let hint = op_hint(body)?;
+    let collation = op_collation(body)?;
+    // the server only takes a sort for updateOne and replaceOne
+    let sort = match name.as_str() {
+        "updateOne" | "replaceOne" => op_optional_document(body, "sort")?,
+        _ => None,
+    };
+    let touches = |filter: &Document, limit: Option<i64>| {
+        Some(Touches {
+            filter: filter.clone(),
+            limit,
+            sort: sort.clone(),
+            hint: hint.clone(),
+            collation: collation.clone(),
+        })
+    };
+    let (model, touches): (WriteModel, Option<Touches>) = match name.as_str() {
+        "insertOne" => (
+            InsertOneModel::builder().namespace(ns.clone()).document(op_document(body, "document")?).build().into(),
+            None,
+        ),
+        "updateOne" | "updateMany" => {
+            let filter = op_document(body, "filter")?;
+            let update = bump_update(parse_update(body.get("update").cloned())?);
+            let array_filters = op_array_filters(body)?;
+            if name == "updateOne" {
+                let model = UpdateOneModel::builder()
+                    .namespace(ns.clone())
+                    .filter(filter.clone())
+                    .update(update)
+                    .upsert(upsert)
+                    .array_filters(array_filters)
+                    .sort(sort.clone())
+                    .hint(hint.clone())
+                    .collation(collation.clone())
+                    .build();
+                (model.into(), touches(&filter, Some(1)))
+            } else {
+                let model = UpdateManyModel::builder()
+                    .namespace(ns.clone())
+                    .filter(filter.clone())
+                    .update(update)
+                    .upsert(upsert)
+                    .array_filters(array_filters)
+                    .hint(hint.clone())
+                    .collation(collation.clone())
+                    .build();
+                (model.into(), touches(&filter, None))
+            }
+        }
+        "replaceOne" => {
+            let filter = op_document(body, "filter")?;
+            let replacement = op_document(body, "replacement")?;
+            if replacement.keys().any(|k| k.starts_with('$')) {
+                return Err("replacement cannot contain update operators".to_string());
+            }
+            let model: WriteModel = match bumped_replacement(&replacement) {
+                Some(pipeline) => UpdateOneModel::builder()
+                    .namespace(ns.clone())
+                    .filter(filter.clone())
+                    .update(UpdateModifications::Pipeline(pipeline))
+                    .upsert(upsert)
+                    .sort(sort.clone())
+                    .hint(hint.clone())
+                    .collation(collation.clone())
+                    .build()
+                    .into(),
+                None => ReplaceOneModel::builder()
+                    .namespace(ns.clone())
+                    .filter(filter.clone())
+                    .replacement(replacement)
+                    .upsert(upsert)
+                    .sort(sort.clone())
+                    .hint(hint.clone())
+                    .collation(collation.clone())
+                    .build()
+                    .into(),
+            };
+            (model, touches(&filter, Some(1)))
+        }
+        "deleteOne" | "deleteMany" => {
+            let filter = op_document(body, "filter")?;
+            if name == "deleteOne" {
+                let model = DeleteOneModel::builder()
+                    .namespace(ns.clone())
+                    .filter(filter.clone())
+                    .hint(hint.clone())
+                    .collation(collation.clone())
+                    .build();
+                (model.into(), touches(&filter, Some(1)))
+            } else {
+                let model = DeleteManyModel::builder()
+                    .namespace(ns.clone())
+                    .filter(filter.clone())
+                    .hint(hint.clone())
+                    .collation(collation.clone())
+                    .build();
+                (model.into(), touches(&filter, None))
+            }
+        }
+        other => return Err(format!("unknown operation: {}", other)),
+    };
+    Ok(BulkOperation { name, model, touches })
+}
+
+fn operation_results(
+    operations: &[BulkOperation],
+    results: Option<&VerboseBulkWriteResult>,
+    errors: &HashMap<usize, WriteError>,
+) -> Vec<JsonValue> {
+    operations
+        .iter()
+        .enumerate()
+        .map(|(index, op)| {
+            if let Some(e) = errors.get(&index) {
+                return json!({
+                    "index": index,
+                    "operation": op.name,
+                    "status": "error",
+                    "error": { "code": e.code, "codeName": e.code_name, "message": e.message },
+                });
+            }
+            if let Some(r) = results.and_then(|r| r.insert_results.get(&index)) {
+                return json!({ "index": index, "operation": op.name, "status": "ok", "insertedId": r.inserted_id });
+            }
+            if let Some(r) = results.and_then(|r| r.update_results.get(&index)) {
+                return json!({
+                    "index": index,
+                    "operation": op.name,
+                    "status": "ok",
+                    "matched": r.matched_count,
+                    "modified": r.modified_count,
+                    "upsertedId": r.upserted_id,
+                });
+            }
+            if let Some(r) = results.and_then(|r| r.delete_results.get(&index)) {
+                return json!({ "index": index, "operation": op.name, "status": "ok", "deleted": r.deleted_count });
+            }
+            // ordered writes stop at the first error
+            json!({ "index": index, "operation": op.name, "status": "notExecuted" })
+        })
+        .collect()
+}
+
+fn summary_json(results: Option<&VerboseBulkWriteResult>) -> JsonValue {
+    match results {
+        Some(r) => json!({
+            "inserted": r.summary.inserted_count,
+            "matched": r.summary.matched_count,
+            "modified": r.summary.modified_count,
+            "upserted": r.summary.upserted_count,
+            "deleted": r.summary.deleted_count,
+        }),
+        None => JsonValue::Null,
+    }
+}
+
+fn reported(results: &VerboseBulkWriteResult, index: usize) -> bool {
+    results.insert_results.contains_key(&index)
+        || results.update_results.contains_key(&index)
+        || results.delete_results.contains_key(&index)
+}
+
+/// Drops the before-images of documents no executed operation touched.
+async fn discard_unexecuted(
+    revisions: &RevisionStore,
+    recorded: HashMap<String, ObjectId>,
+    touched_by: &HashMap<String, Vec<usize>>,
+    executed: impl Fn(usize) -> bool,
+) {
+    let stale: Vec<ObjectId> = recorded
+        .into_iter()
+        .filter(|(key, _)| !touched_by.get(key).is_some_and(|ops| ops.iter().any(|i| executed(*i))))
+        .map(|(_, id)| id)
+        .collect();
+    if !stale.is_empty()
+        && let Err(e) = revisions.discard(stale).await
+    {
+        eprintln!("revision discard error: {}", e);
+    }
+}
+
+/// Runs the operations as one `bulkWrite`. Before-images are taken up front, once per document
+/// (its state before the request), and dropped again for documents only touched by operations
+/// the result reports as not executed.
+async fn run_bulk_write(
+    client: &Client,
+    coll: &Collection<Document>,
+    revisions: &RevisionStore,
+    operations: &[BulkOperation],
+    ordered: bool,
+) -> Result<(bool, JsonValue), String> {
+    let mut recorded = HashMap::new();
+    let mut touched_by: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, op) in operations.iter().enumerate() {
+        if let Some(touches) = &op.touches {
+            let keys = revisions
+                .record_matching_once(coll, touches.filter.clone(), touches.find_options(), &op.name, &mut recorded)
+                .await
+                .map_err(|e| format!("failed to record revisions: {}", e))?;
+            for key in keys {
+                touched_by.entry(key).or_default().push(index);
+            }
+        }
+    }
+
+    let models: Vec<WriteModel> = operations.iter().map(|op| op.model.clone()).collect();
+    match client.bulk_write(models).ordered(ordered).verbose_results().await {
+        Ok(result) => Ok((
+            true,
+            json!({
+                "summary": summary_json(Some(&result)),
+                "results": operation_results(operations, Some(&result), &HashMap::new()),
+            }),
+        )),
+        Err(e) => match *e.kind {
+            ErrorKind::BulkWrite(ref failure) => {
+                let partial = match &failure.partial_result {
+                    Some(PartialBulkWriteResult::Verbose(r)) => Some(r),
+                    _ => None,
+                };
+                // a failed updateMany/deleteMany may still have changed some documents
+                let executed = |index: usize| {
+                    partial.is_some_and(|r| reported(r, index))
+                        || (failure.write_errors.contains_key(&index) && operations[index].many())
+                };
+                discard_unexecuted(revisions, recorded, &touched_by, executed).await;
+                let concern: Vec<JsonValue> = failure
+                    .write_concern_errors
+                    .iter()
+                    .map(|w| json!({ "code": w.code, "codeName": w.code_name, "message": w.message }))
+                    .collect();
+                Ok((
+                    false,
+                    json!({
+                        "summary": summary_json(partial),
+                        "results": operation_results(operations, partial, &failure.write_errors),
+                        "writeConcernErrors": concern,
+                    }),
+                ))
+            }
+            // outcome unknown, so the before-images are kept
+            _ => Err(format!("bulk write failed: {}", e)),
+        },
+    }
+}
+
+fn error_json(e: &mongodb::error::Error) -> JsonValue {
+    match *e.kind {
+        ErrorKind::Write(WriteFailure::WriteError(ref w)) => {
+            json!({ "code": w.code, "codeName": w.code_name, "message": w.message })
+        }
+        _ => json!({ "message": e.to_string() }),
+    }
+}
+
+fn update_json(r: &UpdateResult) -> JsonValue {
+    json!({ "matched": r.matched_count, "modified": r.modified_count, "upsertedId": r.upserted_id })
+}
+
+fn array_filters(filters: Option<Vec<Bson>>) -> Option<Vec<Document>> {
+    filters.map(|f| f.into_iter().filter_map(|b| b.as_document().cloned()).collect())
+}
+
+/// One operation through the collection API, reported in `operation_results`' shape.
+async fn run_one(coll: &Collection<Document>, model: WriteModel) -> Result<JsonValue, JsonValue> {
+    let result = match model {
+        WriteModel::InsertOne(m) => coll.insert_one(m.document).await.map(|r| json!({ "insertedId": r.inserted_id })),
+        WriteModel::UpdateOne(m) => coll
+            .update_one(m.filter, m.update)
+            .optional(m.upsert, |a, u| a.upsert(u))
+            .optional(array_filters(m.array_filters), |a, f| a.array_filters(f))
+            .optional(m.sort, |a, s| a.sort(s))
+            .optional(hint(m.hint), |a, h| a.hint(h))
+            .optional(collation(m.collation), |a, c| a.collation(c))
+            .await
+            .map(|r| update_json(&r)),
+        WriteModel::UpdateMany(m) => coll
+            .update_many(m.filter, m.update)
+            .optional(m.upsert, |a, u| a.upsert(u))
+            .optional(array_filters(m.array_filters), |a, f| a.array_filters(f))
+            .optional(hint(m.hint), |a, h| a.hint(h))
+            .optional(collation(m.collation), |a, c| a.collation(c))
+            .await
+            .map(|r| update_json(&r)),
+        WriteModel::ReplaceOne(m) => coll
+            .replace_one(m.filter, m.replacement)
+            .optional(m.upsert, |a, u| a.upsert(u))
+            .optional(m.sort, |a, s| a.sort(s))
+            .optional(hint(m.hint), |a, h| a.hint(h))
+            .optional(collation(m.collation), |a, c| a.collation(c))
+            .await
+            .map(|r| update_json(&r)),
+        WriteModel::DeleteOne(m) => coll
+            .delete_one(m.filter)
+            .optional(hint(m.hint), |a, h| a.hint(h))
+            .optional(collation(m.collation), |a, c| a.collation(c))
+            .await
+            .map(|r| json!({ "deleted": r.deleted_count })),
+        WriteModel::DeleteMany(m) => coll
+            .delete_many(m.filter)
+            .optional(hint(m.hint), |a, h| a.hint(h))
+            .optional(collation(m.collation), |a, c| a.collation(c))
+            .await
+            .map(|r| json!({ "deleted": r.deleted_count })),
+        _ => return Err(json!({ "message": "unsupported operation" })),
+    };
+    result.map_err(|e| error_json(&e))
+}
+
+/// Runs the operations one at a time, for servers without `bulkWrite` (before 8.0). Each
+/// operation's before-images are taken right before it runs, so they include earlier operations.
+async fn run_sequentially(
+    coll: &Collection<Document>,
+    revisions: &RevisionStore,
+    operations: &[BulkOperation],
+    ordered: bool,
+) -> Result<(bool, JsonValue), String> {
+    let mut results = Vec::new();
+    let mut summary = json!({ "inserted": 0, "matched": 0, "modified": 0, "upserted": 0, "deleted": 0 });
+    let mut failed = false;
+    for (index, op) in operations.iter().enumerate() {
+        let mut line = json!({ "index": index, "operation": op.name });
+        if failed && ordered {
+            line["status"] = json!("notExecuted");
+            results.push(line);
+            continue;
+        }
+        let mut recorded = HashMap::new();
+        if let Some(touches) = &op.touches {
+            revisions
+                .record_matching_once(coll, touches.filter.clone(), touches.find_options(), &op.name, &mut recorded)
+                .await
+                .map_err(|e| format!("failed to record revisions: {}", e))?;
+        }
+        match run_one(coll, op.model.clone()).await {
+            Ok(outcome) => {
+                let count = |key: &str| outcome.get(key).and_then(JsonValue::as_u64).unwrap_or(0);
+                let upserted = outcome.get("upsertedId").is_some_and(|id| !id.is_null());
+                for (key, add) in [
+                    ("inserted", outcome.get("insertedId").is_some() as u64),
+                    ("matched", count("matched")),
+                    ("modified", count("modified")),
+                    ("upserted", upserted as u64),
+                    ("deleted", count("deleted")),
+                ] {
+                    summary[key] = json!(summary[key].as_u64().unwrap_or(0) + add);
+                }
+                line["status"] = json!("ok");
+                if let (JsonValue::Object(line), JsonValue::Object(outcome)) = (&mut line, outcome) {
+                    line.extend(outcome);
+                }
+            }
+            Err(error) => {
+                failed = true;
+                if !op.many() {
+                    discard_unexecuted(revisions, recorded, &HashMap::new(), |_| false).await;
+                }
+                line["status"] = json!("error");
+                line["error"] = error;
+            }
+        }
+        results.push(line);
+    }
+    Ok((!failed, json!({ "summary": summary, "results": results })))
+}
+
+/// Mixed insert/update/replace/delete operations, run as one `bulkWrite` on MongoDB 8.0+ and one
+/// by one on older servers. Responds 200 when every operation succeeded and 207 with
+/// per-operation errors otherwise.
+#[post("/bulk/{db_name}/{coll_name}")]
+pub async fn bulk_write(
+    path: web::Path<(String, String)>,
+    body: web::Json<BulkWriteRequest>,
+    data: web::Data<Client>,
+    revisions: web::Data<RevisionStore>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let req = body.into_inner();
+    if req.operations.is_empty() {
+        return Ok(HttpResponse::BadRequest().body("operations must not be empty"));
+    }
+    let ordered = req.ordered.unwrap_or(true);
+    let coll = data.database(&db_name).collection::<Document>(&coll_name);
+    let ns = coll.namespace();
+
+    let mut operations = Vec::new();
+    for (index, op) in req.operations.iter().enumerate() {
+        match parse_operation(&ns, op) {
+            Ok(parsed) => operations.push(parsed),
+            Err(e) => return Ok(HttpResponse::BadRequest().json(json!({ "index": index, "error": e }))),
+        }
+    }
+
+    let server_bulk_write = match supports_bulk_write(&data).await {
+        Ok(supported) => supported,
+        Err(e) => {
+            eprintln!("server version check error: {}", e);
+            return Ok(HttpResponse::InternalServerError().body(format!("failed to check server version: {}", e)));
+        }
+    };
+    let outcome = if server_bulk_write {
+        run_bulk_write(&data, &coll, &revisions, &operations, ordered).await
+    } else {
+        run_sequentially(&coll, &revisions, &operations, ordered).await
+    };
+    match outcome {
+        Ok((ok, mut body)) => {
+            body["ok"] = json!(ok);
+            body["ordered"] = json!(ordered);
+            body["mode"] = json!(if server_bulk_write { "bulkWrite" } else { "perOperation" });
+            if ok {
+                Ok(HttpResponse::Ok().json(body))
+            } else {
+                Ok(HttpResponse::MultiStatus().json(body))
+            }
+        }
+        Err(e) => {
+            eprintln!("bulk write error: {}", e);
+            Ok(HttpResponse::InternalServerError().body(e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_operation, update_to_pipeline};
+    use mongodb::{
+        bson::doc,
+        options::{Hint, UpdateModifications},
+        Namespace,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn translates_update_operators() {
+        let update = UpdateModifications::Document(doc! {
+            "$set": { "status": "$archived" },
+            "$inc": { "visits": 1 },
+            "$unset": { "tmp": "" },
+            "$rename": { "old": "new" },
+        });
+        assert_eq!(
+            update_to_pipeline(&update).unwrap(),
+            vec![
+                doc! { "$set": { "status": { "$literal": "$archived" } } },
+                doc! { "$set": { "visits": { "$add": [ { "$ifNull": [ "$visits", 0 ] }, 1 ] } } },
+                doc! { "$unset": [ "tmp" ] },
+                doc! { "$set": { "new": { "$ifNull": [ "$old", "$$REMOVE" ] } } },
+                doc! { "$unset": [ "old" ] },
+            ]
+        );
+    }
+
+    #[test]
+    fn translates_array_operators_without_reordering() {
+        let update = UpdateModifications::Document(doc! {
+            "$addToSet": { "tags": { "$each": [ "a", "a", "b" ] } },
+            "$pop": { "queue": 1 },
+        });
+        let tags = doc! { "$ifNull": [ "$tags", [] ] };
+        let queue = doc! { "$ifNull": [ "$queue", [] ] };
+        assert_eq!(
+            update_to_pipeline(&update).unwrap(),
+            vec![
+                doc! { "$set": { "tags": { "$concatArrays": [ tags.clone(), { "$filter": {
+                    "input": [ { "$literal": "a" }, { "$literal": "b" } ],
+                    "cond": { "$not": [ { "$in": [ "$$this", tags ] } ] },
+                } } ] } } },
+                doc! { "$set": { "queue": { "$cond": [
+                    { "$lte": [ { "$size": queue.clone() }, 1 ] },
+                    [],
+                    { "$slice": [ queue.clone(), 0, { "$subtract": [ { "$size": queue }, 1 ] } ] },
+                ] } } },
+            ]
+        );
+    }
+
+    #[test]
+    fn translates_push_modifiers_and_timestamps() {
+        let update = UpdateModifications::Document(doc! {
+            "$push": { "scores": { "$each": [ 7 ], "$sort": -1, "$slice": 3 }, "empty": { "$each": [ 1 ], "$slice": 0 } },
+            "$currentDate": { "seen": { "$type": "timestamp" }, "at": true },
+        });
+        let scores = doc! { "$ifNull": [ "$scores", [] ] };
+        let empty: Vec<i32> = Vec::new();
+        assert_eq!(
+            update_to_pipeline(&update).unwrap(),
+            vec![
+                doc! { "$set": {
+                    "scores": { "$slice": [ { "$sortArray": {
+                        "input": { "$concatArrays": [ scores, [ { "$literal": 7 } ] ] },
+                        "sortBy": -1,
+                    } }, 3i64 ] },
+                    "empty": empty,
+                } },
+                doc! { "$set": { "seen": "$$CLUSTER_TIME", "at": "$$NOW" } },
+            ]
+        );
+        let positioned = UpdateModifications::Document(doc! { "$push": { "q": { "$each": [ 1 ], "$position": -1 } } });
+        assert!(update_to_pipeline(&positioned).is_ok());
+    }
+
+    #[test]
+    fn rejects_what_aggregation_cannot_express() {
+        let positional = UpdateModifications::Document(doc! { "$set": { "items.$.qty": 1 } });
+        assert!(update_to_pipeline(&positional).is_err());
+        let conditional = UpdateModifications::Document(doc! { "$pull": { "scores": { "$lt": 5 } } });
+        assert!(update_to_pipeline(&conditional).is_err());
+        let indexed = UpdateModifications::Document(doc! { "$set": { "items.0.qty": 1 } });
+        assert!(update_to_pipeline(&indexed).is_err());
+        let unknown = UpdateModifications::Document(doc! { "$push": { "tags": { "$each": [], "$unknown": 1 } } });
+        assert!(update_to_pipeline(&unknown).is_err());
+        let pipeline = vec![doc! { "$set": { "a": 1 } }];
+        assert_eq!(update_to_pipeline(&UpdateModifications::Pipeline(pipeline.clone())).unwrap(), pipeline);
+    }
+
+    #[test]
+    fn parses_bulk_operations() {
+        let ns = Namespace::new("shop", "orders");
+        let op = parse_operation(&ns, &json!({ "updateOne": { "filter": { "sku": "a" }, "update": { "$inc": { "qty": 1 } }, "upsert": true } })).unwrap();
+        assert_eq!(op.name, "updateOne");
+        let touches = op.touches.unwrap();
+        assert_eq!((touches.filter, touches.limit), (doc! { "sku": "a" }, Some(1)));
+        let op = parse_operation(&ns, &json!({ "deleteMany": { "filter": {} } })).unwrap();
+        assert_eq!(op.touches.map(|t| (t.filter, t.limit)), Some((doc! {}, None)));
+        let op = parse_operation(
+            &ns,
+            &json!({ "replaceOne": {
+                "filter": { "sku": "a" },
+                "replacement": { "sku": "a", "qty": 0 },
+                "sort": { "qty": -1 },
+                "hint": "sku_1",
+                "collation": { "locale": "en", "strength": 2 },
+            } }),
+        )
+        .unwrap();
+        let touches = op.touches.unwrap();
+        assert_eq!(touches.sort, Some(doc! { "qty": -1i64 }));
+        let find = touches.find_options();
+        assert_eq!(find.sort, Some(doc! { "qty": -1i64 }));
+        assert_eq!(find.hint, Some(Hint::Name("sku_1".to_string())));
+        assert_eq!(find.collation.map(|c| c.locale), Some("en".to_string()));
+        assert_eq!(find.limit, Some(1));
+        assert!(parse_operation(&ns, &json!({ "deleteOne": { "filter": {}, "hint": 1 } })).is_err());
+        assert!(parse_operation(&ns, &json!({ "deleteOne": { "filter": {}, "collation": { "strength": 2 } } })).is_err());
+        assert!(parse_operation(&ns, &json!({ "insertOne": { "document": { "a": 1 } } })).unwrap().touches.is_none());
+        assert!(parse_operation(&ns, &json!({ "updateOne": { "filter": {}, "update": { "qty": 1 } } })).is_err());
+        assert!(parse_operation(&ns, &json!({ "upsertOne": {} })).is_err());
+        assert!(parse_operation(&ns, &json!({ "insertOne": {}, "deleteOne": {} })).is_err());
+    }
+}
@@ -1,9 +1,15 @@
-use actix_web::{get, post, put, delete, web, HttpResponse};
-use mongodb::{bson::{self, doc, oid::ObjectId, Document, Bson}, Client};
-use serde::{Deserialize, Serialize};
+use actix_web::{get, post, put, delete, http::header, web, HttpRequest, HttpResponse};
+use mongodb::{action::Action, bson::{self, doc, oid::ObjectId, Document, Bson}, options::{ReturnDocument, UpdateModifications}, results::{CollectionSpecification, CollectionType}, Client, ClientSession, Collection};
+use serde::Deserialize;
 use serde_json::Value as JsonValue;
 use futures::stream::TryStreamExt;
 use std::collections::HashMap;
+use tokio::sync::OwnedMutexGuard;
+
+use crate::concurrency::{bump_replacement, bump_update, cache_key, conflict, etag, guarded_filter, if_match, version_token, VersionCache};
+use crate::revisions::RevisionStore;
+use crate::transactions::{join, Transaction, TransactionQuery, TransactionStore};
+use crate::sampling::{analyze_paths, sample_documents, DEFAULT_SAMPLE_SIZE, DEFAULT_SAMPLE_VALUES};
 
 #[derive(Deserialize)]
 pub struct ListDocsQuery {
@@ -19,14 +25,40 @@ pub struct ListDocsQuery {
 pub async fn collections(path: web::Path<String>, data: web::Data<Client>) -> actix_web::Result<HttpResponse> {
 	let db_name = path.into_inner();
 	let db = data.database(&db_name);
-	// list collection names
-	match db.list_collection_names().await {
-		Ok(names) => Ok(HttpResponse::Ok().json(serde_json::json!({"database": db_name, "collections": names}))),
+	// list collection specs so views can be told apart from regular collections
+	let specs: Vec<CollectionSpecification> = match db.list_collections().await {
+		Ok(cursor) => match cursor.try_collect().await {
+			Ok(v) => v,
+			Err(e) => {
+				eprintln!("list collections cursor error: {}", e);
+				return Ok(HttpResponse::InternalServerError().body("failed to list collections"));
+			}
+		},
 		Err(e) => {
 			eprintln!("list collections error: {}", e);
-			Ok(HttpResponse::InternalServerError().body("failed to list collections"))
+			return Ok(HttpResponse::InternalServerError().body("failed to list collections"));
+		}
+	};
+
+	let mut names: Vec<String> = Vec::new();
+	let mut views: Vec<String> = Vec::new();
+	let mut types = serde_json::Map::new();
+	for spec in specs {
+		let kind = match spec.collection_type {
+			CollectionType::View => "view",
+			CollectionType::Timeseries => "timeseries",
+			_ => "collection",
+		};
+		if kind == "view" {
+			views.push(spec.name.clone());
 		}
+		types.insert(spec.name.clone(), JsonValue::String(kind.to_string()));
+		names.push(spec.name);
 	}
+	names.sort();
+	views.sort();
+
+	Ok(HttpResponse::Ok().json(serde_json::json!({"database": db_name, "collections": names, "views": views, "types": types})))
 }
 
 #[get("/collections/{db_name}/{coll_name}")]
@@ -116,7 +148,7 @@ pub async fn list_indexes(path: web::Path<(String, String)>, data: web::Data<Cli
 
 // get document by id
 #[get("/documents/{db_name}/{coll_name}/{id}")]
-pub async fn get_document_by_id(path: web::Path<(String, String, String)>, data: web::Data<Client>) -> actix_web::Result<HttpResponse> {
+pub async fn get_document_by_id(path: web::Path<(String, String, String)>, data: web::Data<Client>, versions: web::Data<VersionCache>) -> actix_web::Result<HttpResponse> {
 	let (db_name, coll_name, id_str) = path.into_inner();
 	let db = data.database(&db_name);
 	let coll = db.collection::<Document>(&coll_name);
@@ -126,7 +158,7 @@ pub async fn get_document_by_id(path: web::Path<(String, String, String)>, data:
 		doc!{"_id": oid}
 	} else {
 		// fall back to string id
-		doc!{"_id": id_str}
+		doc!{"_id": id_str.clone()}
 	};
 
 	match coll.find_one(filter).await {
@@ -134,7 +166,10 @@ pub async fn get_document_by_id(path: web::Path<(String, String, String)>, data:
 			let j = bson::to_bson(&doc).ok()
 				.and_then(|b| serde_json::to_value(&b).ok())
 				.unwrap_or(JsonValue::Null);
-			Ok(HttpResponse::Ok().json(j))
+			// version token for If-Match on later updates/deletes; remembered so a 409 can diff against it
+			let token = version_token(&doc);
+			versions.remember(cache_key(&db_name, &coll_name, &id_str, &token), j.clone()).await;
+			Ok(HttpResponse::Ok().insert_header((header::ETAG, format!("\"{}\"", token))).json(j))
 		}
 		Ok(None) => Ok(HttpResponse::NotFound().body("document not found")),
 		Err(e) => {
@@ -144,66 +179,28 @@ pub async fn get_document_by_id(path: web::Path<(String, String, String)>, data:
 	}
 }
 
-// collection stats via sampling
+// collection stats via sampling: one entry per dotted path, including embedded documents
+// (address.city) and array elements (items[].sku)
 #[get("/collections/{db_name}/{coll_name}/stats")]
 pub async fn collection_stats(path: web::Path<(String, String)>, query: web::Query<HashMap<String, String>>, data: web::Data<Client>) -> actix_web::Result<HttpResponse> {
 	let (db_name, coll_name) = path.into_inner();
 	let db = data.database(&db_name);
 	let coll = db.collection::<Document>(&coll_name);
 
-	let sample_size = query.get("sample").and_then(|s| s.parse::<i64>().ok()).unwrap_or(100);
+	let sample_size = query.get("sample").and_then(|s| s.parse::<i64>().ok()).unwrap_or(DEFAULT_SAMPLE_SIZE);
+	let sample_values = query.get("samples").and_then(|s| s.parse::<usize>().ok()).unwrap_or(DEFAULT_SAMPLE_VALUES);
 
-	// aggregate sample
-	let pipeline = vec![doc! { "$sample": { "size": sample_size } }];
-	let mut cursor = match coll.aggregate(pipeline).await {
-		Ok(c) => c,
+	let docs = match sample_documents(&coll, sample_size).await {
+		Ok(d) => d,
 		Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("aggregate failed: {}", e))),
 	};
 
-	// compute field stats: sample value and type set
-	let mut field_map: HashMap<String, (i64, Vec<String>, Option<JsonValue>)> = HashMap::new();
-
-	while let Some(doc) = cursor.try_next().await.map_err(|e| {
-		eprintln!("sample cursor error: {}", e);
-		actix_web::error::ErrorInternalServerError("cursor error")
-	})? {
-		let j = bson::to_bson(&doc).ok()
-			.and_then(|b| serde_json::to_value(&b).ok())
-			.unwrap_or(JsonValue::Null);
-		if let JsonValue::Object(map) = j {
-			for (k, v) in map.into_iter() {
-				let t = match &v {
-					JsonValue::Null => "null",
-					JsonValue::Bool(_) => "bool",
-					JsonValue::Number(_) => "number",
-					JsonValue::String(_) => "string",
-					JsonValue::Array(_) => "array",
-					JsonValue::Object(_) => "object",
-				};
-				let entry = field_map.entry(k.clone()).or_insert((0, Vec::new(), None));
-				entry.0 += 1;
-				if !entry.1.contains(&t.to_string()) {
-					entry.1.push(t.to_string());
-				}
-				if entry.2.is_none() {
-					entry.2 = Some(v.clone());
-				}
-			}
-		}
-	}
-
-	// produce JSON
-	let mut out = serde_json::Map::new();
-	for (k, (count, types, sample)) in field_map.into_iter() {
-		out.insert(k, serde_json::json!({"count": count, "types": types, "sample": sample}));
-	}
-
-	Ok(HttpResponse::Ok().json(serde_json::Value::Object(out)))
+	Ok(HttpResponse::Ok().json(analyze_paths(&docs, sample_values)))
 }
 
 // Create document
 #[post("/documents/{db_name}/{coll_name}")]
-pub async fn create_document(path: web::Path<(String,String)>, body: web::Json<JsonValue>, data: web::Data<Client>) -> actix_web::Result<HttpResponse> {
+pub async fn create_document(path: web::Path<(String,String)>, query: web::Query<TransactionQuery>, body: web::Json<JsonValue>, data: web::Data<Client>, transactions: web::Data<TransactionStore>) -> actix_web::Result<HttpResponse> {
 	let (db_name, coll_name) = path.into_inner();
 	let db = data.database(&db_name);
 	let coll = db.collection::<Document>(&coll_name);
@@ -215,8 +212,12 @@ pub async fn create_document(path: web::Path<(String,String)>, body: web::Json<J
 		}
 		Err(e) => return Ok(HttpResponse::BadRequest().body(format!("invalid body: {}", e))),
 	};
+	let mut txn = match join(&transactions, query.txn.as_deref()).await {
+		Ok(t) => t,
+		Err(resp) => return Ok(resp),
+	};
 
-	match coll.insert_one(doc).await {
+	match coll.insert_one(doc).optional(session(&mut txn), |a, s| a.session(s)).await {
 		Ok(r) => Ok(HttpResponse::Ok().json(serde_json::json!({"inserted_id": r.inserted_id}))),
 		Err(e) => {
 			eprintln!("insert error: {}", e);
@@ -225,44 +226,277 @@ pub async fn create_document(path: web::Path<(String,String)>, body: web::Json<J
 	}
 }
 
-// Update document by id (partial update: $set)
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateDocumentQuery {
+	// "set" (default) wraps plain fields in $set and passes operator documents/pipelines through,
+	// "replace" swaps the whole document via replace_one
+	mode: Option<String>,
+	upsert: Option<bool>,
+	// stringified JSON array, e.g. ?arrayFilters=[{"elem.qty":{"$gt":1}}]
+	array_filters: Option<String>,
+	// "after" or "before": respond with the document instead of counts
+	return_document: Option<String>,
+	// run inside an open transaction (see /transactions)
+	txn: Option<String>,
+}
+
+/// Body -> update: operator documents (`{"$inc": ...}`) and pipelines (`[...]`) go through as-is,
+/// plain field documents are wrapped in `$set`.
+fn update_modifications(body: Bson) -> Result<UpdateModifications, String> {
+	match body {
+		Bson::Array(stages) => {
+			let mut pipeline = Vec::new();
+			for stage in stages {
+				match stage {
+					Bson::Document(d) => pipeline.push(d),
+					_ => return Err("pipeline stages must be objects".to_string()),
+				}
+			}
+			Ok(UpdateModifications::Pipeline(pipeline))
+		}
+		Bson::Document(d) => {
+			let operators = d.keys().filter(|k| k.starts_with('$')).count();
+			if operators == 0 {
+				Ok(UpdateModifications::Document(doc!{"$set": d}))
+			} else if operators == d.len() {
+				Ok(UpdateModifications::Document(d))
+			} else {
+				Err("update mixes operators and plain fields".to_string())
+			}
+		}
+		other => Ok(UpdateModifications::Document(doc!{"$set": {"value": other}})),
+	}
+}
+
+fn document_response(doc: Option<Document>, after: bool) -> HttpResponse {
+	let mut resp = HttpResponse::Ok();
+	if let Some(d) = &doc && after {
+		resp.insert_header((header::ETAG, etag(d)));
+	}
+	resp.json(serde_json::json!({"matched": doc.is_some(), "document": doc}))
+}
+
+// The guarded write matched nothing: someone changed (or deleted) the document after the check.
+async fn lost_race(coll: &Collection<Document>, id_filter: Document, versions: &VersionCache, key: &str, txn: &mut Option<OwnedMutexGuard<Transaction>>) -> HttpResponse {
+	let current = coll.find_one(id_filter).optional(session(txn), |a, s| a.session(s)).await.ok().flatten();
+	conflict(current.as_ref(), versions.lookup(key).await)
+}
+
+fn session(txn: &mut Option<OwnedMutexGuard<Transaction>>) -> Option<&mut ClientSession> {
+	txn.as_mut().map(|t| t.session())
+}
+
+// Inside a transaction the revision waits for the commit, so an abort leaves no history behind.
+async fn record_revision(revisions: &RevisionStore, txn: &mut Option<OwnedMutexGuard<Transaction>>, db_name: &str, coll_name: &str, operation: &str, before: &Document) {
+	match txn {
+		Some(t) => t.defer_revision(db_name, coll_name, operation, before),
+		None => revisions.record(db_name, coll_name, operation, before).await,
+	}
+}
+
+// Update document by id: $set, raw operators, pipelines or full replacement.
+// With If-Match the write only applies while the document still has that version token.
 #[put("/documents/{db_name}/{coll_name}/{id}")]
-pub async fn update_document(path: web::Path<(String,String,String)>, body: web::Json<JsonValue>, data: web::Data<Client>) -> actix_web::Result<HttpResponse> {
+#[allow(clippy::too_many_arguments)]
+pub async fn update_document(req: HttpRequest, path: web::Path<(String,String,String)>, query: web::Query<UpdateDocumentQuery>, body: web::Json<JsonValue>, data: web::Data<Client>, versions: web::Data<VersionCache>, revisions: web::Data<RevisionStore>, transactions: web::Data<TransactionStore>) -> actix_web::Result<HttpResponse> {
 	let (db_name, coll_name, id_str) = path.into_inner();
 	let db = data.database(&db_name);
 	let coll = db.collection::<Document>(&coll_name);
 
-	let filter = if let Ok(oid) = ObjectId::parse_str(&id_str) { doc!{"_id": oid} } else { doc!{"_id": id_str} };
+	let id_filter = if let Ok(oid) = ObjectId::parse_str(&id_str) { doc!{"_id": oid} } else { doc!{"_id": id_str.clone()} };
 
-	let update_doc = match bson::to_bson(&body.into_inner()) {
-		Ok(Bson::Document(d)) => doc!{"$set": d},
-		Ok(other) => doc!{"$set": {"value": other}},
+	let body = match bson::to_bson(&body.into_inner()) {
+		Ok(b) => b,
 		Err(e) => return Ok(HttpResponse::BadRequest().body(format!("invalid body: {}", e))),
 	};
+	let return_document = match query.return_document.as_deref() {
+		None => None,
+		Some("after") => Some(ReturnDocument::After),
+		Some("before") => Some(ReturnDocument::Before),
+		Some(other) => return Ok(HttpResponse::BadRequest().body(format!("unknown returnDocument: {}", other))),
+	};
+	let replace = match query.mode.as_deref() {
+		None | Some("set") => false,
+		Some("replace") => true,
+		Some(other) => return Ok(HttpResponse::BadRequest().body(format!("unknown mode: {}", other))),
+	};
+
+	let mut txn = match join(&transactions, query.txn.as_deref()).await {
+		Ok(t) => t,
+		Err(resp) => return Ok(resp),
+	};
+	let expected = if_match(&req);
+	let cache = cache_key(&db_name, &coll_name, &id_str, expected.as_deref().unwrap_or(""));
+	// before-image for the revision history, the If-Match check and replacement versioning
+	let current = match coll.find_one(id_filter.clone()).optional(session(&mut txn), |a, s| a.session(s)).await {
+		Ok(c) => c,
+		Err(e) => {
+			eprintln!("find_one error: {}", e);
+			return Ok(HttpResponse::InternalServerError().body("find_one failed"));
+		}
+	};
+	let filter = match (&expected, &current) {
+		(None, _) => id_filter.clone(),
+		(Some(_), None) => return Ok(HttpResponse::NotFound().body("document not found")),
+		(Some(token), Some(c)) if version_token(c) != *token => return Ok(conflict(Some(c), versions.lookup(&cache).await)),
+		(Some(_), Some(c)) => guarded_filter(id_filter.clone(), c),
+	};
+	// a guarded write that misses must report a conflict, not insert a second document
+	let upsert = query.upsert.unwrap_or(false) && expected.is_none();
+
+	if replace {
+		let mut replacement = match body {
+			Bson::Document(d) if !d.keys().any(|k| k.starts_with('$')) => d,
+			Bson::Document(_) => return Ok(HttpResponse::BadRequest().body("replacement document cannot contain update operators")),
+			_ => return Ok(HttpResponse::BadRequest().body("replacement must be an object")),
+		};
+		if query.array_filters.is_some() {
+			return Ok(HttpResponse::BadRequest().body("arrayFilters cannot be used with replace"));
+		}
+		bump_replacement(&mut replacement, current.as_ref());
+		if let Some(rd) = return_document {
+			let after = matches!(rd, ReturnDocument::After);
+			return match coll.find_one_and_replace(filter, replacement).upsert(upsert).optional(session(&mut txn), |a, s| a.session(s)).return_document(rd).await {
+				Ok(None) if expected.is_some() => Ok(lost_race(&coll, id_filter, &versions, &cache, &mut txn).await),
+				Ok(doc) => {
+					if let (Some(_), Some(before)) = (&doc, &current) {
+						record_revision(&revisions, &mut txn, &db_name, &coll_name, "replace", before).await;
+					}
+					Ok(document_response(doc, after))
+				}
+				Err(e) => {
+					eprintln!("replace error: {}", e);
+					Ok(HttpResponse::InternalServerError().body(format!("replace failed: {}", e)))
+				}
+			};
+		}
+		return match coll.replace_one(filter, replacement).upsert(upsert).optional(session(&mut txn), |a, s| a.session(s)).await {
+			Ok(r) if r.matched_count == 0 && expected.is_some() => Ok(lost_race(&coll, id_filter, &versions, &cache, &mut txn).await),
+			Ok(r) => {
+				if let Some(before) = current.as_ref().filter(|_| r.modified_count > 0) {
+					record_revision(&revisions, &mut txn, &db_name, &coll_name, "replace", before).await;
+				}
+				Ok(HttpResponse::Ok().json(serde_json::json!({"matched": r.matched_count, "modified": r.modified_count, "upserted_id": r.upserted_id})))
+			}
+			Err(e) => {
+				eprintln!("replace error: {}", e);
+				Ok(HttpResponse::InternalServerError().body(format!("replace failed: {}", e)))
+			}
+		};
+	}
+
+	let update = match update_modifications(body) {
+		Ok(u) => bump_update(u),
+		Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+	};
+	let array_filters: Option<Vec<Document>> = match &query.array_filters {
+		None => None,
+		Some(raw) => match serde_json::from_str::<JsonValue>(raw).map(|v| bson::to_bson(&v)) {
+			Ok(Ok(Bson::Array(items))) => {
+				let mut filters = Vec::new();
+				for item in items {
+					match item {
+						Bson::Document(d) => filters.push(d),
+						_ => return Ok(HttpResponse::BadRequest().body("arrayFilters entries must be objects")),
+					}
+				}
+				Some(filters)
+			}
+			_ => return Ok(HttpResponse::BadRequest().body("arrayFilters must be a JSON array")),
+		},
+	};
+
+	if let Some(rd) = return_document {
+		let after = matches!(rd, ReturnDocument::After);
+		return match coll.find_one_and_update(filter, update).upsert(upsert).optional(array_filters, |a, f| a.array_filters(f)).optional(session(&mut txn), |a, s| a.session(s)).return_document(rd).await {
+			Ok(None) if expected.is_some() => Ok(lost_race(&coll, id_filter, &versions, &cache, &mut txn).await),
+			Ok(doc) => {
+				if let (Some(_), Some(before)) = (&doc, &current) {
+					record_revision(&revisions, &mut txn, &db_name, &coll_name, "update", before).await;
+				}
+				Ok(document_response(doc, after))
+			}
+			Err(e) => {
+				eprintln!("update error: {}", e);
+				Ok(HttpResponse::InternalServerError().body(format!("update failed: {}", e)))
+			}
+		};
+	}
 
-	match coll.update_one(filter, update_doc).await {
-		Ok(r) => Ok(HttpResponse::Ok().json(serde_json::json!({"matched": r.matched_count, "modified": r.modified_count}))),
+	match coll.update_one(filter, update).upsert(upsert).optional(array_filters, |a, f| a.array_filters(f)).optional(session(&mut txn), |a, s| a.session(s)).await {
+		Ok(r) if r.matched_count == 0 && expected.is_some() => Ok(lost_race(&coll, id_filter, &versions, &cache, &mut txn).await),
+		Ok(r) => {
+			if let Some(before) = current.as_ref().filter(|_| r.modified_count > 0) {
+				record_revision(&revisions, &mut txn, &db_name, &coll_name, "update", before).await;
+			}
+			Ok(HttpResponse::Ok().json(serde_json::json!({"matched": r.matched_count, "modified": r.modified_count, "upserted_id": r.upserted_id})))
+		}
 		Err(e) => {
 			eprintln!("update error: {}", e);
-			Ok(HttpResponse::InternalServerError().body("update failed"))
+			Ok(HttpResponse::InternalServerError().body(format!("update failed: {}", e)))
 		}
 	}
 }
 
-// Delete document
+// Delete document; honours If-Match like update_document
 #[delete("/documents/{db_name}/{coll_name}/{id}")]
-pub async fn delete_document(path: web::Path<(String,String,String)>, data: web::Data<Client>) -> actix_web::Result<HttpResponse> {
+pub async fn delete_document(req: HttpRequest, path: web::Path<(String,String,String)>, query: web::Query<TransactionQuery>, data: web::Data<Client>, versions: web::Data<VersionCache>, revisions: web::Data<RevisionStore>, transactions: web::Data<TransactionStore>) -> actix_web::Result<HttpResponse> {
 	let (db_name, coll_name, id_str) = path.into_inner();
 	let db = data.database(&db_name);
 	let coll = db.collection::<Document>(&coll_name);
 
-	let filter = if let Ok(oid) = ObjectId::parse_str(&id_str) { doc!{"_id": oid} } else { doc!{"_id": id_str} };
+	let id_filter = if let Ok(oid) = ObjectId::parse_str(&id_str) { doc!{"_id": oid} } else { doc!{"_id": id_str.clone()} };
+
+	let mut txn = match join(&transactions, query.txn.as_deref()).await {
+		Ok(t) => t,
+		Err(resp) => return Ok(resp),
+	};
+	let expected = if_match(&req);
+	let cache = cache_key(&db_name, &coll_name, &id_str, expected.as_deref().unwrap_or(""));
+	// before-image kept as a revision so the document can be undeleted
+	let current = match coll.find_one(id_filter.clone()).optional(session(&mut txn), |a, s| a.session(s)).await {
+		Ok(c) => c,
+		Err(e) => {
+			eprintln!("find_one error: {}", e);
+			return Ok(HttpResponse::InternalServerError().body("find_one failed"));
+		}
+	};
+	let filter = match (&expected, &current) {
+		(None, _) => id_filter.clone(),
+		(Some(_), None) => return Ok(HttpResponse::NotFound().body("document not found")),
+		(Some(token), Some(c)) if version_token(c) != *token => return Ok(conflict(Some(c), versions.lookup(&cache).await)),
+		(Some(_), Some(c)) => guarded_filter(id_filter.clone(), c),
+	};
 
-	match coll.delete_one(filter).await {
-		Ok(r) => Ok(HttpResponse::Ok().json(serde_json::json!({"deleted": r.deleted_count}))),
+	match coll.delete_one(filter).optional(session(&mut txn), |a, s| a.session(s)).await {
+		Ok(r) if r.deleted_count == 0 && expected.is_some() => Ok(lost_race(&coll, id_filter, &versions, &cache, &mut txn).await),
+		Ok(r) => {
+			if let Some(before) = current.as_ref().filter(|_| r.deleted_count > 0) {
+				record_revision(&revisions, &mut txn, &db_name, &coll_name, "delete", before).await;
+			}
+			Ok(HttpResponse::Ok().json(serde_json::json!({"deleted": r.deleted_count})))
+		}
 		Err(e) => {
 			eprintln!("delete error: {}", e);
 			Ok(HttpResponse::InternalServerError().body("delete failed"))
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::update_modifications;
+	use mongodb::{bson::{doc, Bson}, options::UpdateModifications};
+
+	#[test]
+	fn wraps_only_plain_fields_in_set() {
+		let plain = update_modifications(Bson::Document(doc!{"name": "x"})).unwrap();
+		assert!(matches!(plain, UpdateModifications::Document(d) if d == doc!{"$set": {"name": "x"}}));
+		let ops = update_modifications(Bson::Document(doc!{"$inc": {"n": 1}, "$unset": {"tmp": ""}})).unwrap();
+		assert!(matches!(ops, UpdateModifications::Document(d) if d == doc!{"$inc": {"n": 1}, "$unset": {"tmp": ""}}));
+		let pipeline = update_modifications(Bson::Array(vec![Bson::Document(doc!{"$set": {"a": 1}})])).unwrap();
+		assert!(matches!(pipeline, UpdateModifications::Pipeline(p) if p.len() == 1));
+		assert!(update_modifications(Bson::Document(doc!{"$set": {"a": 1}, "b": 2})).is_err());
+	}
+}
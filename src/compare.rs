@@ -0,0 +1,288 @@
+//! Compares two collections by `_id`, on the same or different clusters.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use futures::stream::{BoxStream, StreamExt, TryStreamExt};
+use mongodb::{
+    bson::{doc, Bson, Document, RawDocumentBuf},
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use sha2::{Digest, Sha256};
+
+use crate::copy::Namespace;
+use crate::diff::diff_json;
+use crate::export::to_json;
+use crate::ops::JobContext;
+
+const DEFAULT_BATCH_SIZE: usize = 1000;
+// _ids listed per category in the job result; the download has all of them.
+const SAMPLE_IDS: usize = 20;
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CompareRequest {
+    pub a: Namespace,
+    pub b: Namespace,
+    // applied to both sides
+    pub filter: Option<JsonValue>,
+    pub batch_size: Option<usize>,
+}
+
+impl CompareRequest {
+    pub fn batch_size(&self) -> usize {
+        self.batch_size.filter(|b| *b > 0).unwrap_or(DEFAULT_BATCH_SIZE)
+    }
+}
+
+type HashStream = BoxStream<'static, mongodb::error::Result<(Bson, [u8; 32])>>;
+
+/// Map key for an `_id`. Numbers are folded together because `$in` matches 1, 1L and 1.0 alike.
+fn id_key(id: &Bson) -> String {
+    let id = match id {
+        Bson::Int32(n) => Bson::Int64(*n as i64),
+        Bson::Double(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => Bson::Int64(*f as i64),
+        other => other.clone(),
+    };
+    id.into_canonical_extjson().to_string()
+}
+
+/// SHA-256 of the raw BSON, so any change in a value or its type makes the hashes differ.
+/// (`$toHashedIndexKey` is not usable here: it hashes 2.3 and 2 or 1 and 1.0 alike.)
+fn raw_hash(doc: &RawDocumentBuf) -> [u8; 32] {
+    Sha256::digest(doc.as_bytes()).into()
+}
+
+/// `(_id, hash)` for every document matching `filter`, in no particular order.
+async fn hashes(coll: &Collection<Document>, filter: Document) -> mongodb::error::Result<HashStream> {
+    let cursor = coll.clone_with_type::<RawDocumentBuf>().find(filter).await?;
+    Ok(cursor
+        .map_ok(|raw| {
+            let id = raw.get("_id").ok().flatten().and_then(|v| v.to_raw_bson().try_into().ok());
+            (id.unwrap_or(Bson::Null), raw_hash(&raw))
+        })
+        .boxed())
+}
+
+fn with_ids(filter: &Document, ids: &[Bson]) -> Document {
+    let by_id = doc! { "_id": { "$in": ids } };
+    if filter.is_empty() {
+        by_id
+    } else {
+        doc! { "$and": [filter.clone(), by_id] }
+    }
+}
+
+async fn hashes_for(
+    coll: &Collection<Document>,
+    filter: &Document,
+    ids: &[Bson],
+) -> mongodb::error::Result<HashMap<String, [u8; 32]>> {
+    hashes(coll, with_ids(filter, ids))
+        .await?
+        .map_ok(|(id, h)| (id_key(&id), h))
+        .try_collect()
+        .await
+}
+
+async fn documents_for(coll: &Collection<Document>, ids: &[Bson]) -> mongodb::error::Result<HashMap<String, Document>> {
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    coll.find(doc! { "_id": { "$in": ids } })
+        .await?
+        .map_ok(|d| (d.get("_id").map(id_key).unwrap_or_default(), d))
+        .try_collect()
+        .await
+}
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CompareSummary {
+    pub only_in_a: u64,
+    pub only_in_b: u64,
+    pub different: u64,
+    pub identical: u64,
+    // same fields and values, different field order: unequal to MongoDB, but no field diff
+    pub reordered: u64,
+    pub samples: HashMap<&'static str, Vec<JsonValue>>,
+}
+
+impl CompareSummary {
+    fn sample(&mut self, status: &'static str, id: &Bson) {
+        let ids = self.samples.entry(status).or_default();
+        if ids.len() < SAMPLE_IDS {
+            ids.push(id.clone().into_relaxed_extjson());
+        }
+    }
+}
+
+fn canonical(doc: &Document) -> JsonValue {
+    Bson::Document(doc.clone()).into_canonical_extjson()
+}
+
+/// One line of the NDJSON report.
+fn report_line(id: &Bson, status: &str, detail: JsonValue) -> String {
+    let mut line = json!({ "_id": id.clone().into_relaxed_extjson(), "status": status });
+    if let (JsonValue::Object(line), JsonValue::Object(detail)) = (&mut line, detail) {
+        line.extend(detail);
+    }
+    format!("{}\n", line)
+}
+
+struct Comparison<'a> {
+    a: &'a Collection<Document>,
+    b: &'a Collection<Document>,
+    filter: Document,
+    out: BufWriter<File>,
+    summary: CompareSummary,
+}
+
+impl Comparison<'_> {
+    fn write(&mut self, line: String) -> Result<(), String> {
+        self.out.write_all(line.as_bytes()).map_err(|e| e.to_string())
+    }
+
+    async fn only_in(&mut self, status: &'static str, source: &Collection<Document>, ids: Vec<Bson>) -> Result<(), String> {
+        let docs = documents_for(source, &ids).await.map_err(|e| e.to_string())?;
+        for id in ids {
+            let document = docs.get(&id_key(&id)).map(to_json).unwrap_or(JsonValue::Null);
+            self.summary.sample(status, &id);
+            self.write(report_line(&id, status, json!({ "document": document })))?;
+        }
+        Ok(())
+    }
+
+    /// Checks one batch of A's hashes against B.
+    async fn compare_batch(&mut self, batch: Vec<(Bson, [u8; 32])>) -> Result<(), String> {
+        let ids: Vec<Bson> = batch.iter().map(|(id, _)| id.clone()).collect();
+        let other = hashes_for(self.b, &self.filter, &ids).await.map_err(|e| e.to_string())?;
+        let mut missing = Vec::new();
+        let mut changed = Vec::new();
+        for (id, hash) in batch {
+            match other.get(&id_key(&id)) {
+                None => missing.push(id),
+                Some(h) if *h == hash => self.summary.identical += 1,
+                Some(_) => changed.push(id),
+            }
+        }
+        self.summary.only_in_a += missing.len() as u64;
+        self.only_in("onlyInA", self.a, missing).await?;
+
+        if changed.is_empty() {
+            return Ok(());
+        }
+        let before = documents_for(self.a, &changed).await.map_err(|e| e.to_string())?;
+        let after = documents_for(self.b, &changed).await.map_err(|e| e.to_string())?;
+        for id in changed {
+            let key = id_key(&id);
+            let (Some(a), Some(b)) = (before.get(&key), after.get(&key)) else {
+                // changed between the hash and the fetch; the next run will see it
+                continue;
+            };
+            // canonical extjson so an int -> long or int -> double change shows up as a change
+            let changes = diff_json(&canonical(a), &canonical(b));
+            if changes.is_empty() {
+                self.summary.reordered += 1;
+                continue;
+            }
+            self.summary.different += 1;
+            self.summary.sample("different", &id);
+            self.write(report_line(&id, "different", json!({ "changes": changes })))?;
+        }
+        Ok(())
+    }
+
+    /// Finds B's documents that A lacks.
+    async fn missing_batch(&mut self, ids: Vec<Bson>) -> Result<(), String> {
+        let present: HashSet<String> = self
+            .a
+            .find(with_ids(&self.filter, &ids))
+            .projection(doc! { "_id": 1 })
+            .await
+            .map_err(|e| e.to_string())?
+            .map_ok(|d| d.get("_id").map(id_key).unwrap_or_default())
+            .try_collect()
+            .await
+            .map_err(|e| e.to_string())?;
+        let missing: Vec<Bson> = ids.into_iter().filter(|id| !present.contains(&id_key(id))).collect();
+        self.summary.only_in_b += missing.len() as u64;
+        self.only_in("onlyInB", self.b, missing).await
+    }
+}
+
+/// Streams A's hashes in batches and looks each batch up in B, then streams B's `_id`s to find
+/// what A lacks. Every difference goes to `out` as NDJSON; the summary is returned.
+pub async fn compare_collections(
+    a: &Collection<Document>,
+    b: &Collection<Document>,
+    filter: Document,
+    batch_size: usize,
+    ctx: &JobContext,
+    out: &Path,
+) -> Result<CompareSummary, String> {
+    let total_a = a.count_documents(filter.clone()).await.map_err(|e| e.to_string())?;
+    let total_b = b.count_documents(filter.clone()).await.map_err(|e| e.to_string())?;
+    let total = (total_a + total_b).max(1) as f64;
+
+    let mut comparison = Comparison {
+        a,
+        b,
+        filter: filter.clone(),
+        out: BufWriter::new(File::create(out).map_err(|e| e.to_string())?),
+        summary: CompareSummary::default(),
+    };
+    let mut seen = 0u64;
+
+    let mut stream = hashes(a, filter.clone()).await.map_err(|e| e.to_string())?.chunks(batch_size);
+    while let Some(chunk) = stream.next().await {
+        let batch: Vec<(Bson, [u8; 32])> = chunk.into_iter().collect::<Result<_, _>>().map_err(|e| e.to_string())?;
+        seen += batch.len() as u64;
+        comparison.compare_batch(batch).await?;
+        ctx.progress(seen as f64 * 100.0 / total).await;
+    }
+    ctx.log(format!("compared {} documents from A", total_a)).await;
+
+    let mut stream = b
+        .find(filter)
+        .projection(doc! { "_id": 1 })
+        .await
+        .map_err(|e| e.to_string())?
+        .map_ok(|d| d.get("_id").cloned().unwrap_or(Bson::Null))
+        .chunks(batch_size);
+    while let Some(chunk) = stream.next().await {
+        let batch: Vec<Bson> = chunk.into_iter().collect::<Result<_, _>>().map_err(|e| e.to_string())?;
+        seen += batch.len() as u64;
+        comparison.missing_batch(batch).await?;
+        ctx.progress(seen as f64 * 100.0 / total).await;
+    }
+    comparison.out.flush().map_err(|e| e.to_string())?;
+    Ok(comparison.summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_report_lines_and_id_filters() {
+        let line = report_line(&Bson::Int32(7), "different", json!({ "changes": [] }));
+        assert!(line.ends_with('\n'));
+        let parsed: JsonValue = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed, json!({ "_id": 7, "status": "different", "changes": [] }));
+        assert_eq!(with_ids(&doc! {}, &[Bson::Int32(1)]), doc! { "_id": { "$in": [1] } });
+        assert_eq!(
+            with_ids(&doc! { "x": 1 }, &[Bson::Int32(1)]),
+            doc! { "$and": [{ "x": 1 }, { "_id": { "$in": [1] } }] }
+        );
+        // numbers match across types like they do in $in, strings do not
+        assert_eq!(id_key(&Bson::Int32(1)), id_key(&Bson::Int64(1)));
+        assert_ne!(id_key(&Bson::Int32(1)), id_key(&Bson::String("1".into())));
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    env,
+};
+
+use actix_web::{http::header, HttpRequest, HttpResponse};
+use mongodb::{
+    bson::{self, doc, Bson, Document},
+    options::UpdateModifications,
+};
+use serde_json::{json, Value as JsonValue};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use crate::diff::diff_json;
+
+// Documents remembered per version token so a 409 can show what changed since the client read it.
+const VERSION_CACHE_SIZE: usize = 1000;
+// Upper bound on the serialized size of all cached documents together.
+const VERSION_CACHE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Field holding an application-maintained version number (`GLOBE_VERSION_FIELD`, e.g. `__v`).
+/// When unset, or missing from a document, the version token is a hash of the whole document.
+pub fn version_field() -> Option<String> {
+    env::var("GLOBE_VERSION_FIELD")
+        .ok()
+        .map(|f| f.trim().to_string())
+        .filter(|f| !f.is_empty())
+}
+
+pub fn version_token(doc: &Document) -> String {
+    if let Some(field) = version_field()
+        && let Some(value) = doc.get(&field)
+    {
+        // quotes would break the ETag header, so string versions are used bare
+        return format!("v{}", serde_json::to_value(value).unwrap_or(JsonValue::Null)).replace('"', "");
+    }
+    let bytes = bson::to_vec(doc).unwrap_or_default();
+    format!("{:x}", Sha256::digest(&bytes))
+}
+
+pub fn etag(doc: &Document) -> String {
+    format!("\"{}\"", version_token(doc))
+}
+
+/// The `If-Match` token, without quotes or a weak `W/` prefix.
+pub fn if_match(req: &HttpRequest) -> Option<String> {
+    let raw = req.headers().get(header::IF_MATCH)?.to_str().ok()?.trim();
+    let raw = raw.strip_prefix("W/").unwrap_or(raw);
+    Some(raw.trim_matches('"').to_string())
+}
+
+/// Narrows an `_id` filter so the write only applies while the document is still exactly
+/// `current`: by version field when one is configured, otherwise by whole-document equality.
+pub fn guarded_filter(mut filter: Document, current: &Document) -> Document {
+    if let Some(field) = version_field()
+        && let Some(value) = current.get(&field)
+    {
+        filter.insert(field, value.clone());
+        return filter;
+    }
+    filter.insert("$expr", doc! { "$eq": [ "$$ROOT", { "$literal": current.clone() } ] });
+    filter
+}
+
+fn next_version(current: Option<&Bson>) -> Bson {
+    match current {
+        Some(Bson::Int32(v)) => Bson::Int32(v + 1),
+        Some(Bson::Int64(v)) => Bson::Int64(v + 1),
+        Some(Bson::Double(v)) => Bson::Double(v + 1.0),
+        _ => Bson::Int32(1),
+    }
+}
+
+/// Adds a version increment to an update unless it already writes the version field itself.
+pub fn bump_update(update: UpdateModifications) -> UpdateModifications {
+    let Some(field) = version_field() else {
+        return update;
+    };
+    match update {
+        UpdateModifications::Document(mut d) => {
+            let touched = d.values().any(|spec| matches!(spec, Bson::Document(f) if f.contains_key(&field)));
+            if !touched {
+                match d.get_mut("$inc") {
+                    Some(Bson::Document(inc)) => {
+                        inc.insert(field, 1);
+                    }
+                    _ => {
+                        d.insert("$inc", doc! { field: 1 });
+                    }
+                }
+            }
+            UpdateModifications::Document(d)
+        }
+        UpdateModifications::Pipeline(mut stages) => {
+            let current = format!("${}", field);
+            stages.push(doc! { "$set": { field: { "$add": [ { "$ifNull": [ current, 0 ] }, 1 ] } } });
+            UpdateModifications::Pipeline(stages)
+        }
+        other => other,
+    }
+}
+
+/// Sets the version field of a replacement to one past the stored document's.
+pub fn bump_replacement(replacement: &mut Document, current: Option<&Document>) {
+    if let Some(field) = version_field() {
+        let next = next_version(current.and_then(|c| c.get(&field)));
+        replacement.insert(field, next);
+    }
+}
+
+/// For replacements written without reading the stored document first (bulk writes, imports):
+/// an update pipeline that replaces the document and sets the version field one past the
+/// stored one. `None` when no version field is configured, so a plain replace can be used.
+pub fn bumped_replacement(replacement: &Document) -> Option<Vec<Document>> {
+    let field = version_field()?;
+    let mut literal = replacement.clone();
+    literal.remove(&field);
+    let current = format!("${}", field);
+    Some(vec![doc! { "$replaceWith": { "$mergeObjects": [
+        { "$literal": literal },
+        { field: { "$add": [ { "$ifNull": [ current, 0 ] }, 1 ] } },
+    ] } }])
+}
+
+/// Recently served documents keyed by namespace, id and version token, oldest evicted first once
+/// either the entry count or the total serialized size goes over its cap.
+pub struct VersionCache {
+    entries: Mutex<CachedVersions>,
+}
+
+#[derive(Default)]
+struct CachedVersions {
+    docs: HashMap<String, (JsonValue, usize)>,
+    order: VecDeque<String>,
+    bytes: usize,
+}
+
+impl VersionCache {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(CachedVersions::default()),
+        }
+    }
+
+    pub async fn remember(&self, key: String, doc: JsonValue) {
+        let size = doc.to_string().len();
+        if size > VERSION_CACHE_BYTES {
+            return;
+        }
+        let mut entries = self.entries.lock().await;
+        if entries.docs.contains_key(&key) {
+            return;
+        }
+        while entries.order.len() >= VERSION_CACHE_SIZE || entries.bytes + size > VERSION_CACHE_BYTES {
+            let Some(oldest) = entries.order.pop_front() else {
+                break;
+            };
+            if let Some((_, freed)) = entries.docs.remove(&oldest) {
+                entries.bytes -= freed;
+            }
+        }
+        entries.bytes += size;
+        entries.order.push_back(key.clone());
+        entries.docs.insert(key, (doc, size));
+    }
+
+    pub async fn lookup(&self, key: &str) -> Option<JsonValue> {
+        self.entries.lock().await.docs.get(key).map(|(d, _)| d.clone())
+    }
+}
+
+pub fn cache_key(db: &str, coll: &str, id: &str, token: &str) -> String {
+    format!("{}/{}/{}/{}", db, coll, id, token)
+}
+
+/// 409 body: the current document and token, plus a field diff from the version the client
+/// read (when it is still cached) to the current one.
+pub fn conflict(current: Option<&Document>, base: Option<JsonValue>) -> HttpResponse {
+    let current_json = current
+        .map(|d| serde_json::to_value(d).unwrap_or(JsonValue::Null))
+        .unwrap_or(JsonValue::Null);
+    let changes = base.map(|b| diff_json(&b, &current_json));
+    HttpResponse::Conflict().json(json!({
+        "error": "document changed since it was read",
+        "currentVersion": current.map(version_token),
+        "current": current_json,
+        "changes": changes,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{guarded_filter, version_token};
+    use mongodb::bson::doc;
+
+    // Assumes GLOBE_VERSION_FIELD is unset, i.e. hash-based tokens.
+    #[test]
+    fn hashes_whole_documents() {
+        let a = doc! { "_id": 1, "name": "x" };
+        assert_eq!(version_token(&a), version_token(&a.clone()));
+        assert_ne!(version_token(&a), version_token(&doc! { "_id": 1, "name": "y" }));
+        assert_eq!(version_token(&a).len(), 64);
+        assert_eq!(
+            guarded_filter(doc! { "_id": 1 }, &a),
+            doc! { "_id": 1, "$expr": { "$eq": [ "$$ROOT", { "$literal": { "_id": 1, "name": "x" } } ] } }
+        );
+    }
+}
@@ -0,0 +1,691 @@
+//! Copy and sync documents from one namespace to another, optionally across connection profiles.
+
+use actix_web::{get, post, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    action::Action,
+    bson::{self, doc, Bson, Document},
+    change_stream::event::{OperationType, ResumeToken},
+    error::ErrorKind,
+    Client, Collection,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+
+use crate::dump::first_batch;
+use crate::export::json_document;
+use crate::ops::{tasks::accepted, JobContext, JobOutcome, JobStore};
+use crate::masking::{masker_for, Masker};
+use crate::profiles::{ProfileStore, DEFAULT_PROFILE};
+use crate::schemas::SchemaStore;
+use crate::state::AppInfo;
+use crate::store::JsonStore;
+
+const COPIES_FILE: &str = "copies.json";
+const DEFAULT_BATCH_SIZE: usize = 1000;
+// The resume token is saved after this many applied changes, and whenever the stream goes idle.
+const CHANGE_CHECKPOINT_EVERY: u64 = 100;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Namespace {
+    pub profile: Option<String>,
+    pub database: String,
+    pub collection: String,
+}
+
+impl Namespace {
+    pub fn label(&self) -> String {
+        format!("{}:{}.{}", self.profile.as_deref().unwrap_or(DEFAULT_PROFILE), self.database, self.collection)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CopyRequest {
+    pub source: Namespace,
+    pub target: Namespace,
+    pub filter: Option<JsonValue>,
+    pub projection: Option<JsonValue>,
+    pub batch_size: Option<usize>,
+    // defaults to true
+    pub copy_indexes: Option<bool>,
+    pub drop_target: Option<bool>,
+    // keep following the source's change stream after the initial copy
+    pub incremental: Option<bool>,
+    // apply the source collection's saved masking rules
+    pub mask: Option<bool>,
+}
+
+/// A copy and its checkpoint. `lastId` is the highest source `_id` written to the target, in
+/// canonical extended JSON, so a resumed run continues with `_id > lastId`. `inFlight` is the
+/// last `_id` of the batch being written, saved before the write, so a resumed run knows which
+/// duplicate keys an interrupted write of its own left behind.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CopyTask {
+    pub id: String,
+    #[serde(flatten)]
+    pub request: CopyRequest,
+    pub last_id: Option<JsonValue>,
+    #[serde(default)]
+    pub in_flight: Option<JsonValue>,
+    pub copied: u64,
+    // documents an interrupted run had already written, left as they were
+    pub skipped: u64,
+    pub initial_complete: bool,
+    pub resume_token: Option<JsonValue>,
+    pub changes_applied: u64,
+    pub job_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct CopiesFile {
+    copies: Vec<CopyTask>,
+}
+
+pub struct CopyStore {
+    store: JsonStore<CopiesFile>,
+}
+
+impl CopyStore {
+    pub fn new() -> Self {
+        Self {
+            store: JsonStore::open(COPIES_FILE),
+        }
+    }
+
+    pub async fn get(&self, id: &str) -> Option<CopyTask> {
+        self.store.read(|file| file.copies.iter().find(|c| c.id == id).cloned()).await
+    }
+
+    async fn insert(&self, task: CopyTask) -> std::io::Result<()> {
+        self.store.update(|file| file.copies.push(task)).await
+    }
+
+    async fn modify(&self, id: &str, f: impl FnOnce(&mut CopyTask)) -> Result<(), String> {
+        self.store
+            .update(|file| {
+                if let Some(task) = file.copies.iter_mut().find(|c| c.id == id) {
+                    f(task);
+                    task.updated_at = Utc::now();
+                }
+            })
+            .await
+            .map_err(|e| format!("saving copy checkpoint failed: {}", e))
+    }
+}
+
+// `$type` aliases in BSON sort order, grouped where types compare with each other
+// (numbers; strings and symbols). Arrays cannot be `_id`s.
+const ID_SORT_ORDER: [&[&str]; 11] = [
+    &["minKey"],
+    &["null"],
+    &["int", "long", "double", "decimal"],
+    &["string", "symbol"],
+    &["object"],
+    &["binData"],
+    &["objectId"],
+    &["bool"],
+    &["date"],
+    &["timestamp"],
+    &["regex"],
+];
+
+/// Matches `_id`s that sort after `last` in `{_id: 1}` order. `$gt` only compares within one
+/// BSON type, so `_id`s of the types that sort later are added explicitly; otherwise a
+/// collection with mixed `_id` types would silently lose them on resume.
+pub fn id_after(last: Bson) -> Document {
+    let type_name = match &last {
+        Bson::MinKey => "minKey",
+        Bson::Null => "null",
+        Bson::Int32(_) => "int",
+        Bson::Int64(_) => "long",
+        Bson::Double(_) => "double",
+        Bson::Decimal128(_) => "decimal",
+        Bson::String(_) | Bson::Symbol(_) => "string",
+        Bson::Document(_) => "object",
+        Bson::Binary(_) => "binData",
+        Bson::ObjectId(_) => "objectId",
+        Bson::Boolean(_) => "bool",
+        Bson::DateTime(_) => "date",
+        Bson::Timestamp(_) => "timestamp",
+        Bson::RegularExpression(_) => "regex",
+        // maxKey, code, undefined, ...: nothing reliably sorts after them
+        _ => return doc! { "_id": { "$gt": last } },
+    };
+    let rank = ID_SORT_ORDER.iter().position(|group| group.contains(&type_name)).unwrap_or(0);
+    let mut later: Vec<&str> = ID_SORT_ORDER[rank + 1..].iter().flat_map(|group| group.iter().copied()).collect();
+    later.push("maxKey");
+    doc! { "$or": [{ "_id": { "$gt": last } }, { "_id": { "$type": later } }] }
+}
+
+/// Source query for a (possibly resumed) pass: the user's filter, restricted to `_id`s after
+/// `last_id` (see `id_after`).
+pub fn resume_filter(filter: Option<Document>, last_id: Option<Bson>) -> Document {
+    let filter = filter.unwrap_or_default();
+    match last_id {
+        None => filter,
+        Some(id) if filter.is_empty() => id_after(id),
+        Some(id) => doc! { "$and": [filter, id_after(id)] },
+    }
+}
+
+/// Whether a projection drops `_id`, which copies checkpoint and match documents by.
+fn excludes_id(projection: &Document) -> bool {
+    match projection.get("_id") {
+        Some(Bson::Boolean(b)) => !b,
+        Some(Bson::Int32(v)) => *v == 0,
+        Some(Bson::Int64(v)) => *v == 0,
+        Some(Bson::Double(v)) => *v == 0.0,
+        _ => false,
+    }
+}
+
+fn token_to_json(token: &ResumeToken) -> Result<JsonValue, String> {
+    bson::to_bson(token)
+        .map(|b| b.into_canonical_extjson())
+        .map_err(|e| format!("encoding resume token failed: {}", e))
+}
+
+fn token_from_json(value: &JsonValue) -> Result<ResumeToken, String> {
+    let bson = Bson::try_from(value.clone()).map_err(|e| format!("invalid resume token: {}", e))?;
+    bson::from_bson(bson).map_err(|e| format!("invalid resume token: {}", e))
+}
+
+/// How many leading documents of a resumed batch were already written by the interrupted run:
+/// those up to and including `in_flight`, or the whole batch if it does not contain it.
+pub fn replayed_prefix(ids: &[Bson], in_flight: &JsonValue) -> usize {
+    ids.iter()
+        .position(|id| id.clone().into_canonical_extjson() == *in_flight)
+        .map_or(ids.len(), |i| i + 1)
+}
+
+/// Writes one batch. Duplicate keys among the first `replayed` documents were written by an
+/// interrupted run and are skipped; any other duplicate (a pre-filled target, or masked `_id`s
+/// colliding) fails the copy rather than silently dropping the document.
+/// Returns (inserted, skipped).
+async fn write_batch(target: &Collection<Document>, batch: &[Document], replayed: usize) -> Result<(u64, u64), String> {
+    match target.insert_many(batch).ordered(false).await {
+        Ok(r) => Ok((r.inserted_ids.len() as u64, 0)),
+        Err(e) => match *e.kind {
+            ErrorKind::InsertMany(ref failure) if failure.write_concern_error.is_none() => {
+                let write_errors = failure.write_errors.clone().unwrap_or_default();
+                if let Some(w) = write_errors.iter().find(|w| w.code != 11000 || w.index >= replayed) {
+                    return Err(format!("insert into target failed: {}", w.message));
+                }
+                let skipped = write_errors.len() as u64;
+                Ok((batch.len() as u64 - skipped, skipped))
+            }
+            _ => Err(format!("insert into target failed: {}", e)),
+        },
+    }
+}
+
+async fn copy_indexes(source: &Collection<Document>, target: &Collection<Document>) -> Result<usize, String> {
+    let source_db = source.client().database(&source.namespace().db);
+    let indexes: Vec<Bson> = first_batch(&source_db, doc! { "listIndexes": source.name(), "cursor": { "batchSize": 10_000 } })
+        .await
+        .map_err(|e| format!("listIndexes failed: {}", e))?
+        .into_iter()
+        .filter(|i| i.get_str("name") != Ok("_id_"))
+        .map(|mut i| {
+            i.remove("ns");
+            Bson::Document(i)
+        })
+        .collect();
+    if indexes.is_empty() {
+        return Ok(0);
+    }
+    let target_db = target.client().database(&target.namespace().db);
+    target_db
+        .run_command(doc! { "createIndexes": target.name(), "indexes": indexes.clone() })
+        .await
+        .map_err(|e| format!("createIndexes failed: {}", e))?;
+    Ok(indexes.len())
+}
+
+struct CopyRun {
+    id: String,
+    source: Collection<Document>,
+    target: Collection<Document>,
+    filter: Option<Document>,
+    projection: Option<Document>,
+    batch_size: usize,
+    fresh: bool,
+    mask: Option<Masker>,
+}
+
+impl CopyRun {
+    async fn initial_copy(&self, ctx: &JobContext, copies: &CopyStore, task: &CopyTask) -> Result<(), String> {
+        let last_id = match &task.last_id {
+            Some(v) => Some(Bson::try_from(v.clone()).map_err(|e| format!("invalid checkpoint: {}", e))?),
+            None => None,
+        };
+        if last_id.is_some() {
+            ctx.log(format!("resuming after {} copied documents", task.copied)).await;
+        }
+        let filter = resume_filter(self.filter.clone(), last_id);
+        let remaining = self.source.count_documents(filter.clone()).await.map_err(|e| format!("count failed: {}", e))?;
+        let total = task.copied + task.skipped + remaining;
+
+        let mut cursor = self
+            .source
+            .find(filter)
+            .sort(doc! { "_id": 1 })
+            .optional(self.projection.clone(), |a, p| a.projection(p))
+            .batch_size(self.batch_size as u32)
+            .await
+            .map_err(|e| format!("reading source failed: {}", e))?;
+        let (mut copied, mut skipped) = (task.copied, task.skipped);
+        // only the first batch of a resumed run can overlap the interrupted write
+        let mut in_flight = task.in_flight.clone();
+        let mut batch = Vec::with_capacity(self.batch_size);
+        loop {
+            let next = cursor.try_next().await.map_err(|e| format!("reading source failed: {}", e))?;
+            let done = next.is_none();
+            batch.extend(next);
+            if batch.len() < self.batch_size && !done {
+                continue;
+            }
+            if let Some(last) = batch.last().and_then(|d| d.get("_id")).cloned() {
+                let replayed = match in_flight.take() {
+                    Some(bound) => {
+                        let ids: Vec<Bson> = batch.iter().map(|d| d.get("_id").cloned().unwrap_or(Bson::Null)).collect();
+                        replayed_prefix(&ids, &bound)
+                    }
+                    None => 0,
+                };
+                let last = last.into_canonical_extjson();
+                let pending = last.clone();
+                copies.modify(&self.id, |t| t.in_flight = Some(pending)).await?;
+                if let Some(mask) = &self.mask {
+                    batch.iter_mut().for_each(|d| mask.apply(d));
+                }
+                let (inserted, dup) = write_batch(&self.target, &batch, replayed).await?;
+                copied += inserted;
+                skipped += dup;
+                batch.clear();
+                copies
+                    .modify(&self.id, |t| {
+                        t.last_id = Some(last);
+                        t.in_flight = None;
+                        t.copied = copied;
+                        t.skipped = skipped;
+                    })
+                    .await?;
+                if total > 0 {
+                    ctx.progress((copied + skipped) as f64 * 100.0 / total as f64).await;
+                }
+            } else if !batch.is_empty() {
+                return Err("source documents have no _id to checkpoint by".to_string());
+            }
+            if done {
+                break;
+            }
+        }
+        ctx.log(format!("copied {} documents ({} already present)", copied, skipped)).await;
+        Ok(())
+    }
+
+    /// Applies one change to the target. The current source document is re-read with the
+    /// copy's filter, projection and masking, so the target only ever holds what a fresh copy would.
+    async fn apply_change(&self, operation: &OperationType, key: Option<Document>) -> Result<bool, String> {
+        let Some(id) = key.and_then(|k| k.get("_id").cloned()) else {
+            return Ok(false);
+        };
+        let target_id = match &self.mask {
+            Some(mask) => mask.mask_field("_id", id.clone()),
+            None => id.clone(),
+        };
+        match operation {
+            OperationType::Insert | OperationType::Update | OperationType::Replace => {
+                let filter = match &self.filter {
+                    Some(f) => doc! { "$and": [{ "_id": id.clone() }, f.clone()] },
+                    None => doc! { "_id": id.clone() },
+                };
+                let current = self
+                    .source
+                    .find_one(filter)
+                    .optional(self.projection.clone(), |a, p| a.projection(p))
+                    .await
+                    .map_err(|e| format!("reading source failed: {}", e))?;
+                let result = match current {
+                    Some(mut doc) => {
+                        if let Some(mask) = &self.mask {
+                            mask.apply(&mut doc);
+                        }
+                        self.target.replace_one(doc! { "_id": target_id }, doc).upsert(true).await.map(|_| ())
+                    }
+                    // no longer matches the filter
+                    None => self.target.delete_one(doc! { "_id": target_id }).await.map(|_| ()),
+                };
+                result.map_err(|e| format!("applying change failed: {}", e))?;
+            }
+            OperationType::Delete => {
+                self.target
+                    .delete_one(doc! { "_id": target_id })
+                    .await
+                    .map_err(|e| format!("applying change failed: {}", e))?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Follows the source's change stream from the saved token until the job is cancelled.
+    async fn follow(&self, ctx: &JobContext, copies: &CopyStore, token: ResumeToken) -> Result<JsonValue, String> {
+        let mut stream = self
+            .source
+            .watch()
+            .resume_after(token)
+            .await
+            .map_err(|e| format!("opening change stream failed: {}", e))?;
+        ctx.log("following change stream").await;
+        let mut applied = copies.get(&self.id).await.map_or(0, |t| t.changes_applied);
+        let mut unsaved = 0;
+        loop {
+            let event = stream.next_if_any().await.map_err(|e| format!("change stream failed: {}", e))?;
+            match event {
+                Some(event) => {
+                    match event.operation_type {
+                        OperationType::Drop | OperationType::DropDatabase | OperationType::Rename | OperationType::Invalidate => {
+                            self.save_token(copies, stream.resume_token(), applied).await?;
+                            return Err("source collection was dropped or renamed".to_string());
+                        }
+                        ref op => {
+                            if self.apply_change(op, event.document_key).await? {
+                                applied += 1;
+                                unsaved += 1;
+                            }
+                        }
+                    }
+                    if unsaved >= CHANGE_CHECKPOINT_EVERY {
+                        self.save_token(copies, stream.resume_token(), applied).await?;
+                        unsaved = 0;
+                    }
+                }
+                None => {
+                    if unsaved > 0 {
+                        self.save_token(copies, stream.resume_token(), applied).await?;
+                        unsaved = 0;
+                    }
+                }
+            }
+            if !stream.is_alive() {
+                return Ok(json!({ "changesApplied": applied }));
+            }
+        }
+    }
+
+    async fn save_token(&self, copies: &CopyStore, token: Option<ResumeToken>, applied: u64) -> Result<(), String> {
+        let token = token.as_ref().map(token_to_json).transpose()?;
+        copies
+            .modify(&self.id, |t| {
+                if token.is_some() {
+                    t.resume_token = token;
+                }
+                t.changes_applied = applied;
+            })
+            .await
+    }
+
+    async fn run(self, ctx: JobContext, copies: web::Data<CopyStore>) -> Result<JobOutcome, String> {
+        let task = copies.get(&self.id).await.ok_or("copy checkpoint disappeared")?;
+        let incremental = task.request.incremental.unwrap_or(false);
+
+        if self.fresh && task.request.drop_target.unwrap_or(false) {
+            self.target.drop().await.map_err(|e| format!("dropping target failed: {}", e))?;
+            ctx.log("dropped target collection").await;
+        }
+
+        // Opened before the initial pass, so writes made while it runs are replayed afterwards.
+        if incremental && task.resume_token.is_none() && !task.initial_complete {
+            let stream = self.source.watch().await.map_err(|e| format!("opening change stream failed: {}", e))?;
+            let token = stream.resume_token().as_ref().map(token_to_json).transpose()?;
+            copies.modify(&self.id, |t| t.resume_token = token).await?;
+        }
+
+        if !task.initial_complete {
+            self.initial_copy(&ctx, &copies, &task).await?;
+            if task.request.copy_indexes.unwrap_or(true) {
+                let created = copy_indexes(&self.source, &self.target).await?;
+                ctx.log(format!("copied {} indexes", created)).await;
+            }
+            copies.modify(&self.id, |t| t.initial_complete = true).await?;
+        }
+        ctx.progress(100.0).await;
+
+        let task = copies.get(&self.id).await.ok_or("copy checkpoint disappeared")?;
+        let mut result = json!({ "copy": task.id, "copied": task.copied, "skipped": task.skipped });
+        if incremental {
+            let token = task.resume_token.as_ref().ok_or("no resume token to follow the change stream from")?;
+            ctx.release_slot();
+            let followed = self.follow(&ctx, &copies, token_from_json(token)?).await?;
+            result["changesApplied"] = followed["changesApplied"].clone();
+        }
+        Ok(JobOutcome::result(result))
+    }
+}
+
+/// The collection a namespace names, on its profile's cluster.
+pub async fn collection(
+    ns: &Namespace,
+    profiles: &ProfileStore,
+    main: &Client,
+    app_info: &AppInfo,
+) -> Result<Collection<Document>, String> {
+    let client = profiles.client(ns.profile.as_deref(), main, app_info).await?;
+    Ok(client.database(&ns.database).collection(&ns.collection))
+}
+
+/// Resolves both ends of a copy and starts it as a `copy` job, recording the job on the task.
+#[allow(clippy::too_many_arguments)]
+async fn start_copy(
+    task: CopyTask,
+    fresh: bool,
+    copies: web::Data<CopyStore>,
+    profiles: &ProfileStore,
+    main: &Client,
+    app_info: &AppInfo,
+    schemas: &SchemaStore,
+    jobs: &web::Data<JobStore>,
+) -> actix_web::Result<HttpResponse> {
+    let req = &task.request;
+    let (source, target) = match (
+        collection(&req.source, profiles, main, app_info).await,
+        collection(&req.target, profiles, main, app_info).await,
+    ) {
+        (Ok(s), Ok(t)) => (s, t),
+        (Err(e), _) | (_, Err(e)) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let (filter, projection) = match (
+        json_document(req.filter.as_ref(), "filter"),
+        json_document(req.projection.as_ref(), "projection"),
+    ) {
+        (Ok(f), Ok(p)) => (f, p),
+        (Err(e), _) | (_, Err(e)) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    if projection.as_ref().is_some_and(excludes_id) {
+        return Ok(HttpResponse::BadRequest().body("projection must keep _id: copies checkpoint and match documents by it"));
+    }
+    let source_connection = match profiles.connection(req.source.profile.as_deref(), app_info).await {
+        Ok(c) => c,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let mask = match masker_for(schemas, &source_connection, &req.source.database, &req.source.collection, req.mask).await {
+        Ok(m) => m,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+
+    let run = CopyRun {
+        id: task.id.clone(),
+        source,
+        target,
+        filter,
+        projection,
+        batch_size: req.batch_size.filter(|b| *b > 0).unwrap_or(DEFAULT_BATCH_SIZE),
+        fresh,
+        mask,
+    };
+    let mode = if req.incremental.unwrap_or(false) { "Sync" } else { "Copy" };
+    let description = format!("{} {} to {}", mode, req.source.label(), req.target.label());
+    let params = json!({ "copy": task.id, "source": req.source, "target": req.target, "masked": req.mask.unwrap_or(false) });
+    let id = task.id.clone();
+    let started = JobStore::start(jobs, "copy", description, params, {
+        let copies = copies.clone();
+        move |ctx| run.run(ctx, copies)
+    })
+    .await;
+    if let Ok(job) = &started
+        && let Err(e) = copies.modify(&id, |t| t.job_id = Some(job.id.clone())).await
+    {
+        eprintln!("copy job link error: {}", e);
+    }
+    accepted(started)
+}
+
+/// Starts a copy of `source` into `target` (`{profile?, database, collection}` each; a missing
+/// profile means globe's own connection). With `incremental`, the job keeps the target in sync
+/// from the source's change stream until it is stopped.
+#[post("/copy")]
+pub async fn create_copy(
+    body: web::Json<CopyRequest>,
+    data: web::Data<Client>,
+    app_info: web::Data<AppInfo>,
+    profiles: web::Data<ProfileStore>,
+    schemas: web::Data<SchemaStore>,
+    copies: web::Data<CopyStore>,
+    jobs: web::Data<JobStore>,
+) -> actix_web::Result<HttpResponse> {
+    let request = body.into_inner();
+    // compared by resolved cluster (scheme and hosts), since two profiles may point at the same one
+    let (source_cluster, target_cluster) = match (
+        profiles.connection(request.source.profile.as_deref(), &app_info).await,
+        profiles.connection(request.target.profile.as_deref(), &app_info).await,
+    ) {
+        (Ok(s), Ok(t)) => (s, t),
+        (Err(e), _) | (_, Err(e)) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    if source_cluster == target_cluster
+        && request.source.database == request.target.database
+        && request.source.collection == request.target.collection
+    {
+        return Ok(HttpResponse::BadRequest().body("source and target are the same collection"));
+    }
+    let now = Utc::now();
+    let task = CopyTask {
+        id: bson::oid::ObjectId::new().to_hex(),
+        request,
+        last_id: None,
+        in_flight: None,
+        copied: 0,
+        skipped: 0,
+        initial_complete: false,
+        resume_token: None,
+        changes_applied: 0,
+        job_id: None,
+        created_at: now,
+        updated_at: now,
+    };
+    if let Err(e) = copies.insert(task.clone()).await {
+        eprintln!("copy save error: {}", e);
+        return Ok(HttpResponse::InternalServerError().body(format!("failed to save copy: {}", e)));
+    }
+    start_copy(task, true, copies, &profiles, &data, &app_info, &schemas, &jobs).await
+}
+
+#[get("/copy")]
+pub async fn list_copies(copies: web::Data<CopyStore>) -> actix_web::Result<HttpResponse> {
+    let listed = copies.store.read(|file| file.copies.clone()).await;
+    Ok(HttpResponse::Ok().json(listed))
+}
+
+#[get("/copy/{id}")]
+pub async fn get_copy(path: web::Path<String>, copies: web::Data<CopyStore>) -> actix_web::Result<HttpResponse> {
+    match copies.get(&path.into_inner()).await {
+        Some(task) => Ok(HttpResponse::Ok().json(task)),
+        None => Ok(HttpResponse::NotFound().body("copy not found")),
+    }
+}
+
+/// Continues a stopped or failed copy from its checkpoint.
+#[post("/copy/{id}/resume")]
+pub async fn resume_copy(
+    path: web::Path<String>,
+    data: web::Data<Client>,
+    app_info: web::Data<AppInfo>,
+    profiles: web::Data<ProfileStore>,
+    schemas: web::Data<SchemaStore>,
+    copies: web::Data<CopyStore>,
+    jobs: web::Data<JobStore>,
+) -> actix_web::Result<HttpResponse> {
+    let Some(task) = copies.get(&path.into_inner()).await else {
+        return Ok(HttpResponse::NotFound().body("copy not found"));
+    };
+    if let Some(job_id) = &task.job_id
+        && let Some(job) = jobs.get(job_id).await
+        && !job.status.is_finished()
+    {
+        return Ok(HttpResponse::Conflict().body(format!("copy is still running as job {}", job_id)));
+    }
+    if task.initial_complete && !task.request.incremental.unwrap_or(false) {
+        return Ok(HttpResponse::Conflict().body("copy already completed"));
+    }
+    start_copy(task, false, copies, &profiles, &data, &app_info, &schemas, &jobs).await
+}
+
+/// Stops the copy's job; the checkpoint is kept so it can be resumed.
+#[post("/copy/{id}/stop")]
+pub async fn stop_copy(
+    path: web::Path<String>,
+    copies: web::Data<CopyStore>,
+    jobs: web::Data<JobStore>,
+) -> actix_web::Result<HttpResponse> {
+    let Some(task) = copies.get(&path.into_inner()).await else {
+        return Ok(HttpResponse::NotFound().body("copy not found"));
+    };
+    let Some(job_id) = &task.job_id else {
+        return Ok(HttpResponse::Conflict().body("copy has no job"));
+    };
+    match jobs.cancel(job_id).await {
+        Some(job) => Ok(HttpResponse::Ok().json(json!({ "copy": task.id, "job": job.summary(false) }))),
+        None => Ok(HttpResponse::NotFound().body("job not found")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restricts_resumed_pass_to_later_ids() {
+        assert_eq!(resume_filter(None, None), doc! {});
+        let after_five = doc! { "$or": [
+            { "_id": { "$gt": 5 } },
+            { "_id": { "$type": ["string", "symbol", "object", "binData", "objectId", "bool", "date", "timestamp", "regex", "maxKey"] } },
+        ] };
+        assert_eq!(resume_filter(None, Some(Bson::Int32(5))), after_five);
+        assert_eq!(
+            resume_filter(Some(doc! { "active": true }), Some(Bson::Int32(5))),
+            doc! { "$and": [{ "active": true }, after_five] }
+        );
+        let oid = bson::oid::ObjectId::new();
+        assert_eq!(
+            id_after(Bson::ObjectId(oid)),
+            doc! { "$or": [{ "_id": { "$gt": oid } }, { "_id": { "$type": ["bool", "date", "timestamp", "regex", "maxKey"] } }] }
+        );
+        assert!(excludes_id(&doc! { "_id": 0, "name": 1 }));
+        assert!(!excludes_id(&doc! { "name": 0 }));
+    }
+
+    #[test]
+    fn replay_stops_at_the_interrupted_batch_end() {
+        let ids = [Bson::Int32(6), Bson::Int32(7), Bson::Int32(8)];
+        assert_eq!(replayed_prefix(&ids, &Bson::Int32(7).into_canonical_extjson()), 2);
+        assert_eq!(replayed_prefix(&ids, &Bson::Int64(7).into_canonical_extjson()), 3);
+    }
+}
@@ -2,6 +2,7 @@ use mongodb::{bson::{self, doc, Bson, Document}, Client};
 use serde_json::Value as JsonValue;
 use actix_web::{get, web, HttpResponse};
 use serde::Serialize;
+use crate::drift::DriftStore;
 use crate::state::AppInfo;
 
 pub async fn list_databases(client: &Client) -> mongodb::error::Result<JsonValue> {
@@ -88,12 +89,23 @@ struct DashboardServer {
     ops_per_second: f64,
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DashboardDrift {
+    database: String,
+    collection: String,
+    detected_at: String,
+    changes: usize,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 struct DashboardResponse {
     connected_to: String,
     totals: DashboardTotals,
     server: DashboardServer,
+    // collections whose shape changed between their last two drift snapshots
+    schema_drift: Vec<DashboardDrift>,
 }
 
 fn extract_i64(value: Option<&Bson>) -> i64 {
@@ -121,6 +133,7 @@ fn sum_opcounters(doc: &Document) -> i64 {
 pub async fn dashboard(
     client: web::Data<Client>,
     app_info: web::Data<AppInfo>,
+    drift: web::Data<DriftStore>,
 ) -> actix_web::Result<HttpResponse> {
     let client = client.get_ref();
     let db_names = match client.list_database_names().await {
@@ -141,7 +154,7 @@ pub async fn dashboard(
 
         let collection_names = db.list_collection_names().await.unwrap_or_default();
         total_collections += collection_names.len() as i64;
-        if(name == "admin" || name == "local"){
+        if name == "admin" || name == "local" {
             continue;
         }
         match db.run_command(doc! { "dbstats": 1 }).await {
@@ -178,6 +191,18 @@ pub async fn dashboard(
         }
     }
 
+    let schema_drift = drift
+        .changed_collections(&app_info.shortened_uri)
+        .await
+        .into_iter()
+        .map(|s| DashboardDrift {
+            database: s.database,
+            collection: s.collection,
+            detected_at: s.taken_at.to_rfc3339(),
+            changes: s.changes,
+        })
+        .collect();
+
     let response = DashboardResponse {
         connected_to: app_info.shortened_uri.clone(),
         totals: DashboardTotals {
@@ -192,6 +217,7 @@ pub async fn dashboard(
             connections_current,
             ops_per_second,
         },
+        schema_drift,
     };
 
     Ok(HttpResponse::Ok().json(response))
@@ -0,0 +1,102 @@
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldChange {
+    pub path: String,
+    pub kind: ChangeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<JsonValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<JsonValue>,
+}
+
+/// Field-level diff between two JSON values. Objects are walked recursively and reported by
+/// dotted path; arrays and scalars are compared as whole values.
+pub fn diff_json(before: &JsonValue, after: &JsonValue) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    diff_at("", before, after, &mut changes);
+    changes
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+fn diff_at(path: &str, before: &JsonValue, after: &JsonValue, changes: &mut Vec<FieldChange>) {
+    match (before, after) {
+        (JsonValue::Object(a), JsonValue::Object(b)) => {
+            for (key, old) in a {
+                let child = join(path, key);
+                match b.get(key) {
+                    Some(new) => diff_at(&child, old, new, changes),
+                    None => changes.push(FieldChange {
+                        path: child,
+                        kind: ChangeKind::Removed,
+                        before: Some(old.clone()),
+                        after: None,
+                    }),
+                }
+            }
+            for (key, new) in b {
+                if !a.contains_key(key) {
+                    changes.push(FieldChange {
+                        path: join(path, key),
+                        kind: ChangeKind::Added,
+                        before: None,
+                        after: Some(new.clone()),
+                    });
+                }
+            }
+        }
+        _ if before != after => changes.push(FieldChange {
+            path: path.to_string(),
+            kind: ChangeKind::Changed,
+            before: Some(before.clone()),
+            after: Some(after.clone()),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_json, ChangeKind};
+    use serde_json::json;
+
+    #[test]
+    fn reports_nested_changes_by_path() {
+        let before = json!({"name": "a", "address": {"city": "x", "zip": 1}, "tags": [1, 2]});
+        let after = json!({"name": "a", "address": {"city": "y"}, "tags": [1, 2, 3], "age": 4});
+        let changes = diff_json(&before, &after);
+        let summary: Vec<(&str, ChangeKind)> = changes.iter().map(|c| (c.path.as_str(), c.kind.clone())).collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("address.city", ChangeKind::Changed),
+                ("address.zip", ChangeKind::Removed),
+                ("tags", ChangeKind::Changed),
+                ("age", ChangeKind::Added),
+            ]
+        );
+    }
+
+    #[test]
+    fn identical_values_have_no_changes() {
+        let value = json!({"a": {"b": [1, {"c": null}]}});
+        assert!(diff_json(&value, &value).is_empty());
+    }
+}
@@ -0,0 +1,560 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::PathBuf,
+    time::Duration,
+};
+
+use actix_web::{get, post, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, Document},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::sampling::{analyze_paths, sample_documents, DEFAULT_SAMPLE_SIZE};
+use crate::state::AppInfo;
+use crate::store::{data_dir, JsonStore};
+
+// Snapshot metadata lives in the index; each snapshot's field shapes in their own file under
+// drift/<collection key>/<id>.json, so recording one never rewrites the others.
+const DRIFT_DIR: &str = "drift";
+const DRIFT_INDEX: &str = "drift/index.json";
+const DEFAULT_INTERVAL_SECS: u64 = 3600;
+const DEFAULT_RETAIN: usize = 20;
+// Percentage points a field's presence or type share must move before it counts as drift.
+const DEFAULT_SHIFT_THRESHOLD: f64 = 10.0;
+// Documents a field must appear in before its appearance or disappearance counts as drift; a
+// field seen once or twice in a sample comes and goes between samples on its own.
+const MIN_SUPPORT: u64 = 5;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldShape {
+    // Share of parent documents containing the path.
+    pub presence: f64,
+    // Parent documents the path was seen in.
+    #[serde(default)]
+    pub support: u64,
+    pub types: BTreeMap<String, f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotInfo {
+    pub connection: String,
+    pub database: String,
+    pub collection: String,
+    pub id: u32,
+    pub taken_at: DateTime<Utc>,
+    pub sampled_documents: usize,
+    pub field_count: usize,
+    // Number of changes against the previous snapshot, 0 for the first one.
+    pub changes: usize,
+}
+
+impl SnapshotInfo {
+    fn is_for(&self, connection: &str, database: &str, collection: &str) -> bool {
+        self.connection == connection && self.database == database && self.collection == collection
+    }
+
+    fn path(&self) -> PathBuf {
+        let key = format!("{}\0{}\0{}", self.connection, self.database, self.collection);
+        let digest: String = Sha256::digest(key.as_bytes()).iter().take(16).map(|b| format!("{:02x}", b)).collect();
+        data_dir().join(DRIFT_DIR).join(digest).join(format!("{}.json", self.id))
+    }
+
+    fn load_fields(&self) -> std::io::Result<BTreeMap<String, FieldShape>> {
+        let bytes = fs::read(self.path())?;
+        serde_json::from_slice(&bytes).map_err(std::io::Error::other)
+    }
+
+    fn save_fields(&self, fields: &BTreeMap<String, FieldShape>) -> std::io::Result<()> {
+        let path = self.path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec(fields).map_err(std::io::Error::other)?)
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    #[serde(flatten)]
+    pub info: SnapshotInfo,
+    pub fields: BTreeMap<String, FieldShape>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct DriftIndex {
+    snapshots: Vec<SnapshotInfo>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ShapeDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub type_changed: Vec<TypeChange>,
+    pub frequency_shifted: Vec<FrequencyShift>,
+}
+
+impl ShapeDiff {
+    pub fn total(&self) -> usize {
+        self.added.len() + self.removed.len() + self.type_changed.len() + self.frequency_shifted.len()
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeChange {
+    pub path: String,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FrequencyShift {
+    pub path: String,
+    // "presence" or a BSON type name whose share moved
+    pub measure: String,
+    pub before: f64,
+    pub after: f64,
+}
+
+pub fn diff_shapes(
+    before: &BTreeMap<String, FieldShape>,
+    after: &BTreeMap<String, FieldShape>,
+    threshold: f64,
+) -> ShapeDiff {
+    let mut diff = ShapeDiff {
+        added: Vec::new(),
+        removed: Vec::new(),
+        type_changed: Vec::new(),
+        frequency_shifted: Vec::new(),
+    };
+
+    for (path, old) in before {
+        let new = match after.get(path) {
+            Some(n) => n,
+            None => {
+                if old.support >= MIN_SUPPORT {
+                    diff.removed.push(path.clone());
+                }
+                continue;
+            }
+        };
+        // types under the threshold on a side are left out, so one stray value is not a type change
+        let significant = |shape: &FieldShape| -> Vec<String> {
+            shape.types.iter().filter(|(_, share)| **share >= threshold).map(|(t, _)| t.clone()).collect()
+        };
+        let old_types = significant(old);
+        let new_types = significant(new);
+        if old_types != new_types {
+            diff.type_changed.push(TypeChange {
+                path: path.clone(),
+                before: old_types,
+                after: new_types,
+            });
+        } else {
+            for (t, share) in &old.types {
+                let new_share = new.types.get(t).copied().unwrap_or(0.0);
+                if (new_share - share).abs() >= threshold {
+                    diff.frequency_shifted.push(FrequencyShift {
+                        path: path.clone(),
+                        measure: t.clone(),
+                        before: *share,
+                        after: new_share,
+                    });
+                }
+            }
+        }
+        if (new.presence - old.presence).abs() >= threshold {
+            diff.frequency_shifted.push(FrequencyShift {
+                path: path.clone(),
+                measure: "presence".to_string(),
+                before: old.presence,
+                after: new.presence,
+            });
+        }
+    }
+    for (path, new) in after {
+        if !before.contains_key(path) && new.support >= MIN_SUPPORT {
+            diff.added.push(path.clone());
+        }
+    }
+
+    diff
+}
+
+pub struct DriftStore {
+    store: JsonStore<DriftIndex>,
+    retain: usize,
+}
+
+impl DriftStore {
+    pub fn new() -> Self {
+        let retain = env::var("GLOBE_DRIFT_RETAIN")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|v| *v >= 2)
+            .unwrap_or(DEFAULT_RETAIN);
+        Self {
+            store: JsonStore::open(DRIFT_INDEX),
+            retain,
+        }
+    }
+
+    pub async fn history(&self, connection: &str, database: &str, collection: &str) -> Vec<SnapshotInfo> {
+        let mut snapshots: Vec<SnapshotInfo> = self
+            .store
+            .read(|index| {
+                index
+                    .snapshots
+                    .iter()
+                    .filter(|s| s.is_for(connection, database, collection))
+                    .cloned()
+                    .collect()
+            })
+            .await;
+        snapshots.sort_by_key(|s| s.id);
+        snapshots
+    }
+
+    /// Collections whose most recent snapshot differs from the one before it.
+    pub async fn changed_collections(&self, connection: &str) -> Vec<SnapshotInfo> {
+        let mut latest: BTreeMap<(String, String), SnapshotInfo> = BTreeMap::new();
+        self.store
+            .read(|index| {
+                for s in index.snapshots.iter().filter(|s| s.connection == connection) {
+                    let key = (s.database.clone(), s.collection.clone());
+                    match latest.get(&key) {
+                        Some(existing) if existing.id >= s.id => {}
+                        _ => {
+                            latest.insert(key, s.clone());
+                        }
+                    }
+                }
+            })
+            .await;
+        latest.into_values().filter(|s| s.changes > 0).collect()
+    }
+
+    async fn record(
+        &self,
+        connection: &str,
+        database: &str,
+        collection: &str,
+        sampled_documents: usize,
+        fields: BTreeMap<String, FieldShape>,
+    ) -> std::io::Result<Snapshot> {
+        let retain = self.retain;
+        let (info, pruned) = self
+            .store
+            .update(|index| -> std::io::Result<(SnapshotInfo, Vec<SnapshotInfo>)> {
+                let previous = index
+                    .snapshots
+                    .iter()
+                    .filter(|s| s.is_for(connection, database, collection))
+                    .max_by_key(|s| s.id);
+                // a previous snapshot whose file is gone counts as no previous snapshot
+                let changes = previous
+                    .and_then(|p| p.load_fields().ok())
+                    .map(|before| diff_shapes(&before, &fields, DEFAULT_SHIFT_THRESHOLD).total())
+                    .unwrap_or(0);
+                let info = SnapshotInfo {
+                    connection: connection.to_string(),
+                    database: database.to_string(),
+                    collection: collection.to_string(),
+                    id: previous.map(|p| p.id + 1).unwrap_or(1),
+                    taken_at: Utc::now(),
+                    sampled_documents,
+                    field_count: fields.len(),
+                    changes,
+                };
+                info.save_fields(&fields)?;
+                index.snapshots.push(info.clone());
+
+                let mut ids: Vec<u32> = index
+                    .snapshots
+                    .iter()
+                    .filter(|s| s.is_for(connection, database, collection))
+                    .map(|s| s.id)
+                    .collect();
+                let mut pruned = Vec::new();
+                if ids.len() > retain {
+                    ids.sort_unstable();
+                    let cutoff = ids[ids.len() - retain];
+                    index.snapshots.retain(|s| {
+                        let expired = s.is_for(connection, database, collection) && s.id < cutoff;
+                        if expired {
+                            pruned.push(s.clone());
+                        }
+                        !expired
+                    });
+                }
+                Ok((info, pruned))
+            })
+            .await??;
+        for old in pruned {
+            let _ = fs::remove_file(old.path());
+        }
+        Ok(Snapshot { info, fields })
+    }
+}
+
+/// Samples a collection the same way `collection_stats` does and records its shape.
+pub async fn take_snapshot(
+    client: &Client,
+    drift: &DriftStore,
+    connection: &str,
+    database: &str,
+    collection: &str,
+) -> Result<Snapshot, String> {
+    let coll = client.database(database).collection::<Document>(collection);
+    let docs = sample_documents(&coll, DEFAULT_SAMPLE_SIZE)
+        .await
+        .map_err(|e| format!("aggregate failed: {}", e))?;
+    let fields: BTreeMap<String, FieldShape> = analyze_paths(&docs, 0)
+        .into_iter()
+        .map(|(path, summary)| {
+            (
+                path,
+                FieldShape {
+                    presence: ((100.0 - summary.missing_percentage) * 100.0).round() / 100.0,
+                    support: (summary.parent_count as f64 * (100.0 - summary.missing_percentage) / 100.0).round() as u64,
+                    types: summary.type_percentages,
+                },
+            )
+        })
+        .collect();
+    drift
+        .record(connection, database, collection, docs.len(), fields)
+        .await
+        .map_err(|e| format!("failed to store snapshot: {}", e))
+}
+
+async fn snapshot_all(client: &Client, drift: &DriftStore, connection: &str) -> mongodb::error::Result<()> {
+    for db_name in client.list_database_names().await? {
+        if db_name == "admin" || db_name == "local" || db_name == "config" {
+            continue;
+        }
+        let db = client.database(&db_name);
+        let names: Vec<String> = db
+            .list_collections()
+            .filter(doc! { "type": "collection" })
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?
+            .into_iter()
+            .map(|spec| spec.name)
+            .filter(|name| !name.starts_with("system."))
+            .collect();
+        for name in names {
+            if let Err(e) = take_snapshot(client, drift, connection, &db_name, &name).await {
+                eprintln!("drift snapshot failed for {}.{}: {}", db_name, name, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Snapshots every collection on a fixed interval (`GLOBE_DRIFT_INTERVAL_SECS`, 0 disables).
+pub async fn run_periodic(client: Client, drift: web::Data<DriftStore>, connection: String) {
+    let secs = env::var("GLOBE_DRIFT_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_INTERVAL_SECS);
+    if secs == 0 {
+        return;
+    }
+    let mut interval = tokio::time::interval(Duration::from_secs(secs));
+    loop {
+        interval.tick().await;
+        if let Err(e) = snapshot_all(&client, &drift, &connection).await {
+            eprintln!("drift snapshot run failed: {}", e);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DriftDiffQuery {
+    // Defaults to the snapshot before `to`.
+    from: Option<u32>,
+    // Defaults to the latest snapshot.
+    to: Option<u32>,
+    threshold: Option<f64>,
+}
+
+#[post("/drift/{db_name}/{coll_name}/snapshots")]
+pub async fn create_snapshot(
+    path: web::Path<(String, String)>,
+    data: web::Data<Client>,
+    drift: web::Data<DriftStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    match take_snapshot(&data, &drift, &app_info.shortened_uri, &db_name, &coll_name).await {
+        Ok(snapshot) => Ok(HttpResponse::Ok().json(snapshot)),
+        Err(e) => {
+            eprintln!("drift snapshot error: {}", e);
+            Ok(HttpResponse::InternalServerError().body(e))
+        }
+    }
+}
+
+#[get("/drift/{db_name}/{coll_name}/snapshots")]
+pub async fn list_snapshots(
+    path: web::Path<(String, String)>,
+    drift: web::Data<DriftStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let snapshots: Vec<serde_json::Value> = drift
+        .history(&app_info.shortened_uri, &db_name, &coll_name)
+        .await
+        .iter()
+        .map(|s| {
+            json!({
+                "id": s.id,
+                "takenAt": s.taken_at.to_rfc3339(),
+                "sampledDocuments": s.sampled_documents,
+                "fieldCount": s.field_count,
+                "changes": s.changes,
+            })
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(json!({
+        "database": db_name,
+        "collection": coll_name,
+        "snapshots": snapshots,
+    })))
+}
+
+#[get("/drift/{db_name}/{coll_name}/diff")]
+pub async fn diff_snapshots(
+    path: web::Path<(String, String)>,
+    query: web::Query<DriftDiffQuery>,
+    drift: web::Data<DriftStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let history = drift.history(&app_info.shortened_uri, &db_name, &coll_name).await;
+
+    let to = match query.to {
+        Some(id) => history.iter().find(|s| s.id == id),
+        None => history.last(),
+    };
+    let to = match to {
+        Some(s) => s,
+        None => return Ok(HttpResponse::NotFound().body("snapshot not found")),
+    };
+    let from = match query.from {
+        Some(id) => history.iter().find(|s| s.id == id),
+        None => history.iter().rev().find(|s| s.id < to.id),
+    };
+    let from = match from {
+        Some(s) => s,
+        None => return Ok(HttpResponse::NotFound().body("no earlier snapshot to compare against")),
+    };
+
+    let (before, after) = match (from.load_fields(), to.load_fields()) {
+        (Ok(before), Ok(after)) => (before, after),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("drift snapshot read error: {}", e);
+            return Ok(HttpResponse::InternalServerError().body(format!("failed to read snapshot: {}", e)));
+        }
+    };
+    let diff = diff_shapes(&before, &after, query.threshold.unwrap_or(DEFAULT_SHIFT_THRESHOLD));
+    Ok(HttpResponse::Ok().json(json!({
+        "database": db_name,
+        "collection": coll_name,
+        "from": { "id": from.id, "takenAt": from.taken_at.to_rfc3339() },
+        "to": { "id": to.id, "takenAt": to.taken_at.to_rfc3339() },
+        "changed": diff.total() > 0,
+        "diff": diff,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_shapes, FieldShape};
+    use std::collections::BTreeMap;
+
+    fn shape(presence: f64, types: &[(&str, f64)]) -> FieldShape {
+        FieldShape {
+            presence,
+            support: presence as u64,
+            types: types.iter().map(|(t, p)| (t.to_string(), *p)).collect(),
+        }
+    }
+
+    #[test]
+    fn detects_each_kind_of_drift() {
+        let before: BTreeMap<String, FieldShape> = [
+            ("name".to_string(), shape(100.0, &[("string", 100.0)])),
+            ("age".to_string(), shape(100.0, &[("int", 100.0)])),
+            ("legacy".to_string(), shape(40.0, &[("string", 100.0)])),
+            ("score".to_string(), shape(90.0, &[("double", 80.0), ("int", 20.0)])),
+        ]
+        .into_iter()
+        .collect();
+        let after: BTreeMap<String, FieldShape> = [
+            ("name".to_string(), shape(95.0, &[("string", 100.0)])),
+            ("age".to_string(), shape(100.0, &[("int", 50.0), ("string", 50.0)])),
+            ("score".to_string(), shape(60.0, &[("double", 50.0), ("int", 50.0)])),
+            ("email".to_string(), shape(30.0, &[("string", 100.0)])),
+        ]
+        .into_iter()
+        .collect();
+
+        let diff = diff_shapes(&before, &after, 10.0);
+        assert_eq!(diff.added, vec!["email"]);
+        assert_eq!(diff.removed, vec!["legacy"]);
+        assert_eq!(diff.type_changed.len(), 1);
+        assert_eq!(diff.type_changed[0].path, "age");
+        let shifts: Vec<(&str, &str)> = diff
+            .frequency_shifted
+            .iter()
+            .map(|s| (s.path.as_str(), s.measure.as_str()))
+            .collect();
+        assert_eq!(shifts, vec![("score", "double"), ("score", "int"), ("score", "presence")]);
+        assert_eq!(diff.total(), 6);
+    }
+
+    #[test]
+    fn ignores_stray_types_below_the_threshold() {
+        let before: BTreeMap<String, FieldShape> = [("age".to_string(), shape(100.0, &[("int", 100.0)]))].into_iter().collect();
+        let after: BTreeMap<String, FieldShape> =
+            [("age".to_string(), shape(100.0, &[("int", 99.0), ("string", 1.0)]))].into_iter().collect();
+        assert_eq!(diff_shapes(&before, &after, 10.0).total(), 0);
+
+        let after: BTreeMap<String, FieldShape> =
+            [("age".to_string(), shape(100.0, &[("int", 85.0), ("string", 15.0)]))].into_iter().collect();
+        let diff = diff_shapes(&before, &after, 10.0);
+        assert_eq!(diff.type_changed.len(), 1);
+        assert_eq!(diff.type_changed[0].after, vec!["int", "string"]);
+    }
+
+    #[test]
+    fn ignores_fields_too_rare_to_tell_apart_from_sampling_noise() {
+        let rare = |support| FieldShape {
+            presence: support as f64,
+            support,
+            types: [("string".to_string(), 100.0)].into_iter().collect(),
+        };
+        let before: BTreeMap<String, FieldShape> = [("once".to_string(), rare(1))].into_iter().collect();
+        let after: BTreeMap<String, FieldShape> =
+            [("twice".to_string(), rare(2)), ("often".to_string(), rare(5))].into_iter().collect();
+
+        let diff = diff_shapes(&before, &after, 10.0);
+        assert_eq!(diff.added, vec!["often"]);
+        assert!(diff.removed.is_empty());
+    }
+}
@@ -0,0 +1,1001 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{get, post, web, web::Bytes, HttpRequest, HttpResponse};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use futures::{
+    stream::{self, TryStreamExt},
+    StreamExt,
+};
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, Document, RawDocumentBuf},
+    error::ErrorKind,
+    Client, Database,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use tokio::sync::mpsc;
+
+use crate::import::{boundary, content_type, disposition_param};
+use crate::store::data_dir;
+
+const RESTORE_BATCH_SIZE: usize = 1000;
+const DEFAULT_RESTORE_MAX_MB: u64 = 10 * 1024;
+// The server rejects documents over 16 MiB; anything claiming more is a corrupt file.
+const MAX_BSON_SIZE: i32 = 16 * 1024 * 1024;
+// Multipart part headers read per part; longer ones are cut off.
+const MAX_PART_HEADERS: u64 = 8 * 1024;
+const DOWNLOAD_CHUNK: usize = 64 * 1024;
+// Raw documents handed to the archive writer at a time, and how many such chunks may queue up.
+const SPOOL_CHUNK: usize = 1024 * 1024;
+const SPOOL_QUEUE: usize = 8;
+// Per-collection errors listed in a restore report; the rest are only counted.
+const MAX_REPORTED_ERRORS: usize = 20;
+
+/// Minimal ustar writer: regular files only, sizes known up front.
+pub struct TarWriter<W: Write> {
+    out: W,
+}
+
+fn tar_header(path: &str, size: u64) -> io::Result<[u8; 512]> {
+    let mut header = [0u8; 512];
+    // names over 100 bytes go in the 155-byte prefix, split at a '/'
+    let (prefix, name) = if path.len() <= 100 {
+        ("", path)
+    } else {
+        // searched by byte: '/' is ASCII, so the split never lands inside a UTF-8 sequence
+        let bytes = path.as_bytes();
+        let split = bytes[..bytes.len().min(156)]
+            .iter()
+            .rposition(|b| *b == b'/')
+            .filter(|i| path.len() - i - 1 <= 100)
+            .ok_or_else(|| io::Error::other(format!("path too long for tar: {}", path)))?;
+        (&path[..split], &path[split + 1..])
+    };
+    let mtime = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[100..108].copy_from_slice(b"0000644\0");
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    header[124..136].copy_from_slice(format!("{:011o}\0", size).as_bytes());
+    header[136..148].copy_from_slice(format!("{:011o}\0", mtime).as_bytes());
+    header[148..156].copy_from_slice(b"        ");
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+    let checksum: u32 = header.iter().map(|b| *b as u32).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+    Ok(header)
+}
+
+impl<W: Write> TarWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    pub fn append(&mut self, path: &str, size: u64, data: &mut impl Read) -> io::Result<()> {
+        self.out.write_all(&tar_header(path, size)?)?;
+        let copied = io::copy(&mut data.take(size), &mut self.out)?;
+        if copied != size {
+            return Err(io::Error::other(format!("{} changed while archiving", path)));
+        }
+        let padding = (512 - (size % 512) as usize) % 512;
+        self.out.write_all(&vec![0u8; padding])
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(&[0u8; 1024])?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn octal(field: &[u8]) -> Result<u64, String> {
+    let text = String::from_utf8_lossy(field);
+    let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).map_err(|_| format!("bad tar number: {}", text))
+}
+
+fn tar_string(field: &[u8]) -> String {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Fills `buf` as far as the reader allows; fewer bytes than asked means end of input.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Streaming tar reader: `next_file` moves to the next regular file, whose contents are then
+/// read from the reader itself.
+pub struct TarReader<R: Read> {
+    inner: R,
+    remaining: u64,
+    padding: u64,
+}
+
+impl<R: Read> TarReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, remaining: 0, padding: 0 }
+    }
+
+    fn skip(&mut self, bytes: u64) -> Result<(), String> {
+        let skipped = io::copy(&mut (&mut self.inner).take(bytes), &mut io::sink()).map_err(|e| e.to_string())?;
+        if skipped < bytes {
+            return Err("tar archive is truncated".to_string());
+        }
+        Ok(())
+    }
+
+    /// Path and size of the next regular file; directories, links and pax headers are skipped.
+    pub fn next_file(&mut self) -> Result<Option<(String, u64)>, String> {
+        self.skip(self.remaining + self.padding)?;
+        self.remaining = 0;
+        self.padding = 0;
+        loop {
+            let mut header = [0u8; 512];
+            match read_full(&mut self.inner, &mut header).map_err(|e| e.to_string())? {
+                0 => return Ok(None),
+                512 => {}
+                _ => return Err("tar header is truncated".to_string()),
+            }
+            if header.iter().all(|b| *b == 0) {
+                return Ok(None);
+            }
+            let name = tar_string(&header[..100]);
+            let prefix = tar_string(&header[345..500]);
+            let size = octal(&header[124..136])?;
+            let padding = (512 - size % 512) % 512;
+            // '0' or NUL is a regular file
+            if matches!(header[156], b'0' | 0) {
+                self.remaining = size;
+                self.padding = padding;
+                let path = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+                return Ok(Some((path, size)));
+            }
+            self.skip(size + padding)?;
+        }
+    }
+}
+
+impl<R: Read> Read for TarReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = buf.len().min(self.remaining as usize);
+        if max == 0 {
+            return Ok(0);
+        }
+        let n = self.inner.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "tar entry is truncated"));
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// Next document of a `.bson` file (concatenated documents), `None` at the end of the file.
+pub fn next_document(reader: &mut impl Read) -> Result<Option<RawDocumentBuf>, String> {
+    let mut len_bytes = [0u8; 4];
+    match read_full(reader, &mut len_bytes).map_err(|e| e.to_string())? {
+        0 => return Ok(None),
+        4 => {}
+        _ => return Err("truncated BSON document".to_string()),
+    }
+    let len = i32::from_le_bytes(len_bytes);
+    if !(5..=MAX_BSON_SIZE).contains(&len) {
+        return Err(format!("invalid BSON document length {}", len));
+    }
+    let mut bytes = vec![0u8; len as usize];
+    bytes[..4].copy_from_slice(&len_bytes);
+    if read_full(reader, &mut bytes[4..]).map_err(|e| e.to_string())? < len as usize - 4 {
+        return Err("truncated BSON document".to_string());
+    }
+    RawDocumentBuf::from_bytes(bytes).map(Some).map_err(|e| format!("invalid BSON: {}", e))
+}
+
+// mongodump percent-encodes characters that are unsafe in file names.
+fn escape_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | '%' | '*' | '<' | '>' | ':' | '|' | '?' | '"' => format!("%{:02X}", c as u32),
+            _ => c.to_string(),
+        })
+        .collect()
+}
+
+fn unescape_name(name: &str) -> String {
+    let bytes = name.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(v) = name.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok())
+        {
+            out.push(v);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+pub async fn first_batch(db: &Database, command: Document) -> mongodb::error::Result<Vec<Document>> {
+    let reply = db.run_command(command).await?;
+    let batch = reply
+        .get_document("cursor")
+        .and_then(|c| c.get_array("firstBatch"))
+        .map(|items| items.iter().filter_map(|b| b.as_document().cloned()).collect())
+        .unwrap_or_default();
+    Ok(batch)
+}
+
+/// The `metadata.json` mongodump writes next to each collection's `.bson`.
+fn metadata(spec: &Document, indexes: Vec<Document>) -> Document {
+    let name = spec.get_str("name").unwrap_or_default();
+    let mut meta = doc! {
+        "indexes": indexes,
+        "collectionName": name,
+        "type": spec.get_str("type").unwrap_or("collection"),
+        "options": spec.get_document("options").cloned().unwrap_or_default(),
+    };
+    if let Ok(Bson::Binary(uuid)) = spec.get_document("info").map(|i| i.get("uuid").cloned().unwrap_or(Bson::Null)) {
+        let hex: String = uuid.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        meta.insert("uuid", hex);
+    }
+    meta
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DumpedCollection {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub documents: u64,
+    pub bytes: u64,
+    pub indexes: usize,
+}
+
+fn gzip_suffix(gzip: bool) -> &'static str {
+    if gzip { ".gz" } else { "" }
+}
+
+fn append_file<W: Write>(tar: &mut TarWriter<W>, path: &str, file: &Path) -> io::Result<()> {
+    let size = fs::metadata(file)?.len();
+    tar.append(path, size, &mut File::open(file)?)
+}
+
+fn spool_writer(path: &Path, gzip: bool) -> io::Result<Box<dyn Write + Send>> {
+    let file = BufWriter::new(File::create(path)?);
+    Ok(if gzip { Box::new(GzEncoder::new(file, Compression::default())) } else { Box::new(file) })
+}
+
+/// What `write_dump` hands the archive writer.
+enum Spooled {
+    Data(Vec<u8>),
+    // closes the spooled entry and appends it to the tar under this path
+    Entry(String),
+}
+
+/// Owns the tar and the spool file on a blocking thread, so file writes and gzip never run on
+/// the async workers. Documents are spooled first because tar needs each entry's size up front.
+fn archive_writer(out: &Path, gzip: bool, mut rx: mpsc::Receiver<Spooled>) -> io::Result<()> {
+    if let Some(parent) = out.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut tar = TarWriter::new(BufWriter::new(File::create(out)?));
+    let spool = out.with_extension("spool");
+    let mut writer: Option<Box<dyn Write + Send>> = None;
+    let mut write = || -> io::Result<()> {
+        while let Some(message) = rx.blocking_recv() {
+            match message {
+                Spooled::Data(bytes) => {
+                    let w = match &mut writer {
+                        Some(w) => w,
+                        None => writer.insert(spool_writer(&spool, gzip)?),
+                    };
+                    w.write_all(&bytes)?;
+                }
+                Spooled::Entry(path) => {
+                    let mut w = match writer.take() {
+                        Some(w) => w,
+                        None => spool_writer(&spool, gzip)?,
+                    };
+                    w.flush()?;
+                    drop(w);
+                    append_file(&mut tar, &path, &spool)?;
+                }
+            }
+        }
+        Ok(())
+    };
+    let written = write();
+    let _ = fs::remove_file(&spool);
+    written?;
+    tar.finish()?;
+    Ok(())
+}
+
+async fn spool(tx: &mpsc::Sender<Spooled>, message: Spooled) -> Result<(), String> {
+    tx.send(message).await.map_err(|_| "archive writer stopped".to_string())
+}
+
+async fn dump_collections(
+    db: &Database,
+    specs: Vec<Document>,
+    gzip: bool,
+    tx: &mpsc::Sender<Spooled>,
+) -> Result<Vec<DumpedCollection>, String> {
+    let dir = escape_name(db.name());
+    let mut dumped = Vec::new();
+    for spec in specs {
+        let name = spec.get_str("name").unwrap_or_default().to_string();
+        let kind = spec.get_str("type").unwrap_or("collection").to_string();
+        let indexes = if kind == "view" {
+            Vec::new()
+        } else {
+            first_batch(db, doc! { "listIndexes": name.as_str(), "cursor": { "batchSize": 10_000 } })
+                .await
+                .map_err(|e| format!("listIndexes on {} failed: {}", name, e))?
+        };
+
+        let mut documents = 0u64;
+        let mut bytes = 0u64;
+        if kind != "view" {
+            let mut cursor = db
+                .collection::<RawDocumentBuf>(&name)
+                .find(doc! {})
+                .await
+                .map_err(|e| format!("reading {} failed: {}", name, e))?;
+            let mut chunk = Vec::new();
+            while let Some(raw) = cursor.try_next().await.map_err(|e| format!("reading {} failed: {}", name, e))? {
+                chunk.extend_from_slice(raw.as_bytes());
+                documents += 1;
+                bytes += raw.as_bytes().len() as u64;
+                if chunk.len() >= SPOOL_CHUNK {
+                    spool(tx, Spooled::Data(std::mem::take(&mut chunk))).await?;
+                }
+            }
+            spool(tx, Spooled::Data(chunk)).await?;
+        }
+
+        let file_name = escape_name(&name);
+        let suffix = gzip_suffix(gzip);
+        spool(tx, Spooled::Entry(format!("{}/{}.bson{}", dir, file_name, suffix))).await?;
+
+        let meta = Bson::Document(metadata(&spec, indexes.clone())).into_canonical_extjson().to_string();
+        spool(tx, Spooled::Data(meta.into_bytes())).await?;
+        spool(tx, Spooled::Entry(format!("{}/{}.metadata.json{}", dir, file_name, suffix))).await?;
+
+        dumped.push(DumpedCollection {
+            name,
+            kind,
+            documents,
+            bytes,
+            indexes: indexes.len(),
+        });
+    }
+    Ok(dumped)
+}
+
+/// Writes `db_name` (or just `collections`) as a tar of mongodump's directory layout:
+/// `<db>/<coll>.bson` and `<coll>.metadata.json`, each gzipped when `gzip` is set, like
+/// `mongodump --gzip`. Extracting it gives a directory `mongorestore` accepts.
+pub async fn write_dump(
+    client: &Client,
+    db_name: &str,
+    collections: Option<&[String]>,
+    gzip: bool,
+    out: &Path,
+) -> Result<Vec<DumpedCollection>, String> {
+    let db = client.database(db_name);
+    let specs = first_batch(&db, doc! { "listCollections": 1, "cursor": { "batchSize": 100_000 } })
+        .await
+        .map_err(|e| format!("listCollections failed: {}", e))?;
+    let mut specs: Vec<Document> = specs
+        .into_iter()
+        .filter(|s| {
+            let name = s.get_str("name").unwrap_or_default();
+            !name.starts_with("system.") && collections.is_none_or(|only| only.iter().any(|c| c == name))
+        })
+        .collect();
+    specs.sort_by(|a, b| a.get_str("name").unwrap_or_default().cmp(b.get_str("name").unwrap_or_default()));
+
+    let (tx, rx) = mpsc::channel(SPOOL_QUEUE);
+    let writer = tokio::task::spawn_blocking({
+        let out = out.to_path_buf();
+        move || archive_writer(&out, gzip, rx)
+    });
+    let dumped = dump_collections(&db, specs, gzip, &tx).await;
+    drop(tx);
+    let written = writer.await.map_err(|e| format!("archive writer failed: {}", e))?;
+    // a failed writer also makes the reading side fail; its own error says why
+    written.map_err(|e| e.to_string())?;
+    dumped
+}
+
+/// Streams a file in fixed-size chunks, deleting it once fully sent when `remove` is set.
+pub fn file_stream(path: PathBuf, remove: bool) -> io::Result<impl futures::Stream<Item = Result<Bytes, actix_web::Error>>> {
+    let file = File::open(&path)?;
+    Ok(stream::unfold(Some((file, path)), move |state| async move {
+        let (mut file, path) = state?;
+        let mut buf = vec![0u8; DOWNLOAD_CHUNK];
+        match file.read(&mut buf) {
+            Ok(0) => {
+                if remove {
+                    let _ = fs::remove_file(&path);
+                }
+                None
+            }
+            Ok(n) => {
+                buf.truncate(n);
+                Some((Ok(Bytes::from(buf)), Some((file, path))))
+            }
+            Err(e) => Some((Err(actix_web::error::ErrorInternalServerError(e)), None)),
+        }
+    }))
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct DumpQuery {
+    // comma separated; defaults to every collection and view
+    pub collections: Option<String>,
+    pub gzip: Option<bool>,
+}
+
+pub fn split_list(value: Option<&str>) -> Option<Vec<String>> {
+    value.map(|v| v.split(',').map(|c| c.trim().to_string()).filter(|c| !c.is_empty()).collect())
+}
+
+#[get("/dump/{db_name}")]
+pub async fn dump_database(
+    path: web::Path<String>,
+    query: web::Query<DumpQuery>,
+    data: web::Data<Client>,
+) -> actix_web::Result<HttpResponse> {
+    let db_name = path.into_inner();
+    let collections = split_list(query.collections.as_deref());
+    let out = data_dir().join("dumps").join(format!("{}.tar", ObjectId::new().to_hex()));
+
+    if let Err(e) = write_dump(&data, &db_name, collections.as_deref(), query.gzip.unwrap_or(false), &out).await {
+        eprintln!("dump error: {}", e);
+        let _ = fs::remove_file(&out);
+        return Ok(HttpResponse::InternalServerError().body(format!("dump failed: {}", e)));
+    }
+    match file_stream(out, true) {
+        Ok(body) => Ok(HttpResponse::Ok()
+            .content_type("application/x-tar")
+            .insert_header(("Content-Disposition", format!("attachment; filename=\"{}.tar\"", db_name)))
+            .streaming(body)),
+        Err(e) => Ok(HttpResponse::InternalServerError().body(format!("dump failed: {}", e))),
+    }
+}
+
+fn restore_limit() -> u64 {
+    env::var("GLOBE_RESTORE_MAX_MB")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_RESTORE_MAX_MB)
+        * 1024
+        * 1024
+}
+
+#[derive(Debug, PartialEq)]
+enum EntryKind {
+    Bson,
+    Metadata,
+}
+
+/// What an archive path holds for a restore: the directory it was dumped under (its database),
+/// collection, kind and whether it is gzipped. mongodump's oplog and prelude files are ignored.
+fn restore_target(path: &str) -> Option<(String, String, EntryKind, bool)> {
+    let (dir, file) = path.rsplit_once('/').unwrap_or(("", path));
+    let (file, gzipped) = match file.strip_suffix(".gz") {
+        Some(f) => (f, true),
+        None => (file, false),
+    };
+    if let Some(name) = file.strip_suffix(".metadata.json") {
+        Some((dir.to_string(), unescape_name(name), EntryKind::Metadata, gzipped))
+    } else {
+        file.strip_suffix(".bson")
+            .filter(|name| *name != "oplog")
+            .map(|name| (dir.to_string(), unescape_name(name), EntryKind::Bson, gzipped))
+    }
+}
+
+/// A collection extracted from the archive: its documents spooled to disk, uncompressed, and
+/// its parsed `metadata.json`.
+#[derive(Default)]
+struct RestoreEntry {
+    bson: Option<PathBuf>,
+    metadata: Option<Document>,
+}
+
+impl RestoreEntry {
+    fn is_view(&self) -> bool {
+        self.metadata.as_ref().is_some_and(|m| m.get_str("type") == Ok("view"))
+    }
+}
+
+/// Byte range of the `file` part in a multipart body spooled to `file`, found by scanning in
+/// chunks so the body never has to fit in memory.
+fn multipart_file_range(file: &mut File, boundary: &str) -> Result<(u64, u64), String> {
+    let delimiter = format!("\r\n--{}", boundary).into_bytes();
+    // the first delimiter may open the body without a preceding CRLF
+    let mut pos = find_in_file(file, &delimiter[2..], 0)?.ok_or("multipart boundary not found")? + delimiter.len() as u64 - 2;
+    loop {
+        let mut closing = [0u8; 2];
+        file.seek(SeekFrom::Start(pos)).map_err(|e| e.to_string())?;
+        read_full(file, &mut closing).map_err(|e| e.to_string())?;
+        if &closing == b"--" {
+            return Err("multipart upload has no file part".to_string());
+        }
+        let headers_end = find_in_file(file, b"\r\n\r\n", pos)?.ok_or("malformed multipart part headers")?;
+        let mut headers = vec![0u8; (headers_end - pos).min(MAX_PART_HEADERS) as usize];
+        file.seek(SeekFrom::Start(pos)).map_err(|e| e.to_string())?;
+        read_full(file, &mut headers).map_err(|e| e.to_string())?;
+        let start = headers_end + 4;
+        let end = find_in_file(file, &delimiter, start)?.ok_or("multipart body is truncated")?;
+        let is_file = String::from_utf8_lossy(&headers).lines().any(|line| {
+            line.split_once(':').is_some_and(|(key, value)| {
+                key.trim().eq_ignore_ascii_case("content-disposition") && disposition_param(value, "name").as_deref() == Some("file")
+            })
+        });
+        if is_file {
+            return Ok((start, end));
+        }
+        pos = end + delimiter.len() as u64;
+    }
+}
+
+/// Offset of the first `needle` at or after `from`.
+fn find_in_file(file: &mut File, needle: &[u8], from: u64) -> Result<Option<u64>, String> {
+    file.seek(SeekFrom::Start(from)).map_err(|e| e.to_string())?;
+    let mut window: Vec<u8> = Vec::new();
+    let mut window_start = from;
+    let mut buf = vec![0u8; DOWNLOAD_CHUNK];
+    loop {
+        let n = file.read(&mut buf).map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Ok(None);
+        }
+        window.extend_from_slice(&buf[..n]);
+        if let Some(i) = window.windows(needle.len()).position(|w| w == needle) {
+            return Ok(Some(window_start + i as u64));
+        }
+        // keep just enough of the tail to catch a needle split across reads
+        let keep = (needle.len() - 1).min(window.len());
+        window_start += (window.len() - keep) as u64;
+        window.drain(..window.len() - keep);
+    }
+}
+
+/// Unpacks the spooled upload into `dir`: the multipart `file` part (or the whole body), gunzipped
+/// if it is gzip, read as a tar one entry at a time. Extracted data past `limit` bytes fails the
+/// restore, so a small upload cannot expand without bound.
+/// Spools an archive's collections under `dir`, keyed by the directory they were dumped under
+/// and their name. Archives holding more than one database's dump are refused.
+fn extract_archive(
+    upload: &Path,
+    boundary: Option<&str>,
+    dir: &Path,
+    limit: u64,
+) -> Result<BTreeMap<(String, String), RestoreEntry>, String> {
+    let mut file = File::open(upload).map_err(|e| e.to_string())?;
+    let (start, end) = match boundary {
+        Some(b) => multipart_file_range(&mut file, b)?,
+        None => (0, file.metadata().map_err(|e| e.to_string())?.len()),
+    };
+    file.seek(SeekFrom::Start(start)).map_err(|e| e.to_string())?;
+    let mut body = BufReader::new(file.take(end - start));
+    let gzipped = body.fill_buf().map_err(|e| e.to_string())?.starts_with(&[0x1f, 0x8b]);
+    let archive: Box<dyn Read> = if gzipped { Box::new(GzDecoder::new(body)) } else { Box::new(body) };
+
+    let mut tar = TarReader::new(archive);
+    let mut grouped: BTreeMap<(String, String), RestoreEntry> = BTreeMap::new();
+    let mut budget = limit;
+    let mut spooled_files = 0;
+    while let Some((path, _)) = tar.next_file()? {
+        let Some((source, name, kind, gz)) = restore_target(&path) else {
+            continue;
+        };
+        if let Some(((first, _), _)) = grouped.first_key_value()
+            && *first != source
+        {
+            return Err(format!(
+                "archive holds dumps of more than one database ({} and {}); restore them one at a time",
+                first, source
+            ));
+        }
+        let contents: Box<dyn Read + '_> = if gz { Box::new(GzDecoder::new(&mut tar)) } else { Box::new(&mut tar) };
+        let mut contents = contents.take(budget + 1);
+        let entry = grouped.entry((source, name.clone())).or_default();
+        let used = match kind {
+            EntryKind::Bson => {
+                // numbered rather than named: collection names may hold any character
+                spooled_files += 1;
+                let spooled = dir.join(format!("{}.bson", spooled_files));
+                let mut out = BufWriter::new(File::create(&spooled).map_err(|e| e.to_string())?);
+                let copied = io::copy(&mut contents, &mut out).map_err(|e| format!("{}: {}", path, e))?;
+                out.flush().map_err(|e| e.to_string())?;
+                entry.bson = Some(spooled);
+                copied
+            }
+            EntryKind::Metadata => {
+                let mut text = Vec::new();
+                contents.read_to_end(&mut text).map_err(|e| format!("{}: {}", path, e))?;
+                let json: JsonValue = serde_json::from_slice(&text).map_err(|e| format!("{} metadata: {}", name, e))?;
+                match Bson::try_from(json) {
+                    Ok(Bson::Document(d)) => entry.metadata = Some(d),
+                    _ => return Err(format!("{} metadata is not an extended JSON object", name)),
+                }
+                text.len() as u64
+            }
+        };
+        if used > budget {
+            return Err(format!("archive expands past {} bytes (GLOBE_RESTORE_MAX_MB)", limit));
+        }
+        budget -= used;
+    }
+    Ok(grouped)
+}
+
+/// Writes the request body to `path` on a blocking thread as it arrives, refusing more than
+/// `limit` bytes.
+async fn spool_upload(payload: &mut web::Payload, path: PathBuf, limit: u64) -> Result<(), HttpResponse> {
+    let (tx, mut rx) = mpsc::channel::<Bytes>(SPOOL_QUEUE);
+    let writer = tokio::task::spawn_blocking(move || -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = BufWriter::new(File::create(&path)?);
+        while let Some(chunk) = rx.blocking_recv() {
+            file.write_all(&chunk)?;
+        }
+        file.flush()
+    });
+
+    let mut received = 0u64;
+    let mut failure = None;
+    while let Some(chunk) = payload.next().await {
+        let chunk = match chunk {
+            Ok(c) => c,
+            Err(e) => {
+                failure = Some(HttpResponse::BadRequest().body(format!("upload failed: {}", e)));
+                break;
+            }
+        };
+        received += chunk.len() as u64;
+        if received > limit {
+            failure = Some(HttpResponse::PayloadTooLarge().body(format!("restore upload exceeds {} bytes (GLOBE_RESTORE_MAX_MB)", limit)));
+            break;
+        }
+        // a closed channel means the writer failed; its error is reported below
+        if tx.send(chunk).await.is_err() {
+            break;
+        }
+    }
+    drop(tx);
+    let written = writer.await;
+    if let Some(resp) = failure {
+        return Err(resp);
+    }
+    match written {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => Err(HttpResponse::InternalServerError().body(format!("spooling upload failed: {}", e))),
+        Err(e) => Err(HttpResponse::InternalServerError().body(format!("spooling upload failed: {}", e))),
+    }
+}
+
+/// Reads a spooled `.bson` file on a blocking thread, `RESTORE_BATCH_SIZE` documents at a time.
+fn bson_batches(path: PathBuf) -> mpsc::Receiver<Result<Vec<RawDocumentBuf>, String>> {
+    let (tx, rx) = mpsc::channel(2);
+    tokio::task::spawn_blocking(move || {
+        let mut reader = match File::open(&path) {
+            Ok(f) => BufReader::new(f),
+            Err(e) => {
+                let _ = tx.blocking_send(Err(e.to_string()));
+                return;
+            }
+        };
+        let mut batch = Vec::with_capacity(RESTORE_BATCH_SIZE);
+        loop {
+            match next_document(&mut reader) {
+                Ok(Some(doc)) => {
+                    batch.push(doc);
+                    if batch.len() >= RESTORE_BATCH_SIZE && tx.blocking_send(Ok(std::mem::take(&mut batch))).is_err() {
+                        return;
+                    }
+                }
+                Ok(None) => {
+                    if !batch.is_empty() {
+                        let _ = tx.blocking_send(Ok(batch));
+                    }
+                    return;
+                }
+                Err(e) => {
+                    let _ = tx.blocking_send(Err(e));
+                    return;
+                }
+            }
+        }
+    });
+    rx
+}
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct RestoredCollection {
+    name: String,
+    documents: u64,
+    inserted: u64,
+    failed: u64,
+    indexes: usize,
+    errors: Vec<String>,
+}
+
+impl RestoredCollection {
+    fn error(&mut self, message: String) {
+        if self.errors.len() < MAX_REPORTED_ERRORS {
+            self.errors.push(message);
+        }
+    }
+}
+
+async fn restore_collection(db: &Database, name: &str, entry: &RestoreEntry, drop: bool) -> Result<RestoredCollection, String> {
+    let mut report = RestoredCollection {
+        name: name.to_string(),
+        ..Default::default()
+    };
+    let meta = entry.metadata.clone().unwrap_or_default();
+
+    if drop {
+        db.collection::<Document>(name).drop().await.map_err(|e| format!("dropping {} failed: {}", name, e))?;
+    }
+    // recreate with the original options (capped, validator, collation, views' pipeline, ...)
+    let mut create = doc! { "create": name };
+    if let Ok(options) = meta.get_document("options") {
+        create.extend(options.clone());
+    }
+    if let Err(e) = db.run_command(create).await {
+        match *e.kind {
+            // NamespaceExists: restore into the existing collection, as mongorestore does
+            ErrorKind::Command(ref c) if c.code == 48 => {}
+            _ => return Err(format!("creating {} failed: {}", name, e)),
+        }
+    }
+
+    if let Some(path) = &entry.bson {
+        let coll = db.collection::<RawDocumentBuf>(name);
+        let mut batches = bson_batches(path.clone());
+        while let Some(batch) = batches.recv().await {
+            let batch = batch.map_err(|e| format!("{}: {}", name, e))?;
+            let attempted = batch.len() as u64;
+            report.documents += attempted;
+            match coll.insert_many(&batch).ordered(false).await {
+                Ok(r) => report.inserted += r.inserted_ids.len() as u64,
+                Err(e) => match *e.kind {
+                    ErrorKind::InsertMany(ref failure) => {
+                        let write_errors = failure.write_errors.clone().unwrap_or_default();
+                        report.inserted += attempted - write_errors.len() as u64;
+                        report.failed += write_errors.len() as u64;
+                        for w in write_errors {
+                            report.error(w.message);
+                        }
+                    }
+                    _ => {
+                        report.failed += attempted;
+                        report.error(e.to_string());
+                    }
+                },
+            }
+        }
+    }
+
+    let indexes: Vec<Bson> = meta
+        .get_array("indexes")
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|i| i.as_document().cloned())
+                .filter(|i| i.get_str("name") != Ok("_id_"))
+                .map(|mut i| {
+                    i.remove("ns");
+                    Bson::Document(i)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if !indexes.is_empty() {
+        report.indexes = indexes.len();
+        if let Err(e) = db.run_command(doc! { "createIndexes": name, "indexes": indexes }).await {
+            report.indexes = 0;
+            report.error(format!("createIndexes failed: {}", e));
+        }
+    }
+    Ok(report)
+}
+
+#[derive(Deserialize)]
+pub struct RestoreQuery {
+    // drop each collection before restoring it (mongorestore --drop)
+    drop: Option<bool>,
+    collections: Option<String>,
+}
+
+async fn restore_spooled(
+    req: &HttpRequest,
+    payload: &mut web::Payload,
+    query: &RestoreQuery,
+    db: &Database,
+    dir: &Path,
+) -> HttpResponse {
+    let limit = restore_limit();
+    let upload = dir.join("upload");
+    if let Err(resp) = spool_upload(payload, upload.clone(), limit).await {
+        return resp;
+    }
+    let boundary = boundary(content_type(req));
+    let extracted = tokio::task::spawn_blocking({
+        let dir = dir.to_path_buf();
+        move || extract_archive(&upload, boundary.as_deref(), &dir, limit)
+    })
+    .await;
+    let grouped = match extracted {
+        Ok(Ok(g)) => g,
+        Ok(Err(e)) => return HttpResponse::BadRequest().body(e),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("extracting archive failed: {}", e)),
+    };
+    if grouped.is_empty() {
+        return HttpResponse::BadRequest().body("archive holds no .bson or .metadata.json files");
+    }
+
+    let only = split_list(query.collections.as_deref());
+    // views last, once the collections they read from exist
+    let (views, collections): (Vec<_>, Vec<_>) = grouped
+        .iter()
+        .filter(|((_, name), _)| only.as_ref().is_none_or(|o| o.contains(name)))
+        .partition(|(_, entry)| entry.is_view());
+
+    let mut restored = Vec::new();
+    for ((_, name), entry) in collections.into_iter().chain(views) {
+        match restore_collection(db, name, entry, query.drop.unwrap_or(false)).await {
+            Ok(report) => restored.push(report),
+            Err(e) => {
+                eprintln!("restore error: {}", e);
+                return HttpResponse::InternalServerError().json(json!({ "error": e, "restored": restored }));
+            }
+        }
+    }
+    HttpResponse::Ok().json(json!({ "database": db.name(), "collections": restored }))
+}
+
+/// Restores a tar (optionally gzipped as a whole) in the layout `dump_database` produces, or a
+/// tarred `mongodump` output directory, uploaded as the raw body or a multipart `file` part.
+/// Collections land in `db_name` whatever database they were dumped from, so an archive may only
+/// hold one database's directory. The upload is spooled to disk and unpacked entry by entry, so
+/// archives only need to fit `GLOBE_RESTORE_MAX_MB`.
+#[post("/restore/{db_name}")]
+pub async fn restore_database(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<RestoreQuery>,
+    mut payload: web::Payload,
+    data: web::Data<Client>,
+) -> actix_web::Result<HttpResponse> {
+    let db = data.database(&path.into_inner());
+    let dir = data_dir().join("restores").join(ObjectId::new().to_hex());
+    let response = restore_spooled(&req, &mut payload, &query, &db, &dir).await;
+    let _ = tokio::task::spawn_blocking(move || fs::remove_dir_all(dir)).await;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_archive, next_document, restore_target, unescape_name, EntryKind, TarReader, TarWriter};
+    use mongodb::bson::{doc, RawDocumentBuf};
+    use std::{fs, io::Read};
+
+    fn read_all(bytes: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut tar = TarReader::new(bytes);
+        let mut entries = Vec::new();
+        while let Some((path, _)) = tar.next_file().unwrap() {
+            let mut contents = Vec::new();
+            tar.read_to_end(&mut contents).unwrap();
+            entries.push((path, contents));
+        }
+        entries
+    }
+
+    #[test]
+    fn round_trips_tar_entries() {
+        let mut tar = TarWriter::new(Vec::new());
+        tar.append("shop/orders.bson", 3, &mut &b"abc"[..]).unwrap();
+        let long = format!("{}/{}.metadata.json", "d".repeat(80), "c".repeat(60));
+        tar.append(&long, 0, &mut &b""[..]).unwrap();
+        let bytes = tar.finish().unwrap();
+        assert_eq!(bytes.len() % 512, 0);
+        assert_eq!(read_all(&bytes), vec![("shop/orders.bson".to_string(), b"abc".to_vec()), (long, Vec::new())]);
+        assert!(TarReader::new(&bytes[..700]).next_file().is_ok_and(|f| f.is_some()));
+        let mut truncated = TarReader::new(&bytes[..514]);
+        truncated.next_file().unwrap();
+        assert!(truncated.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn splits_long_non_ascii_paths_at_a_slash() {
+        // byte 156 falls inside a two-byte 'é'
+        let path = format!("{}/{}.bson", "é".repeat(60), "é".repeat(40));
+        assert!(!path.is_char_boundary(156));
+        let mut tar = TarWriter::new(Vec::new());
+        tar.append(&path, 0, &mut &b""[..]).unwrap();
+        assert_eq!(read_all(&tar.finish().unwrap())[0].0, path);
+    }
+
+    #[test]
+    fn reads_bson_files_and_classifies_entries() {
+        let a = RawDocumentBuf::from_document(&doc! { "_id": 1 }).unwrap();
+        let b = RawDocumentBuf::from_document(&doc! { "_id": 2, "x": "y" }).unwrap();
+        let file = [a.as_bytes(), b.as_bytes()].concat();
+        let mut reader = &file[..];
+        assert_eq!(next_document(&mut reader).unwrap(), Some(a));
+        assert_eq!(next_document(&mut reader).unwrap(), Some(b));
+        assert_eq!(next_document(&mut reader).unwrap(), None);
+        assert!(next_document(&mut &file[..file.len() - 1]).is_ok());
+        assert!(next_document(&mut &file[4..file.len() - 1]).is_err());
+
+        let target = |dir: &str, name: &str, kind, gz| Some((dir.to_string(), name.to_string(), kind, gz));
+        assert_eq!(restore_target("dump/shop/orders.bson.gz"), target("dump/shop", "orders", EntryKind::Bson, true));
+        assert_eq!(
+            restore_target("dump/shop/orders.metadata.json.gz"),
+            target("dump/shop", "orders", EntryKind::Metadata, true)
+        );
+        assert_eq!(restore_target("dump/shop/a%2Fb.bson"), target("dump/shop", "a/b", EntryKind::Bson, false));
+        assert_eq!(restore_target("orders.bson"), target("", "orders", EntryKind::Bson, false));
+        assert_eq!(restore_target("dump/oplog.bson"), None);
+        assert_eq!(restore_target("dump/prelude.json"), None);
+        assert_eq!(unescape_name("x%2Ay"), "x*y");
+    }
+
+    #[test]
+    fn refuses_archives_of_several_databases() {
+        let dir = std::env::temp_dir().join(format!("globe-restore-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let upload = dir.join("upload");
+        let extract = |entries: &[&str]| {
+            let mut tar = TarWriter::new(Vec::new());
+            for path in entries {
+                tar.append(path, 0, &mut &b""[..]).unwrap();
+            }
+            fs::write(&upload, tar.finish().unwrap()).unwrap();
+            extract_archive(&upload, None, &dir, 1024)
+        };
+
+        let grouped = extract(&["dump/shop/orders.bson", "dump/shop/users.bson"]).unwrap();
+        let keys: Vec<_> = grouped.keys().map(|(db, name)| (db.as_str(), name.as_str())).collect();
+        assert_eq!(keys, vec![("dump/shop", "orders"), ("dump/shop", "users")]);
+        assert!(extract(&["dump/shop/orders.bson", "dump/crm/orders.bson"]).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,442 @@
+use actix_web::{post, web, web::Bytes, HttpResponse};
+use futures::stream::{self, Stream, StreamExt};
+use mongodb::{
+    bson::{self, Bson, Document},
+    Client,
+};
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+
+use crate::masking::{masker_for, Masker};
+use crate::schemas::SchemaStore;
+use crate::state::AppInfo;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Ndjson,
+    Json,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Json => "application/json",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+/// How an array lands in a CSV cell.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ArrayMode {
+    /// Scalars joined with `arraySeparator` (default `;`), nested values as JSON.
+    #[default]
+    Join,
+    /// Only the first element.
+    First,
+    /// The whole array as JSON.
+    Json,
+    /// One column per element (`tags.0`, `tags.1`, ...) when columns are derived.
+    Index,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CsvOptions {
+    /// Dotted paths, one column each; defaults to the leaf paths of the first document.
+    pub fields: Option<Vec<String>>,
+    pub arrays: Option<ArrayMode>,
+    pub array_separator: Option<String>,
+    pub delimiter: Option<char>,
+}
+
+/// Relaxed extended JSON, as written by `mongoexport`.
+pub fn to_json(doc: &Document) -> JsonValue {
+    Bson::Document(doc.clone()).into_relaxed_extjson()
+}
+
+/// Value at a dotted path. Numeric segments index arrays; other segments map over array
+/// elements the way MongoDB resolves `items.sku`.
+pub fn resolve_path(value: &Bson, segments: &[&str]) -> Option<Bson> {
+    let Some((head, rest)) = segments.split_first() else {
+        return Some(value.clone());
+    };
+    match value {
+        Bson::Document(d) => resolve_path(d.get(*head)?, rest),
+        Bson::Array(items) => match head.parse::<usize>() {
+            Ok(i) => resolve_path(items.get(i)?, rest),
+            Err(_) => {
+                let values: Vec<Bson> = items.iter().filter_map(|item| resolve_path(item, segments)).collect();
+                if values.is_empty() { None } else { Some(Bson::Array(values)) }
+            }
+        },
+        _ => None,
+    }
+}
+
+fn leaf_paths(doc: &Document, prefix: &str, arrays: ArrayMode, out: &mut Vec<String>) {
+    for (key, value) in doc {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        push_leaf(value, path, arrays, out);
+    }
+}
+
+fn push_leaf(value: &Bson, path: String, arrays: ArrayMode, out: &mut Vec<String>) {
+    match value {
+        Bson::Document(d) if !d.is_empty() => leaf_paths(d, &path, arrays, out),
+        Bson::Array(items) if arrays == ArrayMode::Index && !items.is_empty() => {
+            for (i, item) in items.iter().enumerate() {
+                push_leaf(item, format!("{}.{}", path, i), arrays, out);
+            }
+        }
+        _ => {
+            if !out.contains(&path) {
+                out.push(path);
+            }
+        }
+    }
+}
+
+fn scalar_cell(value: &Bson) -> String {
+    match value {
+        Bson::Null | Bson::Undefined => String::new(),
+        Bson::String(s) => s.clone(),
+        Bson::Boolean(b) => b.to_string(),
+        Bson::Int32(v) => v.to_string(),
+        Bson::Int64(v) => v.to_string(),
+        Bson::Double(v) => v.to_string(),
+        Bson::Decimal128(v) => v.to_string(),
+        Bson::ObjectId(oid) => oid.to_hex(),
+        Bson::DateTime(dt) => dt.try_to_rfc3339_string().unwrap_or_else(|_| dt.timestamp_millis().to_string()),
+        other => other.clone().into_relaxed_extjson().to_string(),
+    }
+}
+
+fn cell(value: &Bson, arrays: ArrayMode, separator: &str) -> String {
+    match value {
+        Bson::Array(items) => match arrays {
+            ArrayMode::First => items.first().map(|v| cell(v, arrays, separator)).unwrap_or_default(),
+            ArrayMode::Json => value.clone().into_relaxed_extjson().to_string(),
+            ArrayMode::Join | ArrayMode::Index => items
+                .iter()
+                .map(|v| match v {
+                    Bson::Document(_) | Bson::Array(_) => v.clone().into_relaxed_extjson().to_string(),
+                    _ => scalar_cell(v),
+                })
+                .collect::<Vec<_>>()
+                .join(separator),
+        },
+        _ => scalar_cell(value),
+    }
+}
+
+fn csv_row(cells: &[String], delimiter: char) -> String {
+    let mut row = cells
+        .iter()
+        .map(|c| {
+            if c.contains(delimiter) || c.contains('"') || c.contains('\n') || c.contains('\r') {
+                format!("\"{}\"", c.replace('"', "\"\""))
+            } else {
+                c.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(&delimiter.to_string());
+    row.push_str("\r\n");
+    row
+}
+
+/// Turns documents into export bytes one at a time; only the CSV columns are kept between calls.
+pub struct Encoder {
+    format: ExportFormat,
+    csv: CsvOptions,
+    columns: Option<Vec<String>>,
+    count: u64,
+    mask: Option<Masker>,
+}
+
+impl Encoder {
+    pub fn new(format: ExportFormat, csv: CsvOptions) -> Self {
+        Self {
+            format,
+            columns: csv.fields.clone(),
+            csv,
+            count: 0,
+            mask: None,
+        }
+    }
+
+    /// Masks every document before it is encoded.
+    pub fn masked(mut self, mask: Option<Masker>) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    pub fn start(&self) -> String {
+        match self.format {
+            ExportFormat::Json => "[".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn header(&self) -> String {
+        csv_row(self.columns.as_deref().unwrap_or_default(), self.csv.delimiter.unwrap_or(','))
+    }
+
+    pub fn encode(&mut self, doc: &Document) -> String {
+        let masked;
+        let doc = match &self.mask {
+            Some(mask) => {
+                let mut copy = doc.clone();
+                mask.apply(&mut copy);
+                masked = copy;
+                &masked
+            }
+            None => doc,
+        };
+        let first = self.count == 0;
+        self.count += 1;
+        match self.format {
+            ExportFormat::Ndjson => format!("{}\n", to_json(doc)),
+            ExportFormat::Json => format!("{}\n{}", if first { "" } else { "," }, to_json(doc)),
+            ExportFormat::Csv => {
+                let arrays = self.csv.arrays.unwrap_or_default();
+                let mut out = String::new();
+                if first {
+                    if self.columns.is_none() {
+                        let mut columns = Vec::new();
+                        leaf_paths(doc, "", arrays, &mut columns);
+                        self.columns = Some(columns);
+                    }
+                    out.push_str(&self.header());
+                }
+                let separator = self.csv.array_separator.as_deref().unwrap_or(";");
+                let root = Bson::Document(doc.clone());
+                let cells: Vec<String> = self
+                    .columns
+                    .as_deref()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|path| {
+                        let segments: Vec<&str> = path.split('.').collect();
+                        resolve_path(&root, &segments)
+                            .map(|v| cell(&v, arrays, separator))
+                            .unwrap_or_default()
+                    })
+                    .collect();
+                out.push_str(&csv_row(&cells, self.csv.delimiter.unwrap_or(',')));
+                out
+            }
+        }
+    }
+
+    pub fn finish(&self) -> String {
+        match self.format {
+            ExportFormat::Json if self.count == 0 => "]\n".to_string(),
+            ExportFormat::Json => "\n]\n".to_string(),
+            // an empty result still gets its header when the columns were given
+            ExportFormat::Csv if self.count == 0 && self.columns.is_some() => self.header(),
+            _ => String::new(),
+        }
+    }
+}
+
+enum Stage {
+    Start,
+    Body,
+    Done,
+}
+
+/// Encodes a document stream lazily, so memory stays flat however many documents the cursor yields.
+pub fn encode_stream<S>(docs: S, encoder: Encoder) -> impl Stream<Item = mongodb::error::Result<Bytes>>
+where
+    S: Stream<Item = mongodb::error::Result<Document>> + Unpin,
+{
+    stream::unfold((docs, encoder, Stage::Start), |(mut docs, mut encoder, stage)| async move {
+        match stage {
+            Stage::Start => {
+                let start = Bytes::from(encoder.start());
+                Some((Ok(start), (docs, encoder, Stage::Body)))
+            }
+            Stage::Body => match docs.next().await {
+                Some(Ok(doc)) => {
+                    let chunk = Bytes::from(encoder.encode(&doc));
+                    Some((Ok(chunk), (docs, encoder, Stage::Body)))
+                }
+                Some(Err(e)) => Some((Err(e), (docs, encoder, Stage::Done))),
+                None => {
+                    let end = Bytes::from(encoder.finish());
+                    Some((Ok(end), (docs, encoder, Stage::Done)))
+                }
+            },
+            Stage::Done => None,
+        }
+    })
+}
+
+/// Source of an export: a find (filter/projection/sort/limit) or an aggregation pipeline.
+#[derive(Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportQuery {
+    pub filter: Option<JsonValue>,
+    pub projection: Option<JsonValue>,
+    pub sort: Option<JsonValue>,
+    pub limit: Option<i64>,
+    pub pipeline: Option<Vec<JsonValue>>,
+}
+
+pub fn json_document(value: Option<&JsonValue>, what: &str) -> Result<Option<Document>, String> {
+    match value.map(bson::to_bson) {
+        None => Ok(None),
+        Some(Ok(Bson::Document(d))) => Ok(Some(d)),
+        Some(Ok(_)) => Err(format!("{} must be an object", what)),
+        Some(Err(e)) => Err(format!("invalid {}: {}", what, e)),
+    }
+}
+
+/// Number of documents a find export will produce, for progress reporting; `None` for pipelines.
+pub async fn estimate(client: &Client, db_name: &str, coll_name: &str, query: &ExportQuery) -> Option<u64> {
+    if query.pipeline.is_some() {
+        return None;
+    }
+    let filter = json_document(query.filter.as_ref(), "filter").ok()?.unwrap_or_default();
+    let coll = client.database(db_name).collection::<Document>(coll_name);
+    let count = coll.count_documents(filter).await.ok()?;
+    Some(query.limit.filter(|l| *l > 0).map_or(count, |l| count.min(l as u64)))
+}
+
+/// Opens the cursor an export reads from.
+pub async fn open_cursor(
+    client: &Client,
+    db_name: &str,
+    coll_name: &str,
+    query: &ExportQuery,
+) -> Result<mongodb::Cursor<Document>, String> {
+    let coll = client.database(db_name).collection::<Document>(coll_name);
+    if let Some(stages) = &query.pipeline {
+        let mut pipeline = Vec::new();
+        for stage in stages {
+            if let Some(d) = json_document(Some(stage), "pipeline stage")? {
+                pipeline.push(d);
+            }
+        }
+        return coll.aggregate(pipeline).allow_disk_use(true).await.map_err(|e| e.to_string());
+    }
+    let filter = json_document(query.filter.as_ref(), "filter")?.unwrap_or_default();
+    let projection = json_document(query.projection.as_ref(), "projection")?;
+    let sort = json_document(query.sort.as_ref(), "sort")?;
+    let mut find = coll.find(filter);
+    if let Some(p) = projection {
+        find = find.projection(p);
+    }
+    if let Some(s) = sort {
+        find = find.sort(s);
+    }
+    if let Some(l) = query.limit {
+        find = find.limit(l);
+    }
+    find.await.map_err(|e| e.to_string())
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportRequest {
+    #[serde(flatten)]
+    pub query: ExportQuery,
+    pub format: Option<ExportFormat>,
+    #[serde(flatten)]
+    pub csv: CsvOptions,
+    // apply the collection's saved masking rules
+    pub mask: Option<bool>,
+}
+
+/// Streams a find or pipeline result as a chunked download in NDJSON, a JSON array or CSV.
+#[post("/export/{db_name}/{coll_name}")]
+pub async fn export_documents(
+    path: web::Path<(String, String)>,
+    body: web::Json<ExportRequest>,
+    data: web::Data<Client>,
+    schemas: web::Data<SchemaStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let req = body.into_inner();
+    let format = req.format.unwrap_or_default();
+    let mask = match masker_for(&schemas, &app_info.shortened_uri, &db_name, &coll_name, req.mask).await {
+        Ok(m) => m,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+
+    let cursor = match open_cursor(&data, &db_name, &coll_name, &req.query).await {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("export error: {}", e);
+            return Ok(HttpResponse::BadRequest().body(format!("export failed: {}", e)));
+        }
+    };
+    // Headers are already sent when a cursor error surfaces mid-stream, so it ends the response early.
+    let body = encode_stream(cursor, Encoder::new(format, req.csv).masked(mask)).map(|chunk| {
+        chunk.map_err(|e| {
+            eprintln!("export stream error: {}", e);
+            actix_web::error::ErrorInternalServerError(e)
+        })
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}.{}\"", coll_name, format.extension()),
+        ))
+        .streaming(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_path, ArrayMode, CsvOptions, Encoder, ExportFormat};
+    use mongodb::bson::{doc, Bson};
+
+    #[test]
+    fn resolves_dotted_paths_through_arrays() {
+        let d = Bson::Document(doc! { "items": [ { "sku": "a" }, { "sku": "b" } ], "tags": ["x", "y"] });
+        assert_eq!(resolve_path(&d, &["items", "sku"]), Some(Bson::Array(vec!["a".into(), "b".into()])));
+        assert_eq!(resolve_path(&d, &["tags", "1"]), Some(Bson::String("y".into())));
+        assert_eq!(resolve_path(&d, &["missing"]), None);
+    }
+
+    #[test]
+    fn encodes_csv_with_derived_columns() {
+        let mut enc = Encoder::new(ExportFormat::Csv, CsvOptions::default());
+        let first = enc.encode(&doc! { "name": "a, b", "address": { "city": "Oslo" }, "tags": ["x", "y"] });
+        assert_eq!(first, "name,address.city,tags\r\n\"a, b\",Oslo,x;y\r\n");
+        assert_eq!(enc.encode(&doc! { "name": "c" }), "c,,\r\n");
+
+        let indexed = CsvOptions { arrays: Some(ArrayMode::Index), ..Default::default() };
+        let mut enc = Encoder::new(ExportFormat::Csv, indexed);
+        assert_eq!(enc.encode(&doc! { "tags": ["x", "y"] }), "tags.0,tags.1\r\nx,y\r\n");
+    }
+
+    #[test]
+    fn wraps_json_arrays() {
+        let mut enc = Encoder::new(ExportFormat::Json, CsvOptions::default());
+        let mut out = enc.start();
+        out.push_str(&enc.encode(&doc! { "a": 1 }));
+        out.push_str(&enc.encode(&doc! { "a": 2 }));
+        out.push_str(&enc.finish());
+        let parsed: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(parsed, serde_json::json!([{ "a": 1 }, { "a": 2 }]));
+    }
+}
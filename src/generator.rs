@@ -0,0 +1,621 @@
+//! Fake documents for load testing, shaped like a collection's stored schema or its sampled data.
+
+use actix_web::{post, web, HttpResponse};
+use chrono::{TimeZone, Utc};
+use mongodb::{
+    bson::{oid::ObjectId, Bson, DateTime, Document},
+    Client,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+
+use crate::export::to_json;
+use crate::sampling::{sample_documents, DEFAULT_SAMPLE_SIZE};
+use crate::schemas::SchemaStore;
+use crate::state::AppInfo;
+
+// Distinct strings kept per path; beyond this a field counts as free text.
+const DISTINCT_LIMIT: usize = 50;
+// Presence of fields an uploaded schema neither requires nor gives a `presence` for.
+const DEFAULT_PRESENCE: f64 = 0.5;
+const MAX_PREVIEW: usize = 50;
+const LETTERS: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum StringFormat {
+    Email,
+    DateTime,
+    Uuid,
+}
+
+impl StringFormat {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "email" => Some(StringFormat::Email),
+            "date" | "date-time" => Some(StringFormat::DateTime),
+            "uuid" => Some(StringFormat::Uuid),
+            _ => None,
+        }
+    }
+
+    fn detect(s: &str) -> Option<Self> {
+        if let Some((user, domain)) = s.split_once('@')
+            && !user.is_empty()
+            && domain.contains('.')
+            && !s.contains(' ')
+        {
+            return Some(StringFormat::Email);
+        }
+        if chrono::DateTime::parse_from_rfc3339(s).is_ok() {
+            return Some(StringFormat::DateTime);
+        }
+        let bytes = s.as_bytes();
+        if bytes.len() == 36
+            && bytes.iter().enumerate().all(|(i, b)| match i {
+                8 | 13 | 18 | 23 => *b == b'-',
+                _ => b.is_ascii_hexdigit(),
+            })
+        {
+            return Some(StringFormat::Uuid);
+        }
+        None
+    }
+}
+
+/// How one value is produced. Numeric and date variants hold sorted observations (or just
+/// `[min, max]` from a schema) and draw between two neighbours, which follows the observed
+/// distribution without repeating it exactly.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Generator {
+    Null,
+    Bool { true_rate: f64 },
+    Int { values: Vec<f64>, long: bool },
+    Double { values: Vec<f64> },
+    // milliseconds since the epoch
+    Date { values: Vec<f64> },
+    ObjectId,
+    // weighted values
+    Choice { values: Vec<(u64, Bson)> },
+    Text {
+        format: Option<StringFormat>,
+        min_len: usize,
+        max_len: usize,
+        words: bool,
+        // observed dates for date-time strings
+        dates: Vec<f64>,
+    },
+    Object { fields: Vec<(String, Field)> },
+    Array { items: Box<Field>, min_len: usize, max_len: usize },
+}
+
+/// A field: how often it is present, and weighted alternatives for its value.
+#[derive(Serialize, Clone, Debug)]
+pub struct Field {
+    pub presence: f64,
+    pub variants: Vec<(u64, Generator)>,
+}
+
+fn draw_between(values: &[f64], rng: &mut StdRng) -> f64 {
+    match values.len() {
+        0 => 0.0,
+        1 => values[0],
+        n => {
+            let i = rng.random_range(0..n - 1);
+            values[i] + (values[i + 1] - values[i]) * rng.random::<f64>()
+        }
+    }
+}
+
+fn pick<'a, T>(weighted: &'a [(u64, T)], rng: &mut StdRng) -> Option<&'a T> {
+    let total: u64 = weighted.iter().map(|(w, _)| *w).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut roll = rng.random_range(0..total);
+    for (weight, item) in weighted {
+        if roll < *weight {
+            return Some(item);
+        }
+        roll -= weight;
+    }
+    None
+}
+
+fn word(len: usize, rng: &mut StdRng) -> String {
+    (0..len).map(|_| LETTERS[rng.random_range(0..LETTERS.len())] as char).collect()
+}
+
+fn random_date(dates: &[f64], rng: &mut StdRng) -> DateTime {
+    DateTime::from_millis(draw_between(dates, rng) as i64)
+}
+
+impl Generator {
+    fn generate(&self, rng: &mut StdRng) -> Bson {
+        match self {
+            Generator::Null => Bson::Null,
+            Generator::Bool { true_rate } => Bson::Boolean(rng.random_bool(true_rate.clamp(0.0, 1.0))),
+            Generator::Int { values, long: true } => Bson::Int64(draw_between(values, rng).round() as i64),
+            Generator::Int { values, long: false } => Bson::Int32(draw_between(values, rng).round() as i32),
+            Generator::Double { values } => Bson::Double(draw_between(values, rng)),
+            Generator::Date { values } => Bson::DateTime(random_date(values, rng)),
+            Generator::ObjectId => Bson::ObjectId(ObjectId::from_bytes(rng.random())),
+            Generator::Choice { values } => pick(values, rng).cloned().unwrap_or(Bson::Null),
+            Generator::Text { format: Some(StringFormat::Email), .. } => {
+                Bson::String(format!("{}.{}@example.com", word(rng.random_range(3..9), rng), word(rng.random_range(3..9), rng)))
+            }
+            Generator::Text { format: Some(StringFormat::DateTime), dates, .. } => {
+                Bson::String(random_date(dates, rng).try_to_rfc3339_string().unwrap_or_default())
+            }
+            Generator::Text { format: Some(StringFormat::Uuid), .. } => {
+                let mut bytes: [u8; 16] = rng.random();
+                bytes[6] = (bytes[6] & 0x0f) | 0x40;
+                bytes[8] = (bytes[8] & 0x3f) | 0x80;
+                let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                Bson::String(format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..]))
+            }
+            Generator::Text { min_len, max_len, words, .. } => {
+                let len = rng.random_range(*min_len..=(*max_len).max(*min_len));
+                let mut text = String::with_capacity(len);
+                while text.len() < len {
+                    if *words && !text.is_empty() {
+                        text.push(' ');
+                    }
+                    let next = if *words { rng.random_range(2..=9) } else { len };
+                    text.push_str(&word(next, rng));
+                }
+                text.truncate(len);
+                if text.ends_with(' ') {
+                    text.pop();
+                    text.push_str(&word(1, rng));
+                }
+                Bson::String(text)
+            }
+            Generator::Object { fields } => Bson::Document(generate_fields(fields, rng)),
+            Generator::Array { items, min_len, max_len } => {
+                let len = rng.random_range(*min_len..=(*max_len).max(*min_len));
+                Bson::Array((0..len).filter_map(|_| items.value(rng)).collect())
+            }
+        }
+    }
+}
+
+fn generate_fields(fields: &[(String, Field)], rng: &mut StdRng) -> Document {
+    let mut doc = Document::new();
+    for (name, field) in fields {
+        if rng.random::<f64>() < field.presence
+            && let Some(value) = field.value(rng)
+        {
+            doc.insert(name.clone(), value);
+        }
+    }
+    doc
+}
+
+impl Field {
+    fn value(&self, rng: &mut StdRng) -> Option<Bson> {
+        pick(&self.variants, rng).map(|g| g.generate(rng))
+    }
+
+    /// Generates one root document.
+    pub fn document(&self, rng: &mut StdRng) -> Document {
+        match self.variants.first() {
+            Some((_, Generator::Object { fields })) => generate_fields(fields, rng),
+            _ => Document::new(),
+        }
+    }
+}
+
+/// Everything seen at one path of the sampled documents.
+#[derive(Default)]
+struct Observed {
+    present: u64,
+    nulls: u64,
+    bools: u64,
+    trues: u64,
+    ints: Vec<f64>,
+    longs: Vec<f64>,
+    doubles: Vec<f64>,
+    dates: Vec<f64>,
+    object_ids: u64,
+    strings: Vec<String>,
+    objects: u64,
+    properties: Vec<(String, Observed)>,
+    arrays: u64,
+    array_lengths: Vec<usize>,
+    items: Option<Box<Observed>>,
+}
+
+fn sorted(mut values: Vec<f64>) -> Vec<f64> {
+    values.sort_by(|a, b| a.total_cmp(b));
+    values
+}
+
+impl Observed {
+    fn observe_document(&mut self, doc: &Document) {
+        self.objects += 1;
+        for (key, value) in doc {
+            let index = match self.properties.iter().position(|(k, _)| k == key) {
+                Some(i) => i,
+                None => {
+                    self.properties.push((key.clone(), Observed::default()));
+                    self.properties.len() - 1
+                }
+            };
+            let child = &mut self.properties[index].1;
+            child.present += 1;
+            child.observe(value);
+        }
+    }
+
+    fn observe(&mut self, value: &Bson) {
+        match value {
+            Bson::Null => self.nulls += 1,
+            Bson::Boolean(b) => {
+                self.bools += 1;
+                self.trues += *b as u64;
+            }
+            Bson::Int32(n) => self.ints.push(*n as f64),
+            Bson::Int64(n) => self.longs.push(*n as f64),
+            Bson::Double(n) => self.doubles.push(*n),
+            Bson::Decimal128(d) => self.doubles.extend(d.to_string().parse::<f64>().ok()),
+            Bson::DateTime(d) => self.dates.push(d.timestamp_millis() as f64),
+            Bson::ObjectId(_) => self.object_ids += 1,
+            Bson::String(s) => self.strings.push(s.clone()),
+            Bson::Document(d) => self.observe_document(d),
+            Bson::Array(items) => {
+                self.arrays += 1;
+                self.array_lengths.push(items.len());
+                let observed = self.items.get_or_insert_with(Box::default);
+                for item in items {
+                    observed.present += 1;
+                    observed.observe(item);
+                }
+            }
+            // types with no sensible fake (regex, code, ...) are left out
+            _ => {}
+        }
+    }
+
+    fn strings(&self) -> Option<Generator> {
+        if self.strings.is_empty() {
+            return None;
+        }
+        let mut counts: Vec<(u64, Bson)> = Vec::new();
+        for s in &self.strings {
+            match counts.iter_mut().find(|(_, v)| v.as_str() == Some(s)) {
+                Some((c, _)) => *c += 1,
+                None => counts.push((1, Bson::String(s.clone()))),
+            }
+            if counts.len() > DISTINCT_LIMIT {
+                break;
+            }
+        }
+        // same rule as schema inference: a category only if values repeat on average
+        if counts.len() <= DISTINCT_LIMIT && self.strings.len() >= 2 * counts.len() {
+            counts.sort_by_key(|c| std::cmp::Reverse(c.0));
+            return Some(Generator::Choice { values: counts });
+        }
+        let first = StringFormat::detect(&self.strings[0]);
+        let format = first.filter(|f| self.strings.iter().all(|s| StringFormat::detect(s) == Some(*f)));
+        let dates = match format {
+            Some(StringFormat::DateTime) => sorted(
+                self.strings
+                    .iter()
+                    .filter_map(|s| chrono::DateTime::parse_from_rfc3339(s).ok())
+                    .map(|d| d.timestamp_millis() as f64)
+                    .collect(),
+            ),
+            _ => Vec::new(),
+        };
+        Some(Generator::Text {
+            format,
+            min_len: self.strings.iter().map(|s| s.len()).min().unwrap_or(0),
+            max_len: self.strings.iter().map(|s| s.len()).max().unwrap_or(0),
+            words: self.strings.iter().any(|s| s.contains(' ')),
+            dates,
+        })
+    }
+
+    fn to_field(&self, parents: u64) -> Field {
+        let mut variants = Vec::new();
+        let mut push = |count: u64, generator: Generator| {
+            if count > 0 {
+                variants.push((count, generator));
+            }
+        };
+        push(self.nulls, Generator::Null);
+        push(self.bools, Generator::Bool { true_rate: self.trues as f64 / self.bools.max(1) as f64 });
+        push(self.ints.len() as u64, Generator::Int { values: sorted(self.ints.clone()), long: false });
+        push(self.longs.len() as u64, Generator::Int { values: sorted(self.longs.clone()), long: true });
+        push(self.doubles.len() as u64, Generator::Double { values: sorted(self.doubles.clone()) });
+        push(self.dates.len() as u64, Generator::Date { values: sorted(self.dates.clone()) });
+        push(self.object_ids, Generator::ObjectId);
+        if let Some(strings) = self.strings() {
+            push(self.strings.len() as u64, strings);
+        }
+        if self.objects > 0 {
+            let fields = self.properties.iter().map(|(k, o)| (k.clone(), o.to_field(self.objects))).collect();
+            push(self.objects, Generator::Object { fields });
+        }
+        if self.arrays > 0 {
+            let items = match &self.items {
+                Some(items) => items.to_field(items.present),
+                None => Field { presence: 1.0, variants: Vec::new() },
+            };
+            push(
+                self.arrays,
+                Generator::Array {
+                    items: Box::new(items),
+                    min_len: self.array_lengths.iter().copied().min().unwrap_or(0),
+                    max_len: self.array_lengths.iter().copied().max().unwrap_or(0),
+                },
+            );
+        }
+        Field {
+            presence: if parents == 0 { 0.0 } else { self.present as f64 / parents as f64 },
+            variants,
+        }
+    }
+}
+
+/// Model of sampled documents: field presence rates, type mix, value distributions.
+pub fn model_from_sample(docs: &[Document]) -> Field {
+    let mut root = Observed::default();
+    for doc in docs {
+        root.present += 1;
+        root.observe_document(doc);
+    }
+    without_id(root.to_field(docs.len() as u64))
+}
+
+/// Drops top-level `_id` so the server assigns one: values drawn from a sample or a schema's
+/// range would collide with existing documents and with each other.
+fn without_id(mut model: Field) -> Field {
+    for (_, generator) in &mut model.variants {
+        if let Generator::Object { fields } = generator {
+            fields.retain(|(name, _)| name != "_id");
+        }
+    }
+    model
+}
+
+fn number(spec: &JsonValue, key: &str) -> Option<f64> {
+    spec.get(key).and_then(|v| v.as_f64())
+}
+
+fn default_dates() -> Vec<f64> {
+    let from = Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap().timestamp_millis() as f64;
+    let to = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap().timestamp_millis() as f64;
+    vec![from, to]
+}
+
+fn schema_generator(type_name: &str, spec: &JsonValue) -> Option<Generator> {
+    let range = |default_min: f64, default_max: f64| {
+        let min = number(spec, "minimum").unwrap_or(default_min);
+        vec![min, number(spec, "maximum").unwrap_or(min.max(default_max))]
+    };
+    let generator = match type_name {
+        "null" => Generator::Null,
+        "bool" | "boolean" => Generator::Bool { true_rate: 0.5 },
+        "int" | "integer" => Generator::Int { values: range(0.0, 1000.0), long: false },
+        "long" => Generator::Int { values: range(0.0, 1_000_000.0), long: true },
+        "number" | "double" | "decimal" => Generator::Double { values: range(0.0, 1000.0) },
+        "date" => Generator::Date { values: default_dates() },
+        "objectId" | "objectid" => Generator::ObjectId,
+        "string" => {
+            let min_len = number(spec, "minLength").unwrap_or(5.0) as usize;
+            Generator::Text {
+                format: spec.get("format").and_then(|f| f.as_str()).and_then(StringFormat::parse),
+                min_len,
+                max_len: (number(spec, "maxLength").unwrap_or(20.0) as usize).max(min_len),
+                words: false,
+                dates: default_dates(),
+            }
+        }
+        "object" => {
+            let required: Vec<&str> = spec
+                .get("required")
+                .and_then(|r| r.as_array())
+                .map(|names| names.iter().filter_map(|n| n.as_str()).collect())
+                .unwrap_or_default();
+            let fields = spec
+                .get("properties")
+                .and_then(|p| p.as_object())
+                .map(|props| {
+                    props
+                        .iter()
+                        .map(|(name, prop)| (name.clone(), model_from_schema(prop, required.contains(&name.as_str()))))
+                        .collect()
+                })
+                .unwrap_or_default();
+            Generator::Object { fields }
+        }
+        "array" => {
+            let items = match spec.get("items") {
+                Some(items @ JsonValue::Object(_)) => model_from_schema(items, true),
+                _ => model_from_schema(&json!({ "type": "string" }), true),
+            };
+            let min_len = number(spec, "minItems").unwrap_or(0.0) as usize;
+            Generator::Array {
+                items: Box::new(items),
+                min_len,
+                max_len: (number(spec, "maxItems").unwrap_or(5.0) as usize).max(min_len),
+            }
+        }
+        _ => return None,
+    };
+    Some(generator)
+}
+
+/// Model of a stored schema, in the upload format (`type`, per-property `required: true`) or
+/// as `$jsonSchema` (`bsonType`, `required` arrays). Optional fields are present at the rate
+/// given by a `presence` keyword (0-1), or half the time.
+pub fn model_from_schema(spec: &JsonValue, required: bool) -> Field {
+    let presence = if required || spec.get("required") == Some(&JsonValue::Bool(true)) {
+        1.0
+    } else {
+        number(spec, "presence").unwrap_or(DEFAULT_PRESENCE).clamp(0.0, 1.0)
+    };
+    if let Some(values) = spec.get("enum").and_then(|e| e.as_array()) {
+        let values = values.iter().filter_map(|v| Bson::try_from(v.clone()).ok()).map(|v| (1, v)).collect();
+        return Field {
+            presence,
+            variants: vec![(1, Generator::Choice { values })],
+        };
+    }
+    let declared = spec.get("bsonType").or_else(|| spec.get("type"));
+    let mut names: Vec<&str> = match declared {
+        Some(JsonValue::String(s)) => vec![s.as_str()],
+        Some(JsonValue::Array(items)) => items.iter().filter_map(|v| v.as_str()).collect(),
+        _ => Vec::new(),
+    };
+    if names.is_empty() {
+        names.push(if spec.get("properties").is_some() {
+            "object"
+        } else if spec.get("items").is_some() {
+            "array"
+        } else {
+            "string"
+        });
+    }
+    Field {
+        presence,
+        variants: names.iter().filter_map(|t| schema_generator(t, spec)).map(|g| (1, g)).collect(),
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateRequest {
+    pub count: Option<u64>,
+    // random when missing; reported back so a run can be repeated
+    pub seed: Option<u64>,
+    // "schema" or "inferred"; defaults to the stored schema when there is one
+    pub source: Option<String>,
+    pub sample: Option<i64>,
+    pub batch_size: Option<usize>,
+    // insert into another collection of the same database
+    pub into: Option<String>,
+}
+
+impl GenerateRequest {
+    pub fn seed(&self) -> u64 {
+        self.seed.unwrap_or_else(rand::random)
+    }
+}
+
+/// Builds the model a generation run uses; returns which source it came from.
+pub async fn build_model(
+    req: &GenerateRequest,
+    client: &Client,
+    schemas: &SchemaStore,
+    app_info: &AppInfo,
+    db_name: &str,
+    coll_name: &str,
+) -> Result<(&'static str, Field), String> {
+    let stored = schemas.latest(&app_info.shortened_uri, db_name, coll_name).await;
+    match (req.source.as_deref(), stored) {
+        (Some("schema") | None, Some(stored)) => Ok(("schema", without_id(model_from_schema(&stored.schema, true)))),
+        (Some("schema"), None) => Err(format!("no schema stored for {}.{}", db_name, coll_name)),
+        (Some("inferred") | None, _) => {
+            let coll = client.database(db_name).collection::<Document>(coll_name);
+            let docs = sample_documents(&coll, req.sample.unwrap_or(DEFAULT_SAMPLE_SIZE))
+                .await
+                .map_err(|e| format!("sampling failed: {}", e))?;
+            if docs.is_empty() {
+                return Err(format!("{}.{} is empty and has no stored schema", db_name, coll_name));
+            }
+            Ok(("inferred", model_from_sample(&docs)))
+        }
+        (Some(other), _) => Err(format!("source must be schema or inferred, got {}", other)),
+    }
+}
+
+pub fn seeded(seed: u64) -> StdRng {
+    StdRng::seed_from_u64(seed)
+}
+
+/// A few generated documents and the model behind them, without inserting anything.
+#[post("/generate/{db_name}/{coll_name}/preview")]
+pub async fn preview_generated(
+    path: web::Path<(String, String)>,
+    body: Option<web::Json<GenerateRequest>>,
+    data: web::Data<Client>,
+    schemas: web::Data<SchemaStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let req = body.map(|b| b.into_inner()).unwrap_or_default();
+    let (source, model) = match build_model(&req, &data, &schemas, &app_info, &db_name, &coll_name).await {
+        Ok(m) => m,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let seed = req.seed();
+    let mut rng = seeded(seed);
+    let count = req.count.unwrap_or(5).min(MAX_PREVIEW as u64);
+    let documents: Vec<JsonValue> = (0..count).map(|_| to_json(&model.document(&mut rng))).collect();
+    Ok(HttpResponse::Ok().json(json!({
+        "seed": seed,
+        "source": source,
+        "model": model,
+        "documents": documents,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::doc;
+
+    #[test]
+    fn reproduces_presence_and_categories_from_samples() {
+        let docs: Vec<Document> = (0..100)
+            .map(|i| {
+                let mut d = doc! { "_id": i % 10, "status": if i % 4 == 0 { "inactive" } else { "active" }, "age": 20 + i % 30 };
+                if i % 2 == 0 {
+                    d.insert("email", format!("user{}@corp.com", i));
+                }
+                d
+            })
+            .collect();
+        let model = model_from_sample(&docs);
+        let mut rng = seeded(7);
+        let generated: Vec<Document> = (0..2000).map(|_| model.document(&mut rng)).collect();
+
+        let with_email = generated.iter().filter(|d| d.contains_key("email")).count();
+        assert!((800..1200).contains(&with_email), "{}", with_email);
+        assert!(generated.iter().filter_map(|d| d.get_str("email").ok()).all(|e| e.ends_with("@example.com")));
+        let inactive = generated.iter().filter(|d| d.get_str("status") == Ok("inactive")).count();
+        assert!((350..650).contains(&inactive), "{}", inactive);
+        assert!(generated.iter().all(|d| (20..=49).contains(&d.get_i32("age").unwrap())));
+        // left to the server, which assigns unique ObjectIds
+        assert!(generated.iter().all(|d| !d.contains_key("_id")));
+
+        // same seed, same documents
+        let mut again = seeded(7);
+        assert_eq!(model.document(&mut again), generated[0]);
+    }
+
+    #[test]
+    fn honours_schema_ranges_enums_and_required() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "required": true, "minLength": 3, "maxLength": 8 },
+                "age": { "type": "integer", "minimum": 18, "maximum": 65, "required": true },
+                "status": { "type": "string", "enum": ["a", "b"], "presence": 0.0 },
+            }
+        });
+        let model = model_from_schema(&schema, true);
+        let mut rng = seeded(1);
+        for _ in 0..200 {
+            let d = model.document(&mut rng);
+            assert!((3..=8).contains(&d.get_str("name").unwrap().len()));
+            assert!((18..=65).contains(&d.get_i32("age").unwrap()));
+            assert!(!d.contains_key("status"));
+        }
+    }
+}
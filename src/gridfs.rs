@@ -0,0 +1,371 @@
+use actix_web::{delete, get, post, web, web::Bytes, HttpRequest, HttpResponse};
+use futures::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    stream::{self, StreamExt, TryStreamExt},
+};
+use mongodb::{
+    action::Action,
+    bson::{doc, oid::ObjectId, Bson, Document},
+    error::{ErrorKind, GridFsErrorKind},
+    gridfs::GridFsBucket,
+    options::GridFsBucketOptions,
+    Client, Database,
+};
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+
+use crate::export::to_json;
+use crate::import::{boundary, content_type, parse_multipart, read_upload};
+
+const DOWNLOAD_CHUNK: usize = 64 * 1024;
+const DEFAULT_LIST_LIMIT: u64 = 50;
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Bucket prefixes in a database: every `<prefix>.files` collection that has a
+/// `<prefix>.chunks` companion.
+pub fn bucket_names(collections: &[String]) -> Vec<String> {
+    let mut buckets: Vec<String> = collections
+        .iter()
+        .filter_map(|name| name.strip_suffix(".files"))
+        .filter(|prefix| collections.iter().any(|c| c.strip_suffix(".chunks") == Some(*prefix)))
+        .map(str::to_string)
+        .collect();
+    buckets.sort();
+    buckets
+}
+
+/// Content type recorded for a file: `metadata.contentType` as current drivers suggest, or the
+/// deprecated top-level `contentType` older drivers wrote.
+pub fn file_content_type(file: &Document) -> String {
+    file.get_document("metadata")
+        .ok()
+        .and_then(|m| m.get_str("contentType").ok())
+        .or_else(|| file.get_str("contentType").ok())
+        .filter(|ct| !ct.trim().is_empty())
+        .unwrap_or(DEFAULT_CONTENT_TYPE)
+        .to_string()
+}
+
+/// Filename safe to put inside a quoted `Content-Disposition` parameter.
+pub fn attachment_name(filename: &str) -> String {
+    let name: String = filename
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if c == '"' || c.is_control() { '_' } else { c })
+        .collect();
+    if name.is_empty() { "download".to_string() } else { name }
+}
+
+fn parse_id(id_str: &str) -> Bson {
+    match ObjectId::parse_str(id_str) {
+        Ok(oid) => Bson::ObjectId(oid),
+        Err(_) => Bson::String(id_str.to_string()),
+    }
+}
+
+fn bucket(db: &Database, name: &str) -> GridFsBucket {
+    db.gridfs_bucket(GridFsBucketOptions::builder().bucket_name(name.to_string()).build())
+}
+
+fn not_found(e: &mongodb::error::Error) -> bool {
+    matches!(*e.kind, ErrorKind::GridFs(GridFsErrorKind::FileNotFound { .. }))
+}
+
+/// Files collection document, read directly so legacy fields (`contentType`, `md5`,
+/// `aliases`) written by older drivers are kept.
+async fn file_document(db: &Database, bucket_name: &str, id: &Bson) -> mongodb::error::Result<Option<Document>> {
+    db.collection::<Document>(&format!("{}.files", bucket_name))
+        .find_one(doc! { "_id": id.clone() })
+        .await
+}
+
+fn file_json(file: &Document) -> JsonValue {
+    let mut value = to_json(file);
+    value["contentType"] = json!(file_content_type(file));
+    value
+}
+
+#[get("/gridfs/{db_name}")]
+pub async fn list_buckets(path: web::Path<String>, data: web::Data<Client>) -> actix_web::Result<HttpResponse> {
+    let db_name = path.into_inner();
+    let db = data.database(&db_name);
+    let names = match db.list_collection_names().await {
+        Ok(names) => names,
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("failed to list collections: {}", e))),
+    };
+
+    let mut buckets = Vec::new();
+    for name in bucket_names(&names) {
+        let files = db.collection::<Document>(&format!("{}.files", name)).estimated_document_count().await;
+        let chunks = db.collection::<Document>(&format!("{}.chunks", name)).estimated_document_count().await;
+        match (files, chunks) {
+            (Ok(files), Ok(chunks)) => buckets.push(json!({ "bucket": name, "files": files, "chunks": chunks })),
+            (Err(e), _) | (_, Err(e)) => {
+                return Ok(HttpResponse::InternalServerError().body(format!("failed to count bucket {}: {}", name, e)));
+            }
+        }
+    }
+    Ok(HttpResponse::Ok().json(buckets))
+}
+
+#[derive(Deserialize)]
+pub struct ListFilesQuery {
+    // prefix match on the filename
+    filename: Option<String>,
+    skip: Option<u64>,
+    limit: Option<u64>,
+}
+
+/// Files in a bucket, newest upload first.
+#[get("/gridfs/{db_name}/{bucket}/files")]
+pub async fn list_files(
+    path: web::Path<(String, String)>,
+    query: web::Query<ListFilesQuery>,
+    data: web::Data<Client>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, bucket_name) = path.into_inner();
+    let files = data.database(&db_name).collection::<Document>(&format!("{}.files", bucket_name));
+    let filter = match query.filename.as_deref().map(str::trim).filter(|f| !f.is_empty()) {
+        Some(prefix) => doc! { "filename": { "$regex": format!("^{}", regex::escape(prefix)) } },
+        None => doc! {},
+    };
+
+    let total = match files.count_documents(filter.clone()).await {
+        Ok(n) => n,
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("failed to count files: {}", e))),
+    };
+    let cursor = files
+        .find(filter)
+        .sort(doc! { "uploadDate": -1, "_id": -1 })
+        .skip(query.skip.unwrap_or(0))
+        .limit(query.limit.unwrap_or(DEFAULT_LIST_LIMIT) as i64)
+        .await;
+    let found: Vec<Document> = match cursor {
+        Ok(c) => match c.try_collect().await {
+            Ok(docs) => docs,
+            Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("failed to read files: {}", e))),
+        },
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("failed to list files: {}", e))),
+    };
+    Ok(HttpResponse::Ok().json(json!({
+        "files": found.iter().map(file_json).collect::<Vec<_>>(),
+        "total": total,
+    })))
+}
+
+#[get("/gridfs/{db_name}/{bucket}/files/{id}")]
+pub async fn get_file(path: web::Path<(String, String, String)>, data: web::Data<Client>) -> actix_web::Result<HttpResponse> {
+    let (db_name, bucket_name, id_str) = path.into_inner();
+    match file_document(&data.database(&db_name), &bucket_name, &parse_id(&id_str)).await {
+        Ok(Some(file)) => Ok(HttpResponse::Ok().json(file_json(&file))),
+        Ok(None) => Ok(HttpResponse::NotFound().body("file not found")),
+        Err(e) => Ok(HttpResponse::InternalServerError().body(format!("failed to read file: {}", e))),
+    }
+}
+
+/// Streams a file's chunks straight from the bucket without buffering the whole file.
+#[get("/gridfs/{db_name}/{bucket}/files/{id}/download")]
+pub async fn download_file(path: web::Path<(String, String, String)>, data: web::Data<Client>) -> actix_web::Result<HttpResponse> {
+    let (db_name, bucket_name, id_str) = path.into_inner();
+    let db = data.database(&db_name);
+    let id = parse_id(&id_str);
+    let file = match file_document(&db, &bucket_name, &id).await {
+        Ok(Some(file)) => file,
+        Ok(None) => return Ok(HttpResponse::NotFound().body("file not found")),
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("failed to read file: {}", e))),
+    };
+    let download = match bucket(&db, &bucket_name).open_download_stream(id).await {
+        Ok(s) => s,
+        Err(e) if not_found(&e) => return Ok(HttpResponse::NotFound().body("file not found")),
+        Err(e) => {
+            eprintln!("gridfs download error: {}", e);
+            return Ok(HttpResponse::InternalServerError().body(format!("failed to open file: {}", e)));
+        }
+    };
+
+    let body = stream::unfold(Some(download), |state| async move {
+        let mut download = state?;
+        let mut buf = vec![0u8; DOWNLOAD_CHUNK];
+        match download.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some((Ok(Bytes::from(buf)), Some(download)))
+            }
+            Err(e) => Some((Err(actix_web::error::ErrorInternalServerError(e)), None)),
+        }
+    });
+    let filename = attachment_name(file.get_str("filename").unwrap_or(&id_str));
+    let length = file.get("length").and_then(|l| match l {
+        Bson::Int32(n) => u64::try_from(*n).ok(),
+        Bson::Int64(n) => u64::try_from(*n).ok(),
+        Bson::Double(n) => Some(*n as u64),
+        _ => None,
+    });
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(file_content_type(&file))
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)));
+    if let Some(length) = length {
+        response.no_chunking(length);
+    }
+    Ok(response.streaming(body))
+}
+
+#[derive(Deserialize)]
+pub struct UploadQuery {
+    filename: Option<String>,
+    #[serde(rename = "contentType")]
+    content_type: Option<String>,
+}
+
+enum Upload {
+    Buffered(Bytes),
+    Streamed(web::Payload),
+}
+
+/// Uploads one file. A raw body is streamed into the bucket chunk by chunk and needs
+/// `?filename=`; a multipart body takes a `file` part and an optional `metadata` part holding
+/// a JSON object stored as the file's metadata.
+#[post("/gridfs/{db_name}/{bucket}/files")]
+pub async fn upload_file(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<UploadQuery>,
+    mut payload: web::Payload,
+    data: web::Data<Client>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, bucket_name) = path.into_inner();
+    let request_type = content_type(&req).to_string();
+    let mut metadata = Document::new();
+    let mut filename = query.filename.clone();
+
+    let upload = if let Some(boundary) = boundary(&request_type) {
+        let body = match read_upload(&mut payload).await {
+            Ok(b) => b,
+            Err(resp) => return Ok(resp),
+        };
+        let parts = match parse_multipart(&body, &boundary) {
+            Ok(p) => p,
+            Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+        };
+        let mut file = None;
+        for part in parts {
+            match part.name.as_str() {
+                "metadata" => {
+                    let parsed = serde_json::from_slice::<JsonValue>(&part.data)
+                        .map_err(|e| e.to_string())
+                        .and_then(|v| Bson::try_from(v).map_err(|e| e.to_string()));
+                    match parsed {
+                        Ok(Bson::Document(d)) => metadata = d,
+                        Ok(_) => return Ok(HttpResponse::BadRequest().body("metadata must be a JSON object")),
+                        Err(e) => return Ok(HttpResponse::BadRequest().body(format!("invalid metadata: {}", e))),
+                    }
+                }
+                "file" => {
+                    if filename.is_none() {
+                        filename = part.filename.clone();
+                    }
+                    file = Some(part.data);
+                }
+                _ => {}
+            }
+        }
+        match file {
+            Some(data) => Upload::Buffered(data),
+            None => return Ok(HttpResponse::BadRequest().body("multipart upload needs a \"file\" part")),
+        }
+    } else {
+        if !request_type.is_empty() && !request_type.starts_with(DEFAULT_CONTENT_TYPE) {
+            metadata.insert("contentType", request_type.clone());
+        }
+        Upload::Streamed(payload)
+    };
+
+    let filename = match filename.map(|f| f.trim().to_string()).filter(|f| !f.is_empty()) {
+        Some(f) => f,
+        None => return Ok(HttpResponse::BadRequest().body("filename is required")),
+    };
+    if let Some(ct) = query.content_type.as_deref().filter(|ct| !ct.trim().is_empty()) {
+        metadata.insert("contentType", ct.trim());
+    }
+
+    let db = data.database(&db_name);
+    let gridfs = bucket(&db, &bucket_name);
+    let mut stream = match gridfs
+        .open_upload_stream(&filename)
+        .optional((!metadata.is_empty()).then_some(metadata), |a, m| a.metadata(m))
+        .await
+    {
+        Ok(s) => s,
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("failed to open upload: {}", e))),
+    };
+
+    let written: Result<(), String> = async {
+        match upload {
+            Upload::Buffered(data) => stream.write_all(&data).await.map_err(|e| e.to_string())?,
+            Upload::Streamed(mut payload) => {
+                while let Some(chunk) = payload.next().await {
+                    let chunk = chunk.map_err(|e| format!("upload failed: {}", e))?;
+                    stream.write_all(&chunk).await.map_err(|e| e.to_string())?;
+                }
+            }
+        }
+        stream.close().await.map_err(|e| e.to_string())
+    }
+    .await;
+    if let Err(e) = written {
+        // drop the chunks already written so no orphaned partial file is left behind
+        if let Err(abort) = stream.abort().await {
+            eprintln!("gridfs abort error: {}", abort);
+        }
+        eprintln!("gridfs upload error: {}", e);
+        return Ok(HttpResponse::InternalServerError().body(format!("upload failed: {}", e)));
+    }
+
+    let id = stream.id().clone();
+    match file_document(&db, &bucket_name, &id).await {
+        Ok(Some(file)) => Ok(HttpResponse::Created().json(file_json(&file))),
+        _ => Ok(HttpResponse::Created().json(json!({ "_id": id.into_relaxed_extjson(), "filename": filename }))),
+    }
+}
+
+/// Deletes the files document and every chunk belonging to it.
+#[delete("/gridfs/{db_name}/{bucket}/files/{id}")]
+pub async fn delete_file(path: web::Path<(String, String, String)>, data: web::Data<Client>) -> actix_web::Result<HttpResponse> {
+    let (db_name, bucket_name, id_str) = path.into_inner();
+    let id = parse_id(&id_str);
+    match bucket(&data.database(&db_name), &bucket_name).delete(id.clone()).await {
+        Ok(()) => Ok(HttpResponse::Ok().json(json!({ "deleted": id.into_relaxed_extjson() }))),
+        Err(e) if not_found(&e) => Ok(HttpResponse::NotFound().body("file not found")),
+        Err(e) => {
+            eprintln!("gridfs delete error: {}", e);
+            Ok(HttpResponse::InternalServerError().body(format!("failed to delete file: {}", e)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_buckets_with_both_collections() {
+        let names: Vec<String> = ["fs.files", "fs.chunks", "avatars.files", "avatars.chunks", "logs.files", "users"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(bucket_names(&names), vec!["avatars", "fs"]);
+    }
+
+    #[test]
+    fn content_type_and_attachment_name() {
+        assert_eq!(file_content_type(&doc! { "metadata": { "contentType": "image/png" } }), "image/png");
+        assert_eq!(file_content_type(&doc! { "contentType": "text/plain" }), "text/plain");
+        assert_eq!(file_content_type(&doc! {}), DEFAULT_CONTENT_TYPE);
+        assert_eq!(attachment_name("uploads/a \"b\".pdf"), "a _b_.pdf");
+        assert_eq!(attachment_name(""), "download");
+    }
+}
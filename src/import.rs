@@ -0,0 +1,639 @@
+use std::{collections::HashMap, env};
+
+use actix_web::{http::header, post, web, web::{Bytes, BytesMut}, HttpRequest, HttpResponse};
+use chrono::{DateTime, NaiveDate};
+use futures::stream::{self, StreamExt};
+use mongodb::{
+    bson::{oid::ObjectId, Bson, Document},
+    error::{ErrorKind, PartialBulkWriteResult},
+    options::{ReplaceOneModel, UpdateModifications, UpdateOneModel, WriteModel},
+    Client, Collection, Namespace,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use tokio::sync::mpsc;
+
+use crate::bulk::supports_bulk_write;
+use crate::concurrency::{bump_update, bumped_replacement};
+
+const DEFAULT_BATCH_SIZE: usize = 1000;
+const DEFAULT_MAX_UPLOAD_MB: usize = 100;
+// Row errors past this many are counted but not reported individually.
+const MAX_REPORTED_ERRORS: usize = 1000;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Json,
+    Ndjson,
+    Csv,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    #[default]
+    Insert,
+    /// `$set` the row onto the document matching its key fields, inserting when there is none.
+    Upsert,
+    /// Replace the document matching its key fields, inserting when there is none.
+    Replace,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ColumnType {
+    #[default]
+    String,
+    Int,
+    Double,
+    Bool,
+    Date,
+    ObjectId,
+}
+
+/// Where a CSV column goes: a (dotted) field path and the type its text is coerced to.
+#[derive(Deserialize, Clone, Default)]
+pub struct ColumnMapping {
+    pub field: Option<String>,
+    #[serde(rename = "type", default)]
+    pub column_type: ColumnType,
+    // drop the column entirely
+    #[serde(default)]
+    pub skip: bool,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportOptions {
+    /// Defaults to the uploaded file's extension.
+    pub format: Option<ImportFormat>,
+    pub mode: Option<ImportMode>,
+    /// Fields identifying an existing document in upsert and replace modes; defaults to `_id`.
+    pub key: Option<Vec<String>>,
+    pub batch_size: Option<usize>,
+    pub delimiter: Option<char>,
+    /// CSV header -> mapping; unmapped columns keep their header as field name and stay strings.
+    pub columns: Option<HashMap<String, ColumnMapping>>,
+    /// Empty CSV cells are left out of the document instead of stored as "" (default true).
+    pub ignore_blanks: Option<bool>,
+}
+
+#[derive(Serialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportCounts {
+    pub rows: u64,
+    pub inserted: u64,
+    pub matched: u64,
+    pub modified: u64,
+    pub upserted: u64,
+    pub failed: u64,
+}
+
+pub struct Part {
+    pub name: String,
+    pub filename: Option<String>,
+    /// A view into the uploaded body, so splitting it copies nothing.
+    pub data: Bytes,
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    if from >= haystack.len() {
+        return None;
+    }
+    haystack[from..].windows(needle.len()).position(|w| w == needle).map(|p| p + from)
+}
+
+pub fn disposition_param(disposition: &str, param: &str) -> Option<String> {
+    disposition.split(';').map(str::trim).find_map(|item| {
+        let (key, value) = item.split_once('=')?;
+        (key.trim().eq_ignore_ascii_case(param)).then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+pub fn boundary(content_type: &str) -> Option<String> {
+    if !content_type.to_ascii_lowercase().starts_with("multipart/form-data") {
+        return None;
+    }
+    disposition_param(content_type, "boundary").filter(|b| !b.is_empty())
+}
+
+/// Splits a buffered `multipart/form-data` body into its parts.
+pub fn parse_multipart(body: &Bytes, boundary: &str) -> Result<Vec<Part>, String> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut pos = find(body, &delimiter, 0).ok_or("multipart boundary not found")? + delimiter.len();
+    let mut parts = Vec::new();
+    loop {
+        if body[pos..].starts_with(b"--") {
+            break;
+        }
+        let headers_start = pos + 2;
+        let headers_end = find(body, b"\r\n\r\n", headers_start).ok_or("malformed multipart part headers")?;
+        let headers = String::from_utf8_lossy(&body[headers_start..headers_end]);
+        let next = find(body, &[b"\r\n".as_slice(), &delimiter].concat(), headers_end + 4)
+            .ok_or("multipart body is truncated")?;
+
+        let disposition = headers
+            .lines()
+            .find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                key.trim().eq_ignore_ascii_case("content-disposition").then(|| value.to_string())
+            })
+            .unwrap_or_default();
+        if let Some(name) = disposition_param(&disposition, "name") {
+            parts.push(Part {
+                name,
+                filename: disposition_param(&disposition, "filename"),
+                data: body.slice(headers_end + 4..next),
+            });
+        }
+        pos = next + 2 + delimiter.len();
+        if pos >= body.len() {
+            break;
+        }
+    }
+    Ok(parts)
+}
+
+/// RFC 4180 records: quoted fields may hold delimiters, doubled quotes and line breaks.
+pub fn parse_csv(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' | '\r' => {
+                record.push(std::mem::take(&mut field));
+                if !(record.len() == 1 && record[0].is_empty()) {
+                    records.push(std::mem::take(&mut record));
+                }
+            }
+            c if c == delimiter => record.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+pub fn coerce(raw: &str, column_type: ColumnType) -> Result<Bson, String> {
+    let value = raw.trim();
+    match column_type {
+        ColumnType::String => Ok(Bson::String(raw.to_string())),
+        ColumnType::Int => {
+            let n: i64 = value.parse().map_err(|_| format!("'{}' is not an integer", value))?;
+            Ok(i32::try_from(n).map(Bson::Int32).unwrap_or(Bson::Int64(n)))
+        }
+        ColumnType::Double => value
+            .parse::<f64>()
+            .map(Bson::Double)
+            .map_err(|_| format!("'{}' is not a number", value)),
+        ColumnType::Bool => match value.to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "y" => Ok(Bson::Boolean(true)),
+            "false" | "0" | "no" | "n" => Ok(Bson::Boolean(false)),
+            _ => Err(format!("'{}' is not a boolean", value)),
+        },
+        ColumnType::Date => {
+            if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+                return Ok(Bson::DateTime(mongodb::bson::DateTime::from_millis(dt.timestamp_millis())));
+            }
+            if let Ok(day) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+                let millis = day.and_hms_opt(0, 0, 0).map(|d| d.and_utc().timestamp_millis()).unwrap_or(0);
+                return Ok(Bson::DateTime(mongodb::bson::DateTime::from_millis(millis)));
+            }
+            value
+                .parse::<i64>()
+                .map(|ms| Bson::DateTime(mongodb::bson::DateTime::from_millis(ms)))
+                .map_err(|_| format!("'{}' is not an RFC 3339 date, YYYY-MM-DD or epoch milliseconds", value))
+        }
+        ColumnType::ObjectId => ObjectId::parse_str(value)
+            .map(Bson::ObjectId)
+            .map_err(|_| format!("'{}' is not an ObjectId", value)),
+    }
+}
+
+/// Sets `value` at a dotted path, creating intermediate documents.
+fn insert_path(doc: &mut Document, path: &str, value: Bson) {
+    match path.split_once('.') {
+        None => {
+            doc.insert(path, value);
+        }
+        Some((head, rest)) => {
+            if !matches!(doc.get(head), Some(Bson::Document(_))) {
+                doc.insert(head, Document::new());
+            }
+            if let Some(Bson::Document(child)) = doc.get_mut(head) {
+                insert_path(child, rest, value);
+            }
+        }
+    }
+}
+
+fn csv_documents(text: &str, options: &ImportOptions) -> Vec<Result<Document, String>> {
+    let mut records = parse_csv(text, options.delimiter.unwrap_or(',')).into_iter();
+    let Some(header) = records.next() else {
+        return Vec::new();
+    };
+    let mapping = options.columns.clone().unwrap_or_default();
+    let ignore_blanks = options.ignore_blanks.unwrap_or(true);
+    records
+        .map(|record| {
+            let mut doc = Document::new();
+            for (name, raw) in header.iter().zip(record.iter()) {
+                let column = mapping.get(name).cloned().unwrap_or_default();
+                if column.skip || (ignore_blanks && raw.trim().is_empty()) {
+                    continue;
+                }
+                let value = coerce(raw, column.column_type).map_err(|e| format!("column {}: {}", name, e))?;
+                insert_path(&mut doc, column.field.as_deref().unwrap_or(name), value);
+            }
+            Ok(doc)
+        })
+        .collect()
+}
+
+// Extended JSON ($oid, $date, ...) is honoured so exports round-trip.
+fn json_document(value: JsonValue) -> Result<Document, String> {
+    match Bson::try_from(value) {
+        Ok(Bson::Document(d)) => Ok(d),
+        Ok(_) => Err("row is not an object".to_string()),
+        Err(e) => Err(format!("invalid extended JSON: {}", e)),
+    }
+}
+
+pub fn parse_rows(data: &[u8], format: ImportFormat, options: &ImportOptions) -> Result<Vec<Result<Document, String>>, String> {
+    let text = std::str::from_utf8(data).map_err(|_| "file is not valid UTF-8".to_string())?;
+    let text = text.trim_start_matches('\u{feff}');
+    match format {
+        ImportFormat::Csv => Ok(csv_documents(text, options)),
+        ImportFormat::Json => match serde_json::from_str::<JsonValue>(text) {
+            Ok(JsonValue::Array(items)) => Ok(items.into_iter().map(json_document).collect()),
+            Ok(other) => Ok(vec![json_document(other)]),
+            Err(e) => Err(format!("invalid JSON: {}", e)),
+        },
+        ImportFormat::Ndjson => Ok(text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str::<JsonValue>(line)
+                    .map_err(|e| format!("invalid JSON: {}", e))
+                    .and_then(json_document)
+            })
+            .collect()),
+    }
+}
+
+pub fn format_from_filename(filename: &str) -> Option<ImportFormat> {
+    let extension = filename.rsplit('.').next()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "json" => Some(ImportFormat::Json),
+        "ndjson" | "jsonl" => Some(ImportFormat::Ndjson),
+        "csv" => Some(ImportFormat::Csv),
+        _ => None,
+    }
+}
+
+fn key_filter(doc: &Document, key: &[String]) -> Result<Document, String> {
+    let mut filter = Document::new();
+    for field in key {
+        let value = crate::export::resolve_path(&Bson::Document(doc.clone()), &field.split('.').collect::<Vec<_>>())
+            .ok_or_else(|| format!("key field {} is missing", field))?;
+        filter.insert(field.clone(), value);
+    }
+    Ok(filter)
+}
+
+/// The upserting write for one keyed row: `$set` for upsert mode, a replacement otherwise.
+fn upsert_model(ns: &Namespace, filter: Document, doc: Document, mode: ImportMode) -> WriteModel {
+    if mode == ImportMode::Upsert {
+        let mut fields = doc;
+        // _id is immutable; it only takes effect when the upsert inserts
+        let id = fields.remove("_id");
+        let mut update = mongodb::bson::doc! { "$set": fields };
+        if let Some(id) = id {
+            update.insert("$setOnInsert", mongodb::bson::doc! { "_id": id });
+        }
+        let update = bump_update(UpdateModifications::Document(update));
+        return UpdateOneModel::builder().namespace(ns.clone()).filter(filter).update(update).upsert(true).build().into();
+    }
+    match bumped_replacement(&doc) {
+        Some(pipeline) => UpdateOneModel::builder()
+            .namespace(ns.clone())
+            .filter(filter)
+            .update(UpdateModifications::Pipeline(pipeline))
+            .upsert(true)
+            .build()
+            .into(),
+        None => ReplaceOneModel::builder().namespace(ns.clone()).filter(filter).replacement(doc).upsert(true).build().into(),
+    }
+}
+
+/// Writes parsed rows in batches, calling `emit` with a progress event after every batch and an
+/// error event for each failing row (1-based). Returns the final counts.
+pub async fn run_import(
+    coll: &Collection<Document>,
+    rows: Vec<Result<Document, String>>,
+    options: &ImportOptions,
+    mut emit: impl FnMut(JsonValue),
+) -> ImportCounts {
+    let mode = options.mode.unwrap_or_default();
+    let key = options.key.clone().unwrap_or_else(|| vec!["_id".to_string()]);
+    let batch_size = options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1);
+    let total = rows.len();
+    let mut counts = ImportCounts::default();
+    let mut reported = 0;
+    let mut fail = |counts: &mut ImportCounts, row: usize, error: String, emit: &mut dyn FnMut(JsonValue)| {
+        counts.failed += 1;
+        if reported < MAX_REPORTED_ERRORS {
+            reported += 1;
+            emit(json!({ "event": "error", "row": row, "error": error }));
+        }
+    };
+
+    let ns = coll.namespace();
+    // keyed rows go through one bulkWrite per batch where the server has it
+    let server_bulk_write =
+        mode != ImportMode::Insert && supports_bulk_write(coll.client()).await.unwrap_or(false);
+
+    let mut rows = rows.into_iter().enumerate().peekable();
+    while rows.peek().is_some() {
+        let batch: Vec<(usize, Result<Document, String>)> = rows.by_ref().take(batch_size).collect();
+        counts.rows += batch.len() as u64;
+        let mut docs = Vec::new();
+        for (index, row) in batch {
+            match row {
+                Ok(doc) => docs.push((index + 1, doc)),
+                Err(e) => fail(&mut counts, index + 1, e, &mut emit),
+            }
+        }
+
+        match mode {
+            ImportMode::Insert if !docs.is_empty() => {
+                let (numbers, batch): (Vec<usize>, Vec<Document>) = docs.into_iter().unzip();
+                let attempted = batch.len() as u64;
+                match coll.insert_many(batch).ordered(false).await {
+                    Ok(r) => counts.inserted += r.inserted_ids.len() as u64,
+                    Err(e) => match *e.kind {
+                        ErrorKind::InsertMany(ref failure) => {
+                            let write_errors = failure.write_errors.clone().unwrap_or_default();
+                            counts.inserted += attempted - write_errors.len() as u64;
+                            for w in write_errors {
+                                fail(&mut counts, numbers.get(w.index).copied().unwrap_or(0), w.message, &mut emit);
+                            }
+                            if let Some(wc) = &failure.write_concern_error {
+                                emit(json!({ "event": "warning", "error": wc.message }));
+                            }
+                        }
+                        _ => {
+                            for number in numbers {
+                                fail(&mut counts, number, e.to_string(), &mut emit);
+                            }
+                        }
+                    },
+                }
+            }
+            ImportMode::Insert => {}
+            ImportMode::Upsert | ImportMode::Replace => {
+                let mut numbers = Vec::new();
+                let mut models = Vec::new();
+                for (number, doc) in docs {
+                    match key_filter(&doc, &key) {
+                        Ok(filter) => {
+                            numbers.push(number);
+                            models.push(upsert_model(&ns, filter, doc, mode));
+                        }
+                        Err(e) => fail(&mut counts, number, e, &mut emit),
+                    }
+                }
+                if server_bulk_write && !models.is_empty() {
+                    match coll.client().bulk_write(models).ordered(false).await {
+                        Ok(r) => {
+                            counts.matched += r.matched_count as u64;
+                            counts.modified += r.modified_count as u64;
+                            counts.upserted += r.upserted_count as u64;
+                        }
+                        Err(e) => match *e.kind {
+                            ErrorKind::BulkWrite(ref failure) => {
+                                if let Some(PartialBulkWriteResult::Summary(r)) = &failure.partial_result {
+                                    counts.matched += r.matched_count as u64;
+                                    counts.modified += r.modified_count as u64;
+                                    counts.upserted += r.upserted_count as u64;
+                                }
+                                let mut errors: Vec<_> = failure.write_errors.iter().collect();
+                                errors.sort_by_key(|(index, _)| **index);
+                                for (index, w) in errors {
+                                    fail(&mut counts, numbers.get(*index).copied().unwrap_or(0), w.message.clone(), &mut emit);
+                                }
+                                for wc in &failure.write_concern_errors {
+                                    emit(json!({ "event": "warning", "error": wc.message }));
+                                }
+                            }
+                            _ => {
+                                for number in numbers {
+                                    fail(&mut counts, number, e.to_string(), &mut emit);
+                                }
+                            }
+                        },
+                    }
+                } else {
+                    for (number, model) in numbers.into_iter().zip(models) {
+                        let result = match model {
+                            WriteModel::UpdateOne(m) => coll.update_one(m.filter, m.update).upsert(true).await,
+                            WriteModel::ReplaceOne(m) => coll.replace_one(m.filter, m.replacement).upsert(true).await,
+                            _ => unreachable!("upsert_model only builds updateOne and replaceOne"),
+                        };
+                        match result {
+                            Ok(r) => {
+                                counts.matched += r.matched_count;
+                                counts.modified += r.modified_count;
+                                counts.upserted += u64::from(r.upserted_id.is_some());
+                            }
+                            Err(e) => fail(&mut counts, number, e.to_string(), &mut emit),
+                        }
+                    }
+                }
+            }
+        }
+
+        let done = counts.rows as usize;
+        emit(json!({
+            "event": "progress",
+            "processed": done,
+            "total": total,
+            "percent": if total == 0 { 100.0 } else { done as f64 * 100.0 / total as f64 },
+            "counts": counts,
+        }));
+    }
+    counts
+}
+
+fn max_upload_bytes() -> usize {
+    env::var("GLOBE_IMPORT_MAX_MB")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_UPLOAD_MB)
+        * 1024
+        * 1024
+}
+
+/// Buffers a request body, refusing uploads larger than `GLOBE_IMPORT_MAX_MB`.
+pub async fn read_upload(payload: &mut web::Payload) -> Result<Bytes, HttpResponse> {
+    let limit = max_upload_bytes();
+    let mut body = BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| HttpResponse::BadRequest().body(format!("upload failed: {}", e)))?;
+        body.extend_from_slice(&chunk);
+        if body.len() > limit {
+            return Err(HttpResponse::PayloadTooLarge().body(format!("upload exceeds {} bytes", limit)));
+        }
+    }
+    Ok(body.freeze())
+}
+
+pub fn content_type(req: &HttpRequest) -> &str {
+    req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// Reads a multipart upload with a `file` part (JSON array, NDJSON or CSV) and an optional
+/// `options` part holding `ImportOptions` as JSON, and parses it into rows.
+pub async fn read_import(
+    req: &HttpRequest,
+    payload: &mut web::Payload,
+) -> Result<(ImportOptions, Vec<Result<Document, String>>), HttpResponse> {
+    let Some(boundary) = boundary(content_type(req)) else {
+        return Err(HttpResponse::BadRequest().body("expected a multipart/form-data upload"));
+    };
+    let body = read_upload(payload).await?;
+    let parts = parse_multipart(&body, &boundary).map_err(|e| HttpResponse::BadRequest().body(e))?;
+
+    let options: ImportOptions = match parts.iter().find(|p| p.name == "options") {
+        Some(part) => serde_json::from_slice(&part.data)
+            .map_err(|e| HttpResponse::BadRequest().body(format!("invalid options: {}", e)))?,
+        None => ImportOptions::default(),
+    };
+    let Some(file) = parts.into_iter().find(|p| p.name == "file") else {
+        return Err(HttpResponse::BadRequest().body("missing file part"));
+    };
+    let Some(format) = options.format.or_else(|| file.filename.as_deref().and_then(format_from_filename)) else {
+        return Err(HttpResponse::BadRequest().body("cannot tell the file format; set options.format"));
+    };
+    let rows = parse_rows(&file.data, format, &options).map_err(|e| HttpResponse::BadRequest().body(e))?;
+    Ok((options, rows))
+}
+
+/// Streams NDJSON events back while importing: `progress` after each batch, `error` per failed
+/// row and a final `done` with the counts.
+#[post("/import/{db_name}/{coll_name}")]
+pub async fn import_documents(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    mut payload: web::Payload,
+    data: web::Data<Client>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let (options, rows) = match read_import(&req, &mut payload).await {
+        Ok(parsed) => parsed,
+        Err(resp) => return Ok(resp),
+    };
+
+    let coll = data.database(&db_name).collection::<Document>(&coll_name);
+    let (tx, rx) = mpsc::unbounded_channel::<JsonValue>();
+    tokio::spawn(async move {
+        let counts = run_import(&coll, rows, &options, |event| {
+            let _ = tx.send(event);
+        })
+        .await;
+        let _ = tx.send(json!({ "event": "done", "counts": counts }));
+    });
+    let events = stream::unfold(rx, |mut rx| async move {
+        let event = rx.recv().await?;
+        Some((Ok::<_, actix_web::Error>(Bytes::from(format!("{}\n", event))), rx))
+    });
+
+    Ok(HttpResponse::Ok().content_type("application/x-ndjson").streaming(events))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        coerce, parse_csv, parse_multipart, parse_rows, upsert_model, ColumnMapping, ColumnType, ImportFormat, ImportMode,
+        ImportOptions,
+    };
+    use actix_web::web::Bytes;
+    use mongodb::{
+        bson::{doc, Bson},
+        options::{UpdateModifications, WriteModel},
+        Namespace,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn parses_quoted_csv() {
+        let records = parse_csv("a,b\r\n\"x, \"\"y\"\"\",\"line\nbreak\"\n1,\n", ',');
+        assert_eq!(records, vec![vec!["a", "b"], vec!["x, \"y\"", "line\nbreak"], vec!["1", ""]]);
+    }
+
+    #[test]
+    fn coerces_and_maps_csv_columns() {
+        assert_eq!(coerce("42", ColumnType::Int), Ok(Bson::Int32(42)));
+        assert_eq!(coerce("yes", ColumnType::Bool), Ok(Bson::Boolean(true)));
+        assert!(coerce("nope", ColumnType::Double).is_err());
+        assert!(matches!(coerce("2024-05-01", ColumnType::Date), Ok(Bson::DateTime(_))));
+
+        let mut columns = HashMap::new();
+        columns.insert(
+            "Age".to_string(),
+            ColumnMapping { field: Some("profile.age".to_string()), column_type: ColumnType::Int, skip: false },
+        );
+        let options = ImportOptions { columns: Some(columns), ..Default::default() };
+        let rows = parse_rows(b"name,Age\nann,31\nbob,x\n", ImportFormat::Csv, &options).unwrap();
+        assert_eq!(rows[0], Ok(doc! { "name": "ann", "profile": { "age": 31 } }));
+        assert!(rows[1].is_err());
+    }
+
+    #[test]
+    fn splits_multipart_parts() {
+        let body = b"--XX\r\nContent-Disposition: form-data; name=\"options\"\r\n\r\n{}\r\n--XX\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.csv\"\r\nContent-Type: text/csv\r\n\r\na,b\r\n1,2\r\n--XX--\r\n";
+        let parts = parse_multipart(&Bytes::from_static(body), "XX").unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[1].filename.as_deref(), Some("a.csv"));
+        assert_eq!(&parts[1].data[..], b"a,b\r\n1,2");
+    }
+
+    #[test]
+    fn upserts_keep_the_row_id_for_inserts_only() {
+        let ns = Namespace::new("shop", "orders");
+        let row = doc! { "_id": 7, "sku": "a", "qty": 2 };
+        let WriteModel::UpdateOne(model) = upsert_model(&ns, doc! { "sku": "a" }, row, ImportMode::Upsert) else {
+            panic!("upsert mode should build an updateOne");
+        };
+        assert_eq!(model.filter, doc! { "sku": "a" });
+        assert_eq!(model.upsert, Some(true));
+        let UpdateModifications::Document(update) = model.update else {
+            panic!("upsert mode should build an update document");
+        };
+        assert_eq!(update.get_document("$set").unwrap(), &doc! { "sku": "a", "qty": 2 });
+        assert_eq!(update.get_document("$setOnInsert").unwrap(), &doc! { "_id": 7 });
+    }
+}
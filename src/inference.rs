@@ -0,0 +1,357 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use actix_web::{get, post, web, HttpResponse};
+use mongodb::{
+    bson::{doc, Bson, Document},
+    Client,
+};
+use serde::Deserialize;
+use serde_json::{json, Map, Value as JsonValue};
+
+use crate::sampling::{bson_type_name, sample_documents, DEFAULT_SAMPLE_SIZE};
+use crate::schemas::{install_validator, json_to_document, SchemaStore};
+use crate::state::AppInfo;
+
+const DEFAULT_ENUM_LIMIT: usize = 10;
+
+/// Observed shape of one position in the sampled documents: the root, a field, or array items.
+#[derive(Default)]
+pub struct SchemaNode {
+    seen: u64,
+    types: BTreeMap<&'static str, u64>,
+    // Number of embedded documents seen here; the denominator for `required`.
+    documents: u64,
+    properties: BTreeMap<String, SchemaNode>,
+    items: Option<Box<SchemaNode>>,
+    strings: BTreeSet<String>,
+    strings_seen: u64,
+    too_many_strings: bool,
+}
+
+impl SchemaNode {
+    pub fn observe_document(&mut self, doc: &Document, enum_limit: usize) {
+        self.seen += 1;
+        *self.types.entry("object").or_insert(0) += 1;
+        self.documents += 1;
+        for (key, value) in doc.iter() {
+            self.properties.entry(key.clone()).or_default().observe(value, enum_limit);
+        }
+    }
+
+    pub fn observe(&mut self, value: &Bson, enum_limit: usize) {
+        match value {
+            Bson::Document(d) => return self.observe_document(d, enum_limit),
+            Bson::Array(values) => {
+                let items = self.items.get_or_insert_with(Box::default);
+                for v in values {
+                    items.observe(v, enum_limit);
+                }
+            }
+            Bson::String(s) => {
+                self.strings_seen += 1;
+                if !self.too_many_strings {
+                    self.strings.insert(s.clone());
+                    if self.strings.len() > enum_limit {
+                        self.too_many_strings = true;
+                        self.strings.clear();
+                    }
+                }
+            }
+            _ => {}
+        }
+        self.seen += 1;
+        *self.types.entry(bson_type_name(value)).or_insert(0) += 1;
+    }
+
+    pub fn to_json_schema(&self) -> JsonValue {
+        let mut out = Map::new();
+
+        let mut types: Vec<(&str, u64)> = self.types.iter().map(|(t, c)| (*t, *c)).collect();
+        types.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        match types.len() {
+            0 => {}
+            1 => {
+                out.insert("bsonType".to_string(), json!(types[0].0));
+            }
+            _ => {
+                out.insert("bsonType".to_string(), json!(types.iter().map(|(t, _)| *t).collect::<Vec<_>>()));
+            }
+        }
+
+        if self.documents > 0 && !self.properties.is_empty() {
+            let mut properties = Map::new();
+            let mut required = Vec::new();
+            for (name, child) in &self.properties {
+                if child.seen >= self.documents {
+                    required.push(json!(name));
+                }
+                properties.insert(name.clone(), child.to_json_schema());
+            }
+            if !required.is_empty() {
+                out.insert("required".to_string(), JsonValue::Array(required));
+            }
+            out.insert("properties".to_string(), JsonValue::Object(properties));
+        }
+
+        if let Some(items) = &self.items
+            && items.seen > 0
+        {
+            out.insert("items".to_string(), items.to_json_schema());
+        }
+
+        // Only offer an enum when every value was a string and each one repeats on average,
+        // otherwise a small sample of free text would be frozen into an enum.
+        let only_strings = types.len() == 1 && types[0].0 == "string";
+        if only_strings
+            && !self.too_many_strings
+            && !self.strings.is_empty()
+            && self.strings_seen >= 2 * self.strings.len() as u64
+        {
+            out.insert("enum".to_string(), json!(self.strings.iter().collect::<Vec<_>>()));
+        }
+
+        JsonValue::Object(out)
+    }
+}
+
+/// Builds a `$jsonSchema` document describing `docs`. Fields present in every sampled parent
+/// document are marked required; string fields with at most `enum_limit` distinct values get an enum.
+pub fn infer_schema(docs: &[Document], enum_limit: usize) -> JsonValue {
+    let mut root = SchemaNode::default();
+    for d in docs {
+        root.observe_document(d, enum_limit);
+    }
+    let mut schema = root.to_json_schema();
+    if let JsonValue::Object(map) = &mut schema {
+        map.insert("bsonType".to_string(), json!("object"));
+    }
+    schema
+}
+
+fn draft7_type(bson_type: &str) -> Option<(&'static str, Option<(&'static str, &'static str)>)> {
+    match bson_type {
+        "double" | "decimal" => Some(("number", None)),
+        "int" | "long" => Some(("integer", None)),
+        "string" | "symbol" => Some(("string", None)),
+        "bool" => Some(("boolean", None)),
+        "object" => Some(("object", None)),
+        "array" => Some(("array", None)),
+        "null" => Some(("null", None)),
+        "date" => Some(("string", Some(("format", "date-time")))),
+        "objectId" => Some(("string", Some(("pattern", "^[0-9a-fA-F]{24}$")))),
+        _ => None,
+    }
+}
+
+fn draft7_node(schema: &JsonValue) -> JsonValue {
+    let obj = match schema.as_object() {
+        Some(o) => o,
+        None => return json!({}),
+    };
+    let mut out = Map::new();
+
+    let bson_types: Vec<&str> = match obj.get("bsonType") {
+        Some(JsonValue::String(t)) => vec![t.as_str()],
+        Some(JsonValue::Array(ts)) => ts.iter().filter_map(|t| t.as_str()).collect(),
+        _ => Vec::new(),
+    };
+    let mapped: Vec<_> = bson_types.iter().filter_map(|t| draft7_type(t)).collect();
+    let mut type_names: Vec<&str> = Vec::new();
+    for (name, _) in &mapped {
+        if !type_names.contains(name) {
+            type_names.push(name);
+        }
+    }
+    // If any type had no draft-07 equivalent, leave the value unconstrained rather than reject it.
+    if !type_names.is_empty() && mapped.len() == bson_types.len() {
+        if type_names.len() == 1 {
+            out.insert("type".to_string(), json!(type_names[0]));
+        } else {
+            out.insert("type".to_string(), json!(type_names));
+        }
+        if let [(_, Some((keyword, value)))] = mapped.as_slice() {
+            out.insert(keyword.to_string(), json!(value));
+        }
+    }
+
+    for key in ["required", "enum"] {
+        if let Some(v) = obj.get(key) {
+            out.insert(key.to_string(), v.clone());
+        }
+    }
+    if let Some(JsonValue::Object(props)) = obj.get("properties") {
+        let converted: Map<String, JsonValue> = props.iter().map(|(k, v)| (k.clone(), draft7_node(v))).collect();
+        out.insert("properties".to_string(), JsonValue::Object(converted));
+    }
+    if let Some(items) = obj.get("items") {
+        out.insert("items".to_string(), draft7_node(items));
+    }
+
+    JsonValue::Object(out)
+}
+
+/// Rewrites a `$jsonSchema` (bsonType-based) document as plain JSON Schema draft-07.
+pub fn to_draft7(schema: &JsonValue) -> JsonValue {
+    let mut out = draft7_node(schema);
+    if let JsonValue::Object(map) = &mut out {
+        map.insert("$schema".to_string(), json!("http://json-schema.org/draft-07/schema#"));
+    }
+    out
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InferQuery {
+    sample: Option<i64>,
+    enum_limit: Option<usize>,
+    // "bson" (default, $jsonSchema with bsonType) or "draft7"
+    dialect: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InferSaveRequest {
+    sample: Option<i64>,
+    enum_limit: Option<usize>,
+    // "registry" stores the result as a new schema version, "validator" installs it via collMod.
+    save: String,
+    validation_level: Option<String>,
+    validation_action: Option<String>,
+    // install the validator even though existing documents would fail it
+    force: Option<bool>,
+}
+
+async fn infer_for(
+    client: &Client,
+    db_name: &str,
+    coll_name: &str,
+    sample: Option<i64>,
+    enum_limit: Option<usize>,
+) -> mongodb::error::Result<(usize, JsonValue)> {
+    let coll = client.database(db_name).collection::<Document>(coll_name);
+    let docs = sample_documents(&coll, sample.unwrap_or(DEFAULT_SAMPLE_SIZE)).await?;
+    let schema = infer_schema(&docs, enum_limit.unwrap_or(DEFAULT_ENUM_LIMIT));
+    Ok((docs.len(), schema))
+}
+
+#[get("/collections/{db_name}/{coll_name}/schema/infer")]
+pub async fn infer_collection_schema(
+    path: web::Path<(String, String)>,
+    query: web::Query<InferQuery>,
+    data: web::Data<Client>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let (sampled, schema) = match infer_for(&data, &db_name, &coll_name, query.sample, query.enum_limit).await {
+        Ok(r) => r,
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("aggregate failed: {}", e))),
+    };
+    let schema = match query.dialect.as_deref() {
+        None | Some("bson") => schema,
+        Some("draft7") => to_draft7(&schema),
+        Some(other) => return Ok(HttpResponse::BadRequest().body(format!("unknown dialect: {}", other))),
+    };
+
+    Ok(HttpResponse::Ok().json(json!({
+        "database": db_name,
+        "collection": coll_name,
+        "sampledDocumentCount": sampled,
+        "jsonSchema": schema,
+    })))
+}
+
+#[post("/collections/{db_name}/{coll_name}/schema/infer")]
+pub async fn save_inferred_schema(
+    path: web::Path<(String, String)>,
+    body: web::Json<InferSaveRequest>,
+    data: web::Data<Client>,
+    schemas: web::Data<SchemaStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let req = body.into_inner();
+    let (sampled, schema) = match infer_for(&data, &db_name, &coll_name, req.sample, req.enum_limit).await {
+        Ok(r) => r,
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("aggregate failed: {}", e))),
+    };
+
+    let saved = match req.save.as_str() {
+        "registry" => match schemas.put(&app_info.shortened_uri, &db_name, &coll_name, schema.clone()).await {
+            Ok(stored) => json!({"registryVersion": stored.version}),
+            Err(e) => {
+                eprintln!("schema store error: {}", e);
+                return Ok(HttpResponse::InternalServerError().body("failed to store schema"));
+            }
+        },
+        "validator" => {
+            let validator = match json_to_document(&schema) {
+                Ok(d) => doc! { "$jsonSchema": d },
+                Err(e) => return Ok(HttpResponse::InternalServerError().body(e)),
+            };
+            let db = data.database(&db_name);
+            let force = req.force.unwrap_or(false);
+            match install_validator(&db, &coll_name, validator, req.validation_level, req.validation_action, None, force).await {
+                Ok(result) => json!({"validator": result}),
+                Err(resp) => return Ok(resp),
+            }
+        }
+        other => return Ok(HttpResponse::BadRequest().body(format!("save must be registry or validator, got {}", other))),
+    };
+
+    Ok(HttpResponse::Ok().json(json!({
+        "database": db_name,
+        "collection": coll_name,
+        "sampledDocumentCount": sampled,
+        "jsonSchema": schema,
+        "saved": saved,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{infer_schema, to_draft7};
+    use mongodb::bson::{doc, oid::ObjectId, DateTime};
+    use serde_json::json;
+
+    #[test]
+    fn infers_nested_types_required_and_enums() {
+        let docs = vec![
+            doc! { "_id": ObjectId::new(), "status": "active", "n": 1_i32, "address": { "city": "a", "zip": 1_i64 }, "tags": ["x"] },
+            doc! { "_id": ObjectId::new(), "status": "active", "n": 2.5, "address": { "city": "b" }, "tags": [] },
+            doc! { "_id": ObjectId::new(), "status": "inactive", "n": 3_i32, "address": { "city": "c" }, "at": DateTime::now() },
+            doc! { "_id": ObjectId::new(), "status": "inactive", "n": 4_i32, "address": { "city": "d" }, "tags": ["y", "z"] },
+        ];
+        let schema = infer_schema(&docs, 10);
+
+        assert_eq!(schema["bsonType"], json!("object"));
+        assert_eq!(schema["required"], json!(["_id", "address", "n", "status"]));
+        assert_eq!(schema["properties"]["_id"]["bsonType"], json!("objectId"));
+        assert_eq!(schema["properties"]["n"]["bsonType"], json!(["int", "double"]));
+        assert_eq!(schema["properties"]["at"]["bsonType"], json!("date"));
+        assert_eq!(schema["properties"]["status"]["enum"], json!(["active", "inactive"]));
+        assert_eq!(schema["properties"]["address"]["required"], json!(["city"]));
+        assert_eq!(schema["properties"]["address"]["properties"]["zip"]["bsonType"], json!("long"));
+        assert_eq!(schema["properties"]["tags"]["items"]["bsonType"], json!("string"));
+        // three distinct tags over three values never repeat, so no enum
+        assert!(schema["properties"]["tags"]["items"].get("enum").is_none());
+    }
+
+    #[test]
+    fn converts_to_draft7() {
+        let schema = json!({
+            "bsonType": "object",
+            "required": ["_id"],
+            "properties": {
+                "_id": { "bsonType": "objectId" },
+                "n": { "bsonType": ["int", "double"] },
+                "at": { "bsonType": "date" },
+                "raw": { "bsonType": ["binData", "string"] }
+            }
+        });
+        let draft = to_draft7(&schema);
+        assert_eq!(draft["$schema"], json!("http://json-schema.org/draft-07/schema#"));
+        assert_eq!(draft["properties"]["_id"], json!({"type": "string", "pattern": "^[0-9a-fA-F]{24}$"}));
+        assert_eq!(draft["properties"]["n"], json!({"type": ["integer", "number"]}));
+        assert_eq!(draft["properties"]["at"], json!({"type": "string", "format": "date-time"}));
+        assert_eq!(draft["properties"]["raw"], json!({}));
+    }
+}
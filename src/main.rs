@@ -3,7 +3,6 @@ use actix_cors::Cors;
 use actix_web::http::header;
 use mongodb::{bson::{self, doc, Document}, Client};
 use serde::{Deserialize, Serialize};
-use dotenv;
 use std::env;
 use futures::stream::TryStreamExt;
 use crate::dbs::databases;
@@ -13,8 +12,41 @@ mod ai;
 mod collections;
 mod state;
 mod monitoring;
+mod views;
+mod diff;
+mod schemas;
+mod store;
+mod sampling;
+mod inference;
+mod stats;
+mod drift;
+mod relationships;
+mod orphans;
+mod bulk;
+mod concurrency;
+mod revisions;
+mod transactions;
+mod export;
+mod import;
+mod dump;
+mod profiles;
+mod copy;
+mod masking;
+mod generator;
+mod compare;
+mod gridfs;
 use state::AppInfo;
 use monitoring::MonitoringState;
+use schemas::SchemaStore;
+use drift::DriftStore;
+use relationships::RelationshipStore;
+use bulk::DryRunStore;
+use concurrency::VersionCache;
+use revisions::RevisionStore;
+use transactions::TransactionStore;
+use ops::JobStore;
+use profiles::ProfileStore;
+use copy::CopyStore;
 
 #[derive(Deserialize)]
 struct QueryRequest {
@@ -106,7 +138,7 @@ async fn main() -> std::io::Result<()> {
         Ok(c) => c,
         Err(e) => {
             eprintln!("mongodb client connection error: {}", e);
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, "mongodb client connection failed"));
+            return Err(std::io::Error::other("mongodb client connection failed"));
         }
     };
 
@@ -115,23 +147,58 @@ async fn main() -> std::io::Result<()> {
     let client_data = web::Data::new(client.clone());
     let app_info = web::Data::new(AppInfo::new(&uri));
     let monitoring_state = web::Data::new(MonitoringState::new());
+    let schema_store = web::Data::new(SchemaStore::new());
+    let drift_store = web::Data::new(DriftStore::new());
+    let relationship_store = web::Data::new(RelationshipStore::new());
+    let dry_runs = web::Data::new(DryRunStore::new());
+    let version_cache = web::Data::new(VersionCache::new());
+    let revision_store = web::Data::new(RevisionStore::new(&client));
+    let transaction_store = web::Data::new(TransactionStore::new());
+    let job_store = web::Data::new(JobStore::new());
+    let profile_store = web::Data::new(ProfileStore::new());
+    let copy_store = web::Data::new(CopyStore::new());
+    job_store.recover().await;
+
+    tokio::spawn(drift::run_periodic(client.clone(), drift_store.clone(), app_info.shortened_uri.clone()));
+    tokio::spawn(transactions::run_expiry(transaction_store.clone()));
 
     eprintln!("Starting HTTP server on 127.0.0.1:6969");
     HttpServer::new({
         let client_data = client_data.clone();
         let app_info = app_info.clone();
         let monitoring_state = monitoring_state.clone();
+        let schema_store = schema_store.clone();
+        let drift_store = drift_store.clone();
+        let relationship_store = relationship_store.clone();
+        let dry_runs = dry_runs.clone();
+        let version_cache = version_cache.clone();
+        let revision_store = revision_store.clone();
+        let transaction_store = transaction_store.clone();
+        let job_store = job_store.clone();
+        let profile_store = profile_store.clone();
+        let copy_store = copy_store.clone();
         move || {
         let cors = Cors::default()
             .allow_any_origin()
-            .allowed_methods(vec!["GET", "POST", "OPTIONS"])
-            .allowed_headers(vec![header::CONTENT_TYPE, header::ACCEPT])
+            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
+            .allowed_headers(vec![header::CONTENT_TYPE, header::ACCEPT, header::IF_MATCH])
+            .expose_headers(vec![header::ETAG])
             .max_age(3600);
         App::new()
             .wrap(cors)
             .app_data(client_data.clone())
             .app_data(app_info.clone())
             .app_data(monitoring_state.clone())
+            .app_data(schema_store.clone())
+            .app_data(drift_store.clone())
+            .app_data(relationship_store.clone())
+            .app_data(dry_runs.clone())
+            .app_data(version_cache.clone())
+            .app_data(revision_store.clone())
+            .app_data(transaction_store.clone())
+            .app_data(job_store.clone())
+            .app_data(profile_store.clone())
+            .app_data(copy_store.clone())
             .service(run_query)
             .service(ai::query)
             .service(databases)
@@ -143,6 +210,83 @@ async fn main() -> std::io::Result<()> {
             .service(crate::collections::create_document)
             .service(crate::collections::update_document)
             .service(crate::collections::delete_document)
+            .service(bulk::update_many_dry_run)
+            .service(bulk::update_many)
+            .service(bulk::delete_many_dry_run)
+            .service(bulk::delete_many)
+            .service(bulk::bulk_write)
+            .service(transactions::start_transaction)
+            .service(transactions::list_transactions)
+            .service(transactions::commit_transaction)
+            .service(transactions::abort_transaction)
+            .service(export::export_documents)
+            .service(import::import_documents)
+            .service(dump::dump_database)
+            .service(dump::restore_database)
+            .service(ops::tasks::export_job)
+            .service(ops::tasks::import_job)
+            .service(ops::tasks::index_job)
+            .service(ops::tasks::update_many_job)
+            .service(ops::tasks::delete_many_job)
+            .service(ops::tasks::dump_job)
+            .service(ops::tasks::orphans_job)
+            .service(ops::tasks::generate_job)
+            .service(ops::tasks::compare_job)
+            .service(generator::preview_generated)
+            .service(profiles::list_profiles)
+            .service(profiles::save_profile)
+            .service(profiles::delete_profile)
+            .service(copy::create_copy)
+            .service(copy::list_copies)
+            .service(copy::get_copy)
+            .service(copy::resume_copy)
+            .service(copy::stop_copy)
+            .service(gridfs::list_buckets)
+            .service(gridfs::list_files)
+            .service(gridfs::upload_file)
+            .service(gridfs::get_file)
+            .service(gridfs::download_file)
+            .service(gridfs::delete_file)
+            .service(ops::list_jobs)
+            .service(ops::get_job)
+            .service(ops::cancel_job)
+            .service(ops::delete_job)
+            .service(ops::download_job_result)
+            .service(revisions::document_history)
+            .service(revisions::diff_revisions)
+            .service(revisions::restore_revision)
+            .service(revisions::deleted_documents)
+            .service(views::list_views)
+            .service(views::create_view)
+            .service(views::update_view)
+            .service(views::dependent_views)
+            .service(schemas::upload_schemas)
+            .service(schemas::list_schemas)
+            .service(schemas::get_schema)
+            .service(schemas::put_schema)
+            .service(schemas::delete_schema)
+            .service(schemas::schema_history)
+            .service(schemas::diff_schema_versions)
+            .service(masking::get_masking)
+            .service(masking::put_masking)
+            .service(masking::delete_masking)
+            .service(masking::preview_masking)
+            .service(schemas::current_validator)
+            .service(schemas::dry_run_validator)
+            .service(schemas::apply_validator)
+            .service(inference::infer_collection_schema)
+            .service(inference::save_inferred_schema)
+            .service(stats::field_stats)
+            .service(stats::document_sizes)
+            .service(drift::create_snapshot)
+            .service(drift::list_snapshots)
+            .service(drift::diff_snapshots)
+            .service(relationships::list_declared)
+            .service(relationships::declare_relationship)
+            .service(relationships::remove_relationship)
+            .service(orphans::check_orphans)
+            .service(orphans::export_orphans)
+            .service(relationships::relationship_graph)
             .service(dbs::dashboard)
             .service(dbs::get_status)
         .service(monitoring::metrics)
@@ -0,0 +1,463 @@
+//! Masking rules applied to documents on their way out through export and copy.
+
+use std::{collections::BTreeMap, env, fs, sync::OnceLock};
+
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, oid::ObjectId, Binary, Bson, Document},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::export::to_json;
+use crate::profiles::ProfileStore;
+use crate::schemas::SchemaStore;
+use crate::state::AppInfo;
+use crate::store::data_dir;
+
+const KEY_FILE: &str = "masking.key";
+const DEFAULT_PREVIEW: i64 = 5;
+
+const FIRST_NAMES: &[&str] = &[
+    "Alex", "Blake", "Casey", "Dana", "Eden", "Finley", "Gray", "Harper", "Indigo", "Jordan", "Kai", "Logan", "Morgan",
+    "Noel", "Oakley", "Parker", "Quinn", "Riley", "Sage", "Taylor", "Umi", "Val", "Wren", "Yael",
+];
+const LAST_NAMES: &[&str] = &[
+    "Abbott", "Bishop", "Carver", "Dalton", "Ellison", "Fletcher", "Garner", "Hale", "Ingram", "Jensen", "Keller",
+    "Lowell", "Mercer", "Nolan", "Orwell", "Prescott", "Quill", "Ramsey", "Sutton", "Thorne", "Upton", "Vance",
+    "Whitaker", "York",
+];
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum FakeKind {
+    Name,
+    Email,
+    Phone,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum DateUnit {
+    #[default]
+    Day,
+    Month,
+    Year,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum MaskAction {
+    /// Keyed hash that keeps the BSON type (ObjectIds stay ObjectIds, ints become 64-bit ints).
+    Hash,
+    /// Realistic stand-in picked from the value's hash.
+    Fake { kind: FakeKind },
+    Null,
+    TruncateDate {
+        #[serde(default)]
+        unit: DateUnit,
+    },
+    Keep,
+}
+
+/// A collection's rule set: dotted field paths (array positions are not part of the path) to
+/// actions, plus an optional action for every field no rule covers.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MaskRules {
+    pub fields: BTreeMap<String, MaskAction>,
+    pub default_action: Option<MaskAction>,
+}
+
+/// Key mixed into every hash, so masked values cannot be reversed by hashing guesses.
+/// `GLOBE_MASKING_SECRET` when set, otherwise a random key kept in `masking.key` under the data
+/// directory; either way it is stable, so the same input masks the same way in every collection
+/// and every run.
+fn secret() -> &'static [u8] {
+    static SECRET: OnceLock<Vec<u8>> = OnceLock::new();
+    SECRET.get_or_init(|| {
+        if let Ok(s) = env::var("GLOBE_MASKING_SECRET")
+            && !s.trim().is_empty()
+        {
+            return s.trim().as_bytes().to_vec();
+        }
+        let path = data_dir().join(KEY_FILE);
+        if let Ok(key) = fs::read(&path)
+            && !key.is_empty()
+        {
+            return key;
+        }
+        let key = hex(&rand::random::<[u8; 32]>()).into_bytes();
+        if let Err(e) = fs::create_dir_all(data_dir()).and_then(|_| fs::write(&path, &key)) {
+            eprintln!("failed to save masking key {}: {}", path.display(), e);
+        }
+        key
+    })
+}
+
+pub struct Masker {
+    rules: MaskRules,
+    secret: Vec<u8>,
+}
+
+impl Masker {
+    pub fn new(rules: MaskRules) -> Self {
+        Self::with_secret(rules, secret().to_vec())
+    }
+
+    fn with_secret(rules: MaskRules, secret: Vec<u8>) -> Self {
+        Self { rules, secret }
+    }
+
+    pub fn apply(&self, doc: &mut Document) {
+        for (key, value) in doc.iter_mut() {
+            self.walk(value, key);
+        }
+    }
+
+    /// Masks a single top-level or dotted field value, e.g. an `_id` used as a lookup key.
+    pub fn mask_field(&self, path: &str, mut value: Bson) -> Bson {
+        self.walk(&mut value, path);
+        value
+    }
+
+    fn walk(&self, value: &mut Bson, path: &str) {
+        if let Some(action) = self.rules.fields.get(path) {
+            self.mask(action, value);
+            return;
+        }
+        match value {
+            Bson::Document(doc) => {
+                for (key, child) in doc.iter_mut() {
+                    self.walk(child, &format!("{}.{}", path, key));
+                }
+            }
+            Bson::Array(items) => {
+                for item in items {
+                    self.walk(item, path);
+                }
+            }
+            leaf => {
+                if let Some(action) = &self.rules.default_action {
+                    self.mask(action, leaf);
+                }
+            }
+        }
+    }
+
+    fn mask(&self, action: &MaskAction, value: &mut Bson) {
+        if let Bson::Array(items) = value
+            && *action != MaskAction::Null
+        {
+            for item in items {
+                self.mask(action, item);
+            }
+            return;
+        }
+        let masked = match action {
+            MaskAction::Keep => return,
+            MaskAction::Null => Bson::Null,
+            _ if *value == Bson::Null => return,
+            MaskAction::Hash => self.hash(value),
+            MaskAction::Fake { kind } => Bson::String(fake(*kind, &self.digest(value))),
+            MaskAction::TruncateDate { unit } => match truncate_date(value, *unit) {
+                Some(v) => v,
+                None => return,
+            },
+        };
+        *value = masked;
+    }
+
+    fn digest(&self, value: &Bson) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.secret);
+        hasher.update([0]);
+        // Int32 and Int64 of the same number hash alike, so references survive type drift.
+        match value {
+            Bson::String(s) => hasher.update(s.as_bytes()),
+            Bson::ObjectId(id) => hasher.update(id.bytes()),
+            Bson::Int32(n) => hasher.update((*n as i64).to_le_bytes()),
+            Bson::Int64(n) => hasher.update(n.to_le_bytes()),
+            Bson::Binary(b) => hasher.update(&b.bytes),
+            other => hasher.update(other.clone().into_canonical_extjson().to_string().as_bytes()),
+        }
+        hasher.finalize().into()
+    }
+
+    fn hash(&self, value: &Bson) -> Bson {
+        let digest = self.digest(value);
+        let word = u64::from_le_bytes(digest[..8].try_into().unwrap());
+        match value {
+            Bson::ObjectId(_) => Bson::ObjectId(ObjectId::from_bytes(digest[..12].try_into().unwrap())),
+            // 63 bits keeps collisions between hashed keys negligible; Int32 is widened so that
+            // equal numbers of either width still mask equal
+            Bson::Int32(_) | Bson::Int64(_) => Bson::Int64((word >> 1) as i64),
+            Bson::Double(_) => Bson::Double((word >> 11) as f64),
+            Bson::Binary(b) => Bson::Binary(Binary {
+                subtype: b.subtype,
+                bytes: digest.to_vec(),
+            }),
+            _ => Bson::String(hex(&digest[..16])),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn fake(kind: FakeKind, digest: &[u8; 32]) -> String {
+    let first = FIRST_NAMES[digest[0] as usize % FIRST_NAMES.len()];
+    let last = LAST_NAMES[digest[1] as usize % LAST_NAMES.len()];
+    let number = u32::from_le_bytes(digest[2..6].try_into().unwrap());
+    match kind {
+        FakeKind::Name => format!("{} {}", first, last),
+        FakeKind::Email => format!("{}.{}{}@example.com", first.to_lowercase(), last.to_lowercase(), number % 10_000),
+        // 555-01xx numbers are reserved for fiction
+        FakeKind::Phone => format!("+1-{:03}-555-01{:02}", 200 + number % 800, (number / 800) % 100),
+    }
+}
+
+fn truncate(dt: DateTime<Utc>, unit: DateUnit) -> DateTime<Utc> {
+    let (year, month, day) = match unit {
+        DateUnit::Day => (dt.year(), dt.month(), dt.day()),
+        DateUnit::Month => (dt.year(), dt.month(), 1),
+        DateUnit::Year => (dt.year(), 1, 1),
+    };
+    Utc.with_ymd_and_hms(year, month, day, 0, 0, 0).single().unwrap_or(dt)
+}
+
+/// Dates and RFC 3339 strings; anything else is left untouched.
+fn truncate_date(value: &Bson, unit: DateUnit) -> Option<Bson> {
+    match value {
+        Bson::DateTime(dt) => {
+            let dt = Utc.timestamp_millis_opt(dt.timestamp_millis()).single()?;
+            Some(Bson::DateTime(bson::DateTime::from_millis(truncate(dt, unit).timestamp_millis())))
+        }
+        Bson::String(s) => {
+            let dt = DateTime::parse_from_rfc3339(s).ok()?.with_timezone(&Utc);
+            Some(Bson::String(truncate(dt, unit).to_rfc3339()))
+        }
+        _ => None,
+    }
+}
+
+/// The masker for a `mask: true` export or copy of `connection`'s `database.collection`.
+pub async fn masker_for(
+    schemas: &SchemaStore,
+    connection: &str,
+    database: &str,
+    collection: &str,
+    requested: Option<bool>,
+) -> Result<Option<Masker>, String> {
+    if !requested.unwrap_or(false) {
+        return Ok(None);
+    }
+    match schemas.masking(connection, database, collection).await {
+        Some(rules) => Ok(Some(Masker::new(rules))),
+        None => Err(format!("no masking rules saved for {}.{}", database, collection)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MaskingQuery {
+    // connection profile the collection lives on; rules are kept per connection, under the same
+    // key exports and copies from that profile look them up by
+    profile: Option<String>,
+}
+
+#[get("/schemas/{db_name}/{coll_name}/masking")]
+pub async fn get_masking(
+    path: web::Path<(String, String)>,
+    query: web::Query<MaskingQuery>,
+    schemas: web::Data<SchemaStore>,
+    profiles: web::Data<ProfileStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let connection = match profiles.connection(query.profile.as_deref(), &app_info).await {
+        Ok(c) => c,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    match schemas.masking(&connection, &db_name, &coll_name).await {
+        Some(rules) => Ok(HttpResponse::Ok().json(rules)),
+        None => Ok(HttpResponse::NotFound().body("masking rules not found")),
+    }
+}
+
+#[put("/schemas/{db_name}/{coll_name}/masking")]
+pub async fn put_masking(
+    path: web::Path<(String, String)>,
+    query: web::Query<MaskingQuery>,
+    body: web::Json<MaskRules>,
+    schemas: web::Data<SchemaStore>,
+    profiles: web::Data<ProfileStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let rules = body.into_inner();
+    if let Some(path) = rules.fields.keys().find(|p| p.is_empty() || p.split('.').any(str::is_empty)) {
+        return Ok(HttpResponse::BadRequest().body(format!("invalid field path: {:?}", path)));
+    }
+    let connection = match profiles.connection(query.profile.as_deref(), &app_info).await {
+        Ok(c) => c,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    match schemas.put_masking(&connection, &db_name, &coll_name, rules.clone()).await {
+        Ok(()) => Ok(HttpResponse::Ok().json(rules)),
+        Err(e) => {
+            eprintln!("masking store error: {}", e);
+            Ok(HttpResponse::InternalServerError().body("failed to store masking rules"))
+        }
+    }
+}
+
+#[delete("/schemas/{db_name}/{coll_name}/masking")]
+pub async fn delete_masking(
+    path: web::Path<(String, String)>,
+    query: web::Query<MaskingQuery>,
+    schemas: web::Data<SchemaStore>,
+    profiles: web::Data<ProfileStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let connection = match profiles.connection(query.profile.as_deref(), &app_info).await {
+        Ok(c) => c,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    match schemas.delete_masking(&connection, &db_name, &coll_name).await {
+        Ok(true) => Ok(HttpResponse::Ok().json(json!({"deleted": true}))),
+        Ok(false) => Ok(HttpResponse::NotFound().body("masking rules not found")),
+        Err(e) => {
+            eprintln!("masking store error: {}", e);
+            Ok(HttpResponse::InternalServerError().body("failed to delete masking rules"))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PreviewRequest {
+    // rules to try; defaults to the saved ones
+    rules: Option<MaskRules>,
+    sample: Option<i64>,
+}
+
+/// Shows a few documents before and after masking, to check a rule set before using it.
+#[post("/schemas/{db_name}/{coll_name}/masking/preview")]
+pub async fn preview_masking(
+    path: web::Path<(String, String)>,
+    query: web::Query<MaskingQuery>,
+    body: Option<web::Json<PreviewRequest>>,
+    data: web::Data<Client>,
+    schemas: web::Data<SchemaStore>,
+    profiles: web::Data<ProfileStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let req = body.map(|b| b.into_inner()).unwrap_or(PreviewRequest { rules: None, sample: None });
+    let profile = query.profile.as_deref();
+    let (connection, client) = match (
+        profiles.connection(profile, &app_info).await,
+        profiles.client(profile, &data, &app_info).await,
+    ) {
+        (Ok(c), Ok(client)) => (c, client),
+        (Err(e), _) | (_, Err(e)) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let rules = match req.rules {
+        Some(r) => r,
+        None => match schemas.masking(&connection, &db_name, &coll_name).await {
+            Some(r) => r,
+            None => return Ok(HttpResponse::NotFound().body("masking rules not found")),
+        },
+    };
+    let masker = Masker::new(rules);
+    let coll = client.database(&db_name).collection::<Document>(&coll_name);
+    let sample = req.sample.filter(|s| *s > 0).unwrap_or(DEFAULT_PREVIEW);
+    let docs: Vec<Document> = match coll.find(doc! {}).limit(sample).await {
+        Ok(cursor) => match cursor.try_collect().await {
+            Ok(docs) => docs,
+            Err(e) => {
+                eprintln!("masking preview error: {}", e);
+                return Ok(HttpResponse::InternalServerError().body(format!("preview failed: {}", e)));
+            }
+        },
+        Err(e) => {
+            eprintln!("masking preview error: {}", e);
+            return Ok(HttpResponse::InternalServerError().body(format!("preview failed: {}", e)));
+        }
+    };
+    let pairs: Vec<_> = docs
+        .into_iter()
+        .map(|original| {
+            let mut masked = original.clone();
+            masker.apply(&mut masked);
+            json!({ "original": to_json(&original), "masked": to_json(&masked) })
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(pairs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn masker(rules: serde_json::Value) -> Masker {
+        Masker::with_secret(serde_json::from_value(rules).unwrap(), b"test".to_vec())
+    }
+
+    #[test]
+    fn masks_deterministically_by_path() {
+        let m = masker(json!({
+            "fields": {
+                "_id": { "action": "hash" },
+                "customer.email": { "action": "fake", "kind": "email" },
+                "customer.ssn": { "action": "null" },
+                "tags": { "action": "hash" },
+                "born": { "action": "truncateDate", "unit": "year" },
+            }
+        }));
+        let id = ObjectId::parse_str("64b7f0c2a1b2c3d4e5f60718").unwrap();
+        let born = Utc.with_ymd_and_hms(1990, 7, 14, 9, 30, 0).unwrap();
+        let mut doc = doc! {
+            "_id": id,
+            "customer": { "email": "jane@corp.com", "ssn": "123-45-6789", "name": "Jane" },
+            "tags": ["a", "b"],
+            "born": bson::DateTime::from_millis(born.timestamp_millis()),
+        };
+        m.apply(&mut doc);
+
+        let masked_id = doc.get_object_id("_id").unwrap();
+        assert_ne!(masked_id, id);
+        // the same value masks the same way wherever it appears
+        assert_eq!(m.mask_field("_id", Bson::ObjectId(id)), Bson::ObjectId(masked_id));
+        let customer = doc.get_document("customer").unwrap();
+        assert!(customer.get_str("email").unwrap().ends_with("@example.com"));
+        assert_eq!(customer.get("ssn"), Some(&Bson::Null));
+        assert_eq!(customer.get_str("name"), Ok("Jane"));
+        assert_eq!(doc.get_array("tags").unwrap().len(), 2);
+        assert_eq!(
+            doc.get_datetime("born").unwrap().timestamp_millis(),
+            Utc.with_ymd_and_hms(1990, 1, 1, 0, 0, 0).unwrap().timestamp_millis()
+        );
+    }
+
+    #[test]
+    fn default_action_skips_kept_fields() {
+        let m = masker(json!({
+            "fields": { "_id": { "action": "keep" }, "address": { "action": "keep" } },
+            "defaultAction": { "action": "hash" },
+        }));
+        let mut doc = doc! { "_id": 1, "address": { "city": "Oslo" }, "name": "Jane", "age": 41 };
+        m.apply(&mut doc);
+        assert_eq!(doc.get_i32("_id"), Ok(1));
+        assert_eq!(doc.get_document("address").unwrap().get_str("city"), Ok("Oslo"));
+        assert_ne!(doc.get_str("name"), Ok("Jane"));
+        assert_ne!(doc.get_i32("age"), Ok(41));
+        // ints are widened, so Int32 and Int64 of one value still mask alike
+        assert_eq!(doc.get("age"), Some(&m.mask_field("age", Bson::Int64(41))));
+    }
+}
@@ -1,14 +1,521 @@
-use actix_web::{web, HttpResponse};
-use mongodb::{bson::doc, Client, error::Result as MongoResult};
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, File, OpenOptions},
+    future::Future,
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-pub async fn create_document() -> HttpResponse {
-    HttpResponse::NotImplemented().body("create_document not implemented")
+use actix_web::{delete, get, post, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use tokio::{
+    sync::{Mutex, OwnedSemaphorePermit, Semaphore},
+    task::AbortHandle,
+};
+
+use crate::dump::file_stream;
+use crate::store::{data_dir, JsonStore};
+
+pub mod tasks;
+
+const JOBS_FILE: &str = "jobs.json";
+const DEFAULT_RETAIN: usize = 100;
+const DEFAULT_CONCURRENCY: usize = 2;
+// Log lines written per job; later ones are dropped, apart from the final failed/cancelled line.
+const MAX_LOG_LINES: usize = 1000;
+// How often a running job's progress is written to the job file; reads see it live.
+const PROGRESS_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, JobStatus::Succeeded | JobStatus::Failed | JobStatus::Cancelled)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLine {
+    pub at: DateTime<Utc>,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+    pub id: String,
+    pub kind: String,
+    pub description: String,
+    pub params: JsonValue,
+    pub status: JobStatus,
+    // 0-100; stays 0 for tasks that cannot estimate their total
+    pub progress: f64,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub result: Option<JsonValue>,
+    pub error: Option<String>,
+    // File name under data_dir()/jobs, served by /jobs/{id}/download.
+    pub result_file: Option<String>,
+}
+
+impl Job {
+    pub fn summary(&self, with_logs: bool) -> JsonValue {
+        let mut value = serde_json::to_value(self).unwrap_or(JsonValue::Null);
+        if let Some(obj) = value.as_object_mut() {
+            if with_logs {
+                obj.insert("logs".to_string(), json!(read_logs(&self.id)));
+            }
+            let download = self.result_file.as_ref().map(|_| format!("/jobs/{}/download", self.id));
+            obj.insert("download".to_string(), json!(download));
+        }
+        value
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct JobsFile {
+    jobs: Vec<Job>,
+}
+
+/// What a finished task hands back: a JSON result and optionally a file to download.
+pub struct JobOutcome {
+    pub result: JsonValue,
+    pub file: Option<PathBuf>,
+}
+
+impl JobOutcome {
+    pub fn result(result: JsonValue) -> Self {
+        Self { result, file: None }
+    }
+}
+
+fn jobs_dir() -> PathBuf {
+    data_dir().join("jobs")
+}
+
+// Logs live in their own NDJSON file per job, so logging appends a line instead of rewriting
+// jobs.json.
+fn log_path(id: &str) -> PathBuf {
+    jobs_dir().join("logs").join(format!("{}.ndjson", id))
+}
+
+fn append_log(id: &str, message: String) {
+    let path = log_path(id);
+    let line = LogLine { at: Utc::now(), message };
+    let written = fs::create_dir_all(jobs_dir().join("logs")).and_then(|_| {
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let mut bytes = serde_json::to_vec(&line).map_err(std::io::Error::other)?;
+        bytes.push(b'\n');
+        file.write_all(&bytes)
+    });
+    if let Err(e) = written {
+        eprintln!("failed to write log for job {}: {}", id, e);
+    }
+}
+
+fn read_logs(id: &str) -> Vec<LogLine> {
+    let Ok(file) = File::open(log_path(id)) else {
+        return Vec::new();
+    };
+    BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect()
+}
+
+/// Background jobs persisted in `jobs.json`, so the table (statuses, results) survives a
+/// restart; tasks that were still running when globe stopped are marked failed on startup.
+/// At most `GLOBE_JOB_CONCURRENCY` run at once, the rest wait as queued.
+pub struct JobStore {
+    store: JsonStore<JobsFile>,
+    running: Mutex<HashMap<String, AbortHandle>>,
+    // latest progress of running jobs, ahead of what was last saved
+    live: std::sync::Mutex<HashMap<String, f64>>,
+    slots: Arc<Semaphore>,
+    retain: usize,
+}
+
+impl JobStore {
+    pub fn new() -> Self {
+        let retain = env::var("GLOBE_JOBS_RETAIN")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(DEFAULT_RETAIN);
+        let concurrency = env::var("GLOBE_JOB_CONCURRENCY")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(DEFAULT_CONCURRENCY);
+        Self {
+            store: JsonStore::open(JOBS_FILE),
+            running: Mutex::new(HashMap::new()),
+            live: std::sync::Mutex::new(HashMap::new()),
+            slots: Arc::new(Semaphore::new(concurrency)),
+            retain,
+        }
+    }
+
+    /// Marks jobs left queued or running by a previous process as failed.
+    pub async fn recover(&self) {
+        let result = self
+            .store
+            .update(|file| {
+                for job in file.jobs.iter_mut().filter(|j| !j.status.is_finished()) {
+                    job.status = JobStatus::Failed;
+                    job.finished_at = Some(Utc::now());
+                    job.error = Some("interrupted by a restart".to_string());
+                }
+            })
+            .await;
+        if let Err(e) = result {
+            eprintln!("failed to recover jobs: {}", e);
+        }
+    }
+
+    pub async fn get(&self, id: &str) -> Option<Job> {
+        let job = self.store.read(|file| file.jobs.iter().find(|j| j.id == id).cloned()).await?;
+        Some(self.with_live_progress(job))
+    }
+
+    fn with_live_progress(&self, mut job: Job) -> Job {
+        if let Some(progress) = self.live.lock().unwrap().get(&job.id) {
+            job.progress = *progress;
+        }
+        job
+    }
+
+    async fn modify(&self, id: &str, f: impl FnOnce(&mut Job)) {
+        let result = self
+            .store
+            .update(|file| {
+                if let Some(job) = file.jobs.iter_mut().find(|j| j.id == id) {
+                    f(job);
+                }
+            })
+            .await;
+        if let Err(e) = result {
+            eprintln!("failed to save job {}: {}", id, e);
+        }
+    }
+
+    async fn insert(&self, job: Job) -> std::io::Result<()> {
+        let retain = self.retain;
+        let pruned = self
+            .store
+            .update(|file| {
+                file.jobs.push(job);
+                let finished = file.jobs.iter().filter(|j| j.status.is_finished()).count();
+                let mut excess = finished.saturating_sub(retain);
+                let mut pruned = Vec::new();
+                file.jobs.retain(|j| {
+                    if excess > 0 && j.status.is_finished() {
+                        excess -= 1;
+                        pruned.push(j.id.clone());
+                        pruned.extend(j.result_file.clone());
+                        return false;
+                    }
+                    true
+                });
+                pruned
+            })
+            .await?;
+        for name in pruned {
+            let _ = fs::remove_file(jobs_dir().join(&name));
+            let _ = fs::remove_file(log_path(&name));
+        }
+        Ok(())
+    }
+
+    /// Records a job and runs `task` in the background once a slot is free.
+    pub async fn start<F, Fut>(
+        jobs: &web::Data<JobStore>,
+        kind: &str,
+        description: String,
+        params: JsonValue,
+        task: F,
+    ) -> std::io::Result<Job>
+    where
+        F: FnOnce(JobContext) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<JobOutcome, String>> + Send + 'static,
+    {
+        let job = Job {
+            id: mongodb::bson::oid::ObjectId::new().to_hex(),
+            kind: kind.to_string(),
+            description,
+            params,
+            status: JobStatus::Queued,
+            progress: 0.0,
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            result: None,
+            error: None,
+            result_file: None,
+        };
+        jobs.insert(job.clone()).await?;
+
+        let slot = Arc::new(std::sync::Mutex::new(None));
+        let ctx = JobContext {
+            id: job.id.clone(),
+            jobs: jobs.clone(),
+            slot: slot.clone(),
+            logged: Arc::new(AtomicUsize::new(0)),
+            progress_saved: Arc::new(std::sync::Mutex::new(None)),
+        };
+        let store = jobs.clone();
+        let id = job.id.clone();
+        // held across the spawn so the task cannot deregister itself before it is registered
+        let mut running = jobs.running.lock().await;
+        let handle = tokio::spawn(async move {
+            *slot.lock().unwrap() = store.slots.clone().acquire_owned().await.ok();
+            store
+                .modify(&id, |j| {
+                    j.status = JobStatus::Running;
+                    j.started_at = Some(Utc::now());
+                })
+                .await;
+            let outcome = task(ctx).await;
+            slot.lock().unwrap().take();
+            store.live.lock().unwrap().remove(&id);
+            store
+                .modify(&id, |j| {
+                    if j.status.is_finished() {
+                        return;
+                    }
+                    j.finished_at = Some(Utc::now());
+                    match outcome {
+                        Ok(outcome) => {
+                            j.status = JobStatus::Succeeded;
+                            j.progress = 100.0;
+                            j.result = Some(outcome.result);
+                            j.result_file = outcome
+                                .file
+                                .and_then(|f| f.file_name().map(|n| n.to_string_lossy().into_owned()));
+                        }
+                        Err(e) => {
+                            j.status = JobStatus::Failed;
+                            append_log(&j.id, format!("failed: {}", e));
+                            j.error = Some(e);
+                        }
+                    }
+                })
+                .await;
+            store.running.lock().await.remove(&id);
+        });
+        running.insert(job.id.clone(), handle.abort_handle());
+        Ok(job)
+    }
+
+    /// Stops a queued or running job and removes its partial output. A job that finished in the
+    /// meantime keeps its status and output.
+    pub async fn cancel(&self, id: &str) -> Option<Job> {
+        if let Some(handle) = self.running.lock().await.remove(id) {
+            handle.abort();
+        }
+        self.live.lock().unwrap().remove(id);
+        self.modify(id, |j| {
+            if !j.status.is_finished() {
+                j.status = JobStatus::Cancelled;
+                j.finished_at = Some(Utc::now());
+                append_log(id, "cancelled".to_string());
+            }
+        })
+        .await;
+        let job = self.get(id).await?;
+        if job.status == JobStatus::Cancelled {
+            remove_outputs(id);
+        }
+        Some(job)
+    }
+
+    async fn remove(&self, id: &str) -> std::io::Result<Option<Job>> {
+        let removed = self
+            .store
+            .update(|file| {
+                let index = file.jobs.iter().position(|j| j.id == id)?;
+                Some(file.jobs.remove(index))
+            })
+            .await?;
+        if let Some(file) = removed.as_ref().and_then(|j| j.result_file.as_ref()) {
+            let _ = fs::remove_file(jobs_dir().join(file));
+        }
+        let _ = fs::remove_file(log_path(id));
+        Ok(removed)
+    }
+}
+
+// Partial output files are named after the job id (see `JobContext::output_path`).
+fn remove_outputs(id: &str) {
+    if let Ok(entries) = fs::read_dir(jobs_dir()) {
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with(id) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+/// Handle a running task uses to report progress and log lines.
+#[derive(Clone)]
+pub struct JobContext {
+    id: String,
+    jobs: web::Data<JobStore>,
+    slot: Arc<std::sync::Mutex<Option<OwnedSemaphorePermit>>>,
+    logged: Arc<AtomicUsize>,
+    progress_saved: Arc<std::sync::Mutex<Option<Instant>>>,
+}
+
+impl JobContext {
+    pub async fn log(&self, message: impl Into<String>) {
+        match self.logged.fetch_add(1, Ordering::Relaxed) {
+            n if n < MAX_LOG_LINES => append_log(&self.id, message.into()),
+            n if n == MAX_LOG_LINES => {
+                append_log(&self.id, format!("log limit of {} lines reached; further lines dropped", MAX_LOG_LINES))
+            }
+            _ => {}
+        }
+    }
+
+    /// Kept in memory for readers and written to the job file at most every
+    /// `PROGRESS_SAVE_INTERVAL`, so frequent updates do not rewrite it each time.
+    pub async fn progress(&self, percent: f64) {
+        let percent = percent.clamp(0.0, 100.0);
+        self.jobs.live.lock().unwrap().insert(self.id.clone(), percent);
+        let due = {
+            let mut saved = self.progress_saved.lock().unwrap();
+            let due = saved.is_none_or(|at| at.elapsed() >= PROGRESS_SAVE_INTERVAL);
+            if due {
+                *saved = Some(Instant::now());
+            }
+            due
+        };
+        if due {
+            self.jobs.modify(&self.id, |j| j.progress = percent).await;
+        }
+    }
+
+    /// Gives the concurrency slot back early, for tasks that keep running until cancelled
+    /// (change-stream followers) and would otherwise hold up the queue forever.
+    pub fn release_slot(&self) {
+        self.slot.lock().unwrap().take();
+    }
+
+    /// Where the task writes its downloadable result.
+    pub fn output_path(&self, extension: &str) -> std::io::Result<PathBuf> {
+        let dir = jobs_dir();
+        fs::create_dir_all(&dir)?;
+        Ok(dir.join(format!("{}.{}", self.id, extension)))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct JobListQuery {
+    status: Option<String>,
+    kind: Option<String>,
+}
+
+#[get("/jobs")]
+pub async fn list_jobs(query: web::Query<JobListQuery>, jobs: web::Data<JobStore>) -> actix_web::Result<HttpResponse> {
+    let listed: Vec<JsonValue> = jobs
+        .store
+        .read(|file| {
+            file.jobs
+                .iter()
+                .rev()
+                .filter(|j| {
+                    let status = serde_json::to_value(j.status).ok();
+                    query.status.as_deref().is_none_or(|s| status.as_ref().and_then(JsonValue::as_str) == Some(s))
+                        && query.kind.as_deref().is_none_or(|k| j.kind == k)
+                })
+                .map(|j| jobs.with_live_progress(j.clone()).summary(false))
+                .collect()
+        })
+        .await;
+    Ok(HttpResponse::Ok().json(listed))
+}
+
+#[get("/jobs/{id}")]
+pub async fn get_job(path: web::Path<String>, jobs: web::Data<JobStore>) -> actix_web::Result<HttpResponse> {
+    match jobs.get(&path.into_inner()).await {
+        Some(job) => Ok(HttpResponse::Ok().json(job.summary(true))),
+        None => Ok(HttpResponse::NotFound().body("job not found")),
+    }
+}
+
+#[post("/jobs/{id}/cancel")]
+pub async fn cancel_job(path: web::Path<String>, jobs: web::Data<JobStore>) -> actix_web::Result<HttpResponse> {
+    let id = path.into_inner();
+    match jobs.get(&id).await {
+        None => return Ok(HttpResponse::NotFound().body("job not found")),
+        Some(job) if job.status.is_finished() => {
+            return Ok(HttpResponse::Conflict().body("job has already finished"));
+        }
+        Some(_) => {}
+    }
+    match jobs.cancel(&id).await {
+        Some(job) => Ok(HttpResponse::Ok().json(job.summary(false))),
+        None => Ok(HttpResponse::NotFound().body("job not found")),
+    }
 }
 
-pub async fn update_document() -> HttpResponse {
-    HttpResponse::NotImplemented().body("update_document not implemented")
+#[delete("/jobs/{id}")]
+pub async fn delete_job(path: web::Path<String>, jobs: web::Data<JobStore>) -> actix_web::Result<HttpResponse> {
+    let id = path.into_inner();
+    match jobs.get(&id).await {
+        None => return Ok(HttpResponse::NotFound().body("job not found")),
+        Some(job) if !job.status.is_finished() => {
+            return Ok(HttpResponse::Conflict().body("job is still running; cancel it first"));
+        }
+        Some(_) => {}
+    }
+    match jobs.remove(&id).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(json!({ "deleted": id }))),
+        Err(e) => {
+            eprintln!("job delete error: {}", e);
+            Ok(HttpResponse::InternalServerError().body(format!("failed to delete job: {}", e)))
+        }
+    }
 }
 
-pub async fn delete_document() -> HttpResponse {
-    HttpResponse::NotImplemented().body("delete_document not implemented")
+#[get("/jobs/{id}/download")]
+pub async fn download_job_result(path: web::Path<String>, jobs: web::Data<JobStore>) -> actix_web::Result<HttpResponse> {
+    let Some(job) = jobs.get(&path.into_inner()).await else {
+        return Ok(HttpResponse::NotFound().body("job not found"));
+    };
+    let Some(file) = job.result_file else {
+        return Ok(HttpResponse::NotFound().body("job has no result file"));
+    };
+    let download_name = job
+        .params
+        .get("downloadName")
+        .and_then(JsonValue::as_str)
+        .map(str::to_string)
+        .unwrap_or_else(|| file.clone());
+    match file_stream(jobs_dir().join(&file), false) {
+        Ok(body) => Ok(HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", download_name)))
+            .streaming(body)),
+        Err(e) => Ok(HttpResponse::Gone().body(format!("result file is no longer available: {}", e))),
+    }
 }
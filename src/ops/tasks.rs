@@ -0,0 +1,559 @@
+//! Endpoints that start long-running work as background jobs instead of holding the request open.
+
+use std::{
+    fs::File,
+    future::{Future, IntoFuture},
+    io::{BufWriter, Write},
+    time::Duration,
+};
+
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, Bson, Document},
+    error::ErrorKind,
+    Client,
+};
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+use tokio::sync::mpsc;
+
+use super::{JobContext, JobOutcome, JobStore};
+use crate::bulk::{confirmed_run, execute, BulkKind, ConfirmRequest, DryRun, DryRunStore};
+use crate::compare::{compare_collections, CompareRequest};
+use crate::copy::collection;
+use crate::dump::{split_list, write_dump, DumpQuery};
+use crate::export::{estimate, json_document, open_cursor, Encoder, ExportRequest};
+use crate::generator::{self, build_model, GenerateRequest};
+use crate::import::{read_import, run_import};
+use crate::masking::masker_for;
+use crate::orphans::{check_relationship, OrphanCheckRequest};
+use crate::profiles::ProfileStore;
+use crate::relationships::RelationshipStore;
+use crate::revisions::RevisionStore;
+use crate::schemas::SchemaStore;
+use crate::state::AppInfo;
+
+const PROGRESS_EVERY: u64 = 1000;
+const GENERATE_BATCH_SIZE: usize = 1000;
+const INDEX_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+pub fn accepted(started: std::io::Result<super::Job>) -> actix_web::Result<HttpResponse> {
+    match started {
+        Ok(job) => Ok(HttpResponse::Accepted().json(job.summary(false))),
+        Err(e) => {
+            eprintln!("job start error: {}", e);
+            Ok(HttpResponse::InternalServerError().body(format!("failed to start job: {}", e)))
+        }
+    }
+}
+
+#[post("/jobs/export/{db_name}/{coll_name}")]
+pub async fn export_job(
+    path: web::Path<(String, String)>,
+    body: web::Json<ExportRequest>,
+    data: web::Data<Client>,
+    schemas: web::Data<SchemaStore>,
+    app_info: web::Data<AppInfo>,
+    jobs: web::Data<JobStore>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let req = body.into_inner();
+    let format = req.format.unwrap_or_default();
+    let mask = match masker_for(&schemas, &app_info.shortened_uri, &db_name, &coll_name, req.mask).await {
+        Ok(m) => m,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let params = json!({
+        "database": db_name,
+        "collection": coll_name,
+        "format": format.extension(),
+        "masked": req.mask.unwrap_or(false),
+        "downloadName": format!("{}.{}", coll_name, format.extension()),
+    });
+    let description = format!("Export {}.{} as {}", db_name, coll_name, format.extension());
+    let client = data.get_ref().clone();
+
+    accepted(
+        JobStore::start(&jobs, "export", description, params, move |ctx| async move {
+            let total = estimate(&client, &db_name, &coll_name, &req.query).await;
+            let mut cursor = open_cursor(&client, &db_name, &coll_name, &req.query).await?;
+            let path = ctx.output_path(format.extension()).map_err(|e| e.to_string())?;
+            let mut out = BufWriter::new(File::create(&path).map_err(|e| e.to_string())?);
+            let mut encoder = Encoder::new(format, req.csv).masked(mask);
+
+            out.write_all(encoder.start().as_bytes()).map_err(|e| e.to_string())?;
+            let mut written = 0u64;
+            while let Some(doc) = cursor.try_next().await.map_err(|e| e.to_string())? {
+                out.write_all(encoder.encode(&doc).as_bytes()).map_err(|e| e.to_string())?;
+                written += 1;
+                if written.is_multiple_of(PROGRESS_EVERY)
+                    && let Some(total) = total.filter(|t| *t > 0)
+                {
+                    ctx.progress(written as f64 * 100.0 / total as f64).await;
+                }
+            }
+            out.write_all(encoder.finish().as_bytes()).map_err(|e| e.to_string())?;
+            out.flush().map_err(|e| e.to_string())?;
+            ctx.log(format!("exported {} documents", written)).await;
+            Ok(JobOutcome {
+                result: json!({ "documents": written }),
+                file: Some(path),
+            })
+        })
+        .await,
+    )
+}
+
+/// Same upload as `/import/{db}/{coll}`; row errors end up in the job log.
+#[post("/jobs/import/{db_name}/{coll_name}")]
+pub async fn import_job(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    mut payload: web::Payload,
+    data: web::Data<Client>,
+    jobs: web::Data<JobStore>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let (options, rows) = match read_import(&req, &mut payload).await {
+        Ok(parsed) => parsed,
+        Err(resp) => return Ok(resp),
+    };
+    let params = json!({ "database": db_name, "collection": coll_name, "rows": rows.len() });
+    let description = format!("Import {} rows into {}.{}", rows.len(), db_name, coll_name);
+    let coll = data.database(&db_name).collection::<Document>(&coll_name);
+
+    accepted(
+        JobStore::start(&jobs, "import", description, params, move |ctx| async move {
+            let (tx, mut rx) = mpsc::unbounded_channel::<JsonValue>();
+            let import = async move {
+                run_import(&coll, rows, &options, |event| {
+                    let _ = tx.send(event);
+                })
+                .await
+            };
+            let report = async {
+                while let Some(event) = rx.recv().await {
+                    match event.get("event").and_then(JsonValue::as_str) {
+                        Some("progress") => {
+                            ctx.progress(event.get("percent").and_then(JsonValue::as_f64).unwrap_or(0.0)).await;
+                        }
+                        Some("error") => {
+                            let row = event.get("row").cloned().unwrap_or(JsonValue::Null);
+                            let error = event.get("error").and_then(JsonValue::as_str).unwrap_or_default();
+                            ctx.log(format!("row {}: {}", row, error)).await;
+                        }
+                        _ => ctx.log(event.to_string()).await,
+                    }
+                }
+            };
+            let (counts, _) = tokio::join!(import, report);
+            Ok(JobOutcome::result(serde_json::to_value(counts).unwrap_or(JsonValue::Null)))
+        })
+        .await,
+    )
+}
+
+#[derive(Deserialize)]
+pub struct IndexJobRequest {
+    keys: JsonValue,
+    // createIndexes options: name, unique, sparse, partialFilterExpression, expireAfterSeconds, ...
+    options: Option<JsonValue>,
+}
+
+/// The name the server and shell give an index by default, e.g. `email_1_createdAt_-1`.
+fn default_index_name(keys: &Document) -> String {
+    keys.iter()
+        .map(|(field, direction)| {
+            let direction = match direction {
+                Bson::String(s) => s.clone(),
+                Bson::Int32(v) => v.to_string(),
+                Bson::Int64(v) => v.to_string(),
+                Bson::Double(v) => (*v as i64).to_string(),
+                other => other.to_string(),
+            };
+            format!("{}_{}", field, direction)
+        })
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Builds the index in the background, polling `currentOp` for the server's own progress.
+/// Cancelling the job kills the build on the server.
+#[post("/jobs/indexes/{db_name}/{coll_name}")]
+pub async fn index_job(
+    path: web::Path<(String, String)>,
+    body: web::Json<IndexJobRequest>,
+    data: web::Data<Client>,
+    jobs: web::Data<JobStore>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let req = body.into_inner();
+    let keys = match bson::to_bson(&req.keys) {
+        Ok(Bson::Document(d)) if !d.is_empty() => d,
+        _ => return Ok(HttpResponse::BadRequest().body("keys must be a non-empty object")),
+    };
+    let mut index = match req.options.as_ref().map(bson::to_bson) {
+        None => Document::new(),
+        Some(Ok(Bson::Document(d))) => d,
+        Some(_) => return Ok(HttpResponse::BadRequest().body("options must be an object")),
+    };
+    if !index.contains_key("name") {
+        index.insert("name", default_index_name(&keys));
+    }
+    index.insert("key", keys);
+    let name = index.get_str("name").unwrap_or_default().to_string();
+    let params = json!({ "database": db_name, "collection": coll_name, "index": name });
+    let description = format!("Build index {} on {}.{}", name, db_name, coll_name);
+    let client = data.get_ref().clone();
+
+    accepted(
+        JobStore::start(&jobs, "index", description, params, move |ctx| async move {
+            let db = client.database(&db_name);
+            let build = db.run_command(doc! { "createIndexes": coll_name.as_str(), "indexes": [index] }).into_future();
+            tokio::pin!(build);
+            let namespace = format!("{}.{}", db_name, coll_name);
+            let mut guard = IndexBuildGuard {
+                client: client.clone(),
+                namespace: namespace.clone(),
+                name: name.clone(),
+                finished: false,
+            };
+            let mut poll = tokio::time::interval(INDEX_POLL_INTERVAL);
+            loop {
+                tokio::select! {
+                    result = &mut build => {
+                        guard.finished = true;
+                        let reply = result.map_err(|e| e.to_string())?;
+                        ctx.log(format!("index {} built", name)).await;
+                        return Ok(JobOutcome::result(serde_json::to_value(reply).unwrap_or(JsonValue::Null)));
+                    }
+                    _ = poll.tick() => {
+                        let ops = client
+                            .database("admin")
+                            .run_command(doc! { "currentOp": 1, "ns": namespace.as_str(), "command.createIndexes": { "$exists": true } })
+                            .await;
+                        let progress = ops.ok().and_then(|reply| {
+                            let op = reply.get_array("inprog").ok()?.first()?.as_document()?.clone();
+                            let progress = op.get_document("progress").ok()?;
+                            let done = progress.get("done").and_then(number)?;
+                            let total = progress.get("total").and_then(number).filter(|t| *t > 0.0)?;
+                            Some(done * 100.0 / total)
+                        });
+                        if let Some(percent) = progress {
+                            ctx.progress(percent).await;
+                        }
+                    }
+                }
+            }
+        })
+        .await,
+    )
+}
+
+/// Kills the server-side build if the job is dropped (cancelled) before `createIndexes` returns;
+/// otherwise the server would keep building the index after the job reports it cancelled.
+struct IndexBuildGuard {
+    client: Client,
+    namespace: String,
+    name: String,
+    finished: bool,
+}
+
+impl Drop for IndexBuildGuard {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let (client, namespace, name) = (self.client.clone(), self.namespace.clone(), self.name.clone());
+        tokio::spawn(async move {
+            if let Err(e) = kill_index_build(&client, &namespace, &name).await {
+                eprintln!("failed to stop index build {} on {}: {}", name, namespace, e);
+            }
+        });
+    }
+}
+
+async fn kill_index_build(client: &Client, namespace: &str, name: &str) -> mongodb::error::Result<()> {
+    let admin = client.database("admin");
+    let reply = admin
+        .run_command(doc! { "currentOp": 1, "ns": namespace, "command.createIndexes": { "$exists": true }, "command.indexes.name": name })
+        .await?;
+    for op in reply.get_array("inprog").map(|ops| ops.as_slice()).unwrap_or_default() {
+        if let Some(opid) = op.as_document().and_then(|o| o.get("opid")) {
+            admin.run_command(doc! { "killOp": 1, "op": opid.clone() }).await?;
+        }
+    }
+    Ok(())
+}
+
+fn number(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Int32(v) => Some(*v as f64),
+        Bson::Int64(v) => Some(*v as f64),
+        Bson::Double(v) => Some(*v),
+        _ => None,
+    }
+}
+
+fn start_bulk(
+    jobs: web::Data<JobStore>,
+    client: Client,
+    revisions: web::Data<RevisionStore>,
+    run: DryRun,
+    kind: BulkKind,
+    db_name: &str,
+    coll_name: &str,
+) -> impl Future<Output = std::io::Result<super::Job>> {
+    let operation = match kind {
+        BulkKind::Update => "updateMany",
+        BulkKind::Delete => "deleteMany",
+    };
+    let params = json!({ "database": db_name, "collection": coll_name, "operation": operation });
+    let description = format!("{} on {}.{}", operation, db_name, coll_name);
+    async move {
+        JobStore::start(&jobs, "bulk", description, params, move |_ctx: JobContext| async move {
+            execute(&client, &revisions, run).await.map(JobOutcome::result)
+        })
+        .await
+    }
+}
+
+/// Confirms an update-many dry run and runs it as a job.
+#[post("/jobs/bulk/{db_name}/{coll_name}/update-many")]
+pub async fn update_many_job(
+    path: web::Path<(String, String)>,
+    body: web::Json<ConfirmRequest>,
+    data: web::Data<Client>,
+    dry_runs: web::Data<DryRunStore>,
+    revisions: web::Data<RevisionStore>,
+    jobs: web::Data<JobStore>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let run = match confirmed_run(&dry_runs, &body.token, &db_name, &coll_name, BulkKind::Update).await {
+        Ok(run) => run,
+        Err(resp) => return Ok(resp),
+    };
+    accepted(start_bulk(jobs, data.get_ref().clone(), revisions, run, BulkKind::Update, &db_name, &coll_name).await)
+}
+
+/// Confirms a delete-many dry run and runs it as a job.
+#[post("/jobs/bulk/{db_name}/{coll_name}/delete-many")]
+pub async fn delete_many_job(
+    path: web::Path<(String, String)>,
+    body: web::Json<ConfirmRequest>,
+    data: web::Data<Client>,
+    dry_runs: web::Data<DryRunStore>,
+    revisions: web::Data<RevisionStore>,
+    jobs: web::Data<JobStore>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let run = match confirmed_run(&dry_runs, &body.token, &db_name, &coll_name, BulkKind::Delete).await {
+        Ok(run) => run,
+        Err(resp) => return Ok(resp),
+    };
+    accepted(start_bulk(jobs, data.get_ref().clone(), revisions, run, BulkKind::Delete, &db_name, &coll_name).await)
+}
+
+#[post("/jobs/dump/{db_name}")]
+pub async fn dump_job(
+    path: web::Path<String>,
+    query: web::Query<DumpQuery>,
+    data: web::Data<Client>,
+    jobs: web::Data<JobStore>,
+) -> actix_web::Result<HttpResponse> {
+    let db_name = path.into_inner();
+    let query = query.into_inner();
+    let params = json!({
+        "database": db_name,
+        "collections": query.collections,
+        "gzip": query.gzip,
+        "downloadName": format!("{}.tar", db_name),
+    });
+    let description = format!("Dump {}", db_name);
+    let client = data.get_ref().clone();
+
+    accepted(
+        JobStore::start(&jobs, "dump", description, params, move |ctx| async move {
+            let path = ctx.output_path("tar").map_err(|e| e.to_string())?;
+            let collections = split_list(query.collections.as_deref());
+            let dumped = write_dump(&client, &db_name, collections.as_deref(), query.gzip.unwrap_or(false), &path).await?;
+            for c in &dumped {
+                ctx.log(format!("{}: {} documents, {} indexes", c.name, c.documents, c.indexes)).await;
+            }
+            Ok(JobOutcome {
+                result: json!({ "collections": dumped }),
+                file: Some(path),
+            })
+        })
+        .await,
+    )
+}
+
+/// Inserts `count` generated documents (see `/generate/{db}/{coll}/preview`) in batches.
+#[post("/jobs/generate/{db_name}/{coll_name}")]
+pub async fn generate_job(
+    path: web::Path<(String, String)>,
+    body: web::Json<GenerateRequest>,
+    data: web::Data<Client>,
+    schemas: web::Data<SchemaStore>,
+    app_info: web::Data<AppInfo>,
+    jobs: web::Data<JobStore>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let req = body.into_inner();
+    let Some(count) = req.count.filter(|c| *c > 0) else {
+        return Ok(HttpResponse::BadRequest().body("count must be a positive number"));
+    };
+    let (source, model) = match build_model(&req, &data, &schemas, &app_info, &db_name, &coll_name).await {
+        Ok(m) => m,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let seed = req.seed();
+    let target = req.into.clone().unwrap_or_else(|| coll_name.clone());
+    let batch_size = req.batch_size.filter(|b| *b > 0).unwrap_or(GENERATE_BATCH_SIZE);
+    let params = json!({
+        "database": db_name,
+        "collection": coll_name,
+        "into": target,
+        "count": count,
+        "seed": seed,
+        "source": source,
+    });
+    let description = format!("Generate {} documents into {}.{}", count, db_name, target);
+    let coll = data.database(&db_name).collection::<Document>(&target);
+
+    accepted(
+        JobStore::start(&jobs, "generate", description, params, move |ctx| async move {
+            let mut rng = generator::seeded(seed);
+            let (mut inserted, mut failed) = (0u64, 0u64);
+            let mut remaining = count;
+            while remaining > 0 {
+                let n = remaining.min(batch_size as u64);
+                let batch: Vec<Document> = (0..n).map(|_| model.document(&mut rng)).collect();
+                match coll.insert_many(batch).ordered(false).await {
+                    Ok(r) => inserted += r.inserted_ids.len() as u64,
+                    Err(e) => match *e.kind {
+                        ErrorKind::InsertMany(ref failure) if failure.write_concern_error.is_none() => {
+                            let write_errors = failure.write_errors.clone().unwrap_or_default();
+                            if let Some(first) = write_errors.first() {
+                                ctx.log(format!("{} documents rejected, first: {}", write_errors.len(), first.message)).await;
+                            }
+                            failed += write_errors.len() as u64;
+                            inserted += n - write_errors.len() as u64;
+                        }
+                        _ => return Err(format!("insert failed after {} documents: {}", inserted, e)),
+                    },
+                }
+                remaining -= n;
+                ctx.progress((count - remaining) as f64 * 100.0 / count as f64).await;
+            }
+            ctx.log(format!("inserted {} documents with seed {}", inserted, seed)).await;
+            Ok(JobOutcome::result(json!({ "inserted": inserted, "failed": failed, "seed": seed, "source": source })))
+        })
+        .await,
+    )
+}
+
+/// Compares two collections by `_id`; the full diff is the job's NDJSON download.
+#[post("/jobs/compare")]
+pub async fn compare_job(
+    body: web::Json<CompareRequest>,
+    data: web::Data<Client>,
+    profiles: web::Data<ProfileStore>,
+    app_info: web::Data<AppInfo>,
+    jobs: web::Data<JobStore>,
+) -> actix_web::Result<HttpResponse> {
+    let req = body.into_inner();
+    let (a, b) = match (
+        collection(&req.a, &profiles, &data, &app_info).await,
+        collection(&req.b, &profiles, &data, &app_info).await,
+    ) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(e), _) | (_, Err(e)) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let filter = match json_document(req.filter.as_ref(), "filter") {
+        Ok(f) => f.unwrap_or_default(),
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let params = json!({
+        "a": req.a,
+        "b": req.b,
+        "filter": req.filter,
+        "downloadName": format!("{}-vs-{}.ndjson", req.a.collection, req.b.collection),
+    });
+    let description = format!("Compare {} with {}", req.a.label(), req.b.label());
+    let batch_size = req.batch_size();
+
+    accepted(
+        JobStore::start(&jobs, "compare", description, params, move |ctx| async move {
+            let path = ctx.output_path("ndjson").map_err(|e| e.to_string())?;
+            let summary = compare_collections(&a, &b, filter, batch_size, &ctx, &path).await?;
+            ctx.log(format!(
+                "{} only in A, {} only in B, {} different, {} identical",
+                summary.only_in_a, summary.only_in_b, summary.different, summary.identical
+            ))
+            .await;
+            let result = serde_json::to_value(&summary).unwrap_or(JsonValue::Null);
+            Ok(JobOutcome { result, file: Some(path) })
+        })
+        .await,
+    )
+}
+
+/// Orphaned-reference analysis (see `/relationships/{db}/orphans`) as a job.
+#[post("/jobs/orphans/{db_name}")]
+pub async fn orphans_job(
+    path: web::Path<String>,
+    body: Option<web::Json<OrphanCheckRequest>>,
+    data: web::Data<Client>,
+    relationships: web::Data<RelationshipStore>,
+    app_info: web::Data<AppInfo>,
+    jobs: web::Data<JobStore>,
+) -> actix_web::Result<HttpResponse> {
+    let db_name = path.into_inner();
+    let req = body.map(|b| b.into_inner()).unwrap_or_default();
+    let declared = relationships.list(&app_info.shortened_uri, &db_name).await;
+    let params = json!({ "database": db_name, "request": req });
+    let description = format!("Orphan check on {}", db_name);
+    let client = data.get_ref().clone();
+
+    accepted(
+        JobStore::start(&jobs, "orphans", description, params, move |ctx| async move {
+            let db = client.database(&db_name);
+            let targets = req.targets(&db, declared).await.map_err(|e| e.to_string())?;
+            ctx.log(format!("checking {} relationships", targets.len())).await;
+            let mut reports = Vec::new();
+            for (i, relationship) in targets.iter().enumerate() {
+                let report = check_relationship(&db, relationship, req.batch_size(), req.sample_ids())
+                    .await
+                    .map_err(|e| e.to_string())?;
+                ctx.log(format!(
+                    "{}.{} -> {}: {} orphaned of {}",
+                    relationship.from, relationship.field, relationship.to, report.orphaned_documents, report.checked_documents
+                ))
+                .await;
+                reports.push(report);
+                ctx.progress((i + 1) as f64 * 100.0 / targets.len() as f64).await;
+            }
+            Ok(JobOutcome::result(json!({
+                "database": db_name,
+                "orphanedDocuments": reports.iter().map(|r| r.orphaned_documents).sum::<u64>(),
+                "reports": reports,
+            })))
+        })
+        .await,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::default_index_name;
+    use mongodb::bson::doc;
+
+    #[test]
+    fn names_indexes_like_the_shell() {
+        assert_eq!(default_index_name(&doc! { "email": 1, "createdAt": -1 }), "email_1_createdAt_-1");
+        assert_eq!(default_index_name(&doc! { "body": "text" }), "body_text");
+    }
+}
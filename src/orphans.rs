@@ -0,0 +1,361 @@
+use actix_web::{get, post, web, web::Bytes, HttpResponse};
+use futures::stream::{self, TryStreamExt};
+use mongodb::{
+    bson::{doc, Bson, Document},
+    Client, Collection, Database,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+
+use crate::ai::DEFAULT_SAMPLE_SIZE;
+use crate::copy::id_after;
+use crate::relationships::{infer_relationships, Relationship, RelationshipStore, DEFAULT_MIN_COVERAGE};
+use crate::state::AppInfo;
+
+const DEFAULT_BATCH_SIZE: i64 = 1000;
+const DEFAULT_SAMPLE_IDS: usize = 20;
+
+/// A source document with at least one reference that resolves to nothing.
+pub struct Orphan {
+    pub id: Bson,
+    pub missing: Vec<Bson>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanReport {
+    #[serde(flatten)]
+    pub relationship: Relationship,
+    pub checked_documents: u64,
+    pub orphaned_documents: u64,
+    pub missing_references: u64,
+    pub sample_ids: Vec<JsonValue>,
+    pub sample_missing: Vec<JsonValue>,
+}
+
+fn flatten_refs(value: &Bson, out: &mut Vec<Bson>) {
+    match value {
+        Bson::Array(items) => items.iter().for_each(|item| flatten_refs(item, out)),
+        Bson::Null => {}
+        other => out.push(other.clone()),
+    }
+}
+
+fn numeric(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Int32(v) => Some(*v as f64),
+        Bson::Int64(v) => Some(*v as f64),
+        Bson::Double(v) => Some(*v),
+        _ => None,
+    }
+}
+
+// `$lookup` matches 1, 1L and 1.0 to each other, so numeric references compare by value.
+fn same_ref(a: &Bson, b: &Bson) -> bool {
+    match (numeric(a), numeric(b)) {
+        (Some(x), Some(y)) => x == y,
+        _ => a == b,
+    }
+}
+
+/// References in `refs` with no counterpart among the `_id`s `$lookup` returned.
+fn missing_refs(refs: &Bson, matched: &[Bson]) -> Vec<Bson> {
+    let mut values = Vec::new();
+    flatten_refs(refs, &mut values);
+    let mut missing: Vec<Bson> = Vec::new();
+    for value in values {
+        if !matched.iter().any(|m| same_ref(m, &value)) && !missing.iter().any(|m| same_ref(m, &value)) {
+            missing.push(value);
+        }
+    }
+    missing
+}
+
+/// Walks the source collection in `_id` order, `batch_size` documents at a time, joining each
+/// batch against the target with `$lookup`.
+pub struct OrphanScan {
+    source: Collection<Document>,
+    target: String,
+    local_field: String,
+    batch_size: i64,
+    last_id: Option<Bson>,
+    done: bool,
+    /// Source documents that carried the reference field so far.
+    pub checked: u64,
+}
+
+impl OrphanScan {
+    pub fn new(db: &Database, relationship: &Relationship, batch_size: i64) -> Self {
+        Self {
+            source: db.collection::<Document>(&relationship.from),
+            target: relationship.to.clone(),
+            // `items[].product` -> `items.product`; $lookup's localField resolves through arrays itself.
+            local_field: relationship.field.replace("[]", ""),
+            batch_size: batch_size.max(1),
+            last_id: None,
+            done: false,
+            checked: 0,
+        }
+    }
+
+    /// Orphans in the next batch, or `None` once the source is exhausted.
+    pub async fn next_batch(&mut self) -> mongodb::error::Result<Option<Vec<Orphan>>> {
+        if self.done {
+            return Ok(None);
+        }
+        // `$exists` only: with an array path, `$ne: null` would drop every document in which a
+        // single element lacks the field, hiding dangling references in the other elements.
+        // Null and missing values are dropped from the looked-up refs instead.
+        let mut filter = doc! { self.local_field.as_str(): { "$exists": true } };
+        if let Some(last) = &self.last_id {
+            filter.extend(id_after(last.clone()));
+        }
+        let pipeline = vec![
+            doc! { "$match": filter },
+            doc! { "$sort": { "_id": 1 } },
+            doc! { "$limit": self.batch_size },
+            doc! { "$lookup": {
+                "from": self.target.as_str(),
+                "localField": self.local_field.as_str(),
+                "foreignField": "_id",
+                "as": "__matched",
+            } },
+            doc! { "$project": {
+                "refs": format!("${}", self.local_field),
+                "matched": "$__matched._id",
+            } },
+        ];
+        let batch: Vec<Document> = self.source.aggregate(pipeline).await?.try_collect().await?;
+        self.last_id = batch.last().and_then(|row| row.get("_id").cloned());
+        self.done = (batch.len() as i64) < self.batch_size || self.last_id.is_none();
+        if batch.is_empty() {
+            return Ok(None);
+        }
+
+        let mut orphans = Vec::new();
+        for row in &batch {
+            self.checked += 1;
+            let matched = match row.get("matched") {
+                Some(Bson::Array(ids)) => ids.clone(),
+                _ => Vec::new(),
+            };
+            let missing = missing_refs(row.get("refs").unwrap_or(&Bson::Null), &matched);
+            if !missing.is_empty() {
+                orphans.push(Orphan {
+                    id: row.get("_id").cloned().unwrap_or(Bson::Null),
+                    missing,
+                });
+            }
+        }
+        Ok(Some(orphans))
+    }
+}
+
+/// Runs an [`OrphanScan`] to the end, handing every orphan to `visit`.
+/// Returns the number of source documents that carried the reference field.
+pub async fn scan_orphans(
+    db: &Database,
+    relationship: &Relationship,
+    batch_size: i64,
+    mut visit: impl FnMut(Orphan),
+) -> mongodb::error::Result<u64> {
+    let mut scan = OrphanScan::new(db, relationship, batch_size);
+    while let Some(orphans) = scan.next_batch().await? {
+        orphans.into_iter().for_each(&mut visit);
+    }
+    Ok(scan.checked)
+}
+
+fn orphan_lines(orphans: Vec<Orphan>) -> Bytes {
+    let mut body = String::new();
+    for orphan in orphans {
+        body.push_str(&json!({ "_id": orphan.id, "missing": orphan.missing }).to_string());
+        body.push('\n');
+    }
+    Bytes::from(body)
+}
+
+pub async fn check_relationship(
+    db: &Database,
+    relationship: &Relationship,
+    batch_size: i64,
+    sample_ids: usize,
+) -> mongodb::error::Result<OrphanReport> {
+    let mut report = OrphanReport {
+        relationship: relationship.clone(),
+        checked_documents: 0,
+        orphaned_documents: 0,
+        missing_references: 0,
+        sample_ids: Vec::new(),
+        sample_missing: Vec::new(),
+    };
+    let checked = scan_orphans(db, relationship, batch_size, |orphan| {
+        report.orphaned_documents += 1;
+        report.missing_references += orphan.missing.len() as u64;
+        if report.sample_ids.len() < sample_ids {
+            report.sample_ids.push(serde_json::to_value(&orphan.id).unwrap_or(JsonValue::Null));
+        }
+        for value in orphan.missing {
+            let json = serde_json::to_value(&value).unwrap_or(JsonValue::Null);
+            if report.sample_missing.len() < sample_ids && !report.sample_missing.contains(&json) {
+                report.sample_missing.push(json);
+            }
+        }
+    })
+    .await?;
+    report.checked_documents = checked;
+    Ok(report)
+}
+
+#[derive(Deserialize, Serialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanCheckRequest {
+    // Relationships to check; defaults to the declared ones for the database.
+    pub relationships: Option<Vec<Relationship>>,
+    // Also check inferred relationships (always done when nothing is declared).
+    pub inferred: Option<bool>,
+    pub sample: Option<i64>,
+    pub min_coverage: Option<f64>,
+    pub batch_size: Option<i64>,
+    pub sample_ids: Option<usize>,
+}
+
+impl OrphanCheckRequest {
+    pub fn batch_size(&self) -> i64 {
+        self.batch_size.unwrap_or(DEFAULT_BATCH_SIZE)
+    }
+
+    pub fn sample_ids(&self) -> usize {
+        self.sample_ids.unwrap_or(DEFAULT_SAMPLE_IDS)
+    }
+
+    /// The relationships to check: the requested ones, else `declared`, plus inferred edges when
+    /// asked for or when there is nothing else to check.
+    pub async fn targets(&self, db: &Database, declared: Vec<Relationship>) -> mongodb::error::Result<Vec<Relationship>> {
+        let mut targets = self.relationships.clone().unwrap_or(declared);
+        if self.inferred.unwrap_or(false) || targets.is_empty() {
+            let graph = infer_relationships(
+                db,
+                self.sample.unwrap_or(DEFAULT_SAMPLE_SIZE),
+                self.min_coverage.unwrap_or(DEFAULT_MIN_COVERAGE),
+            )
+            .await?;
+            for edge in graph.edges {
+                if !targets.iter().any(|t| t.same_edge(&edge)) {
+                    targets.push(edge);
+                }
+            }
+        }
+        Ok(targets)
+    }
+}
+
+#[post("/relationships/{db_name}/orphans")]
+pub async fn check_orphans(
+    path: web::Path<String>,
+    body: Option<web::Json<OrphanCheckRequest>>,
+    data: web::Data<Client>,
+    relationships: web::Data<RelationshipStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let db_name = path.into_inner();
+    let req = body.map(|b| b.into_inner()).unwrap_or_default();
+    let db = data.database(&db_name);
+
+    let declared = relationships.list(&app_info.shortened_uri, &db_name).await;
+    let targets = match req.targets(&db, declared).await {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("relationship inference error: {}", e);
+            return Ok(HttpResponse::InternalServerError().body(format!("relationship inference failed: {}", e)));
+        }
+    };
+
+    let batch_size = req.batch_size();
+    let sample_ids = req.sample_ids();
+    let mut reports = Vec::new();
+    for relationship in &targets {
+        match check_relationship(&db, relationship, batch_size, sample_ids).await {
+            Ok(report) => reports.push(report),
+            Err(e) => {
+                eprintln!("orphan check error: {}", e);
+                return Ok(HttpResponse::InternalServerError().body(format!(
+                    "orphan check for {}.{} -> {} failed: {}",
+                    relationship.from, relationship.field, relationship.to, e
+                )));
+            }
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "database": db_name,
+        "orphanedDocuments": reports.iter().map(|r| r.orphaned_documents).sum::<u64>(),
+        "reports": reports,
+    })))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanExportQuery {
+    from: String,
+    field: String,
+    to: String,
+    batch_size: Option<i64>,
+}
+
+/// Full orphan list for one relationship as NDJSON: `{"_id": ..., "missing": [...]}` per line.
+#[get("/relationships/{db_name}/orphans/export")]
+pub async fn export_orphans(
+    path: web::Path<String>,
+    query: web::Query<OrphanExportQuery>,
+    data: web::Data<Client>,
+) -> actix_web::Result<HttpResponse> {
+    let db_name = path.into_inner();
+    let query = query.into_inner();
+    let relationship = Relationship {
+        from: query.from,
+        field: query.field,
+        to: query.to,
+        many: false,
+        coverage: None,
+    };
+
+    // streamed batch by batch, so memory stays flat however many orphans there are
+    let scan = OrphanScan::new(&data.database(&db_name), &relationship, query.batch_size.unwrap_or(DEFAULT_BATCH_SIZE));
+    let body = stream::unfold(Some(scan), |state| async move {
+        let mut scan = state?;
+        match scan.next_batch().await {
+            Ok(Some(orphans)) => Some((Ok(orphan_lines(orphans)), Some(scan))),
+            Ok(None) => None,
+            Err(e) => {
+                eprintln!("orphan export error: {}", e);
+                Some((Err(actix_web::error::ErrorInternalServerError(e)), None))
+            }
+        }
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}.{}-orphans.ndjson\"", relationship.from, relationship.field),
+        ))
+        .streaming(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::missing_refs;
+    use mongodb::bson::{oid::ObjectId, Bson};
+
+    #[test]
+    fn finds_missing_references() {
+        let a = ObjectId::new();
+        let b = ObjectId::new();
+        let refs = Bson::Array(vec![Bson::ObjectId(a), Bson::ObjectId(b), Bson::ObjectId(b), Bson::Null]);
+        assert_eq!(missing_refs(&refs, &[Bson::ObjectId(a)]), vec![Bson::ObjectId(b)]);
+        assert!(missing_refs(&Bson::ObjectId(a), &[Bson::ObjectId(a)]).is_empty());
+        // numeric ids match across int/long like $lookup does
+        assert!(missing_refs(&Bson::Int32(7), &[Bson::Int64(7)]).is_empty());
+        assert_eq!(missing_refs(&Bson::String("x".into()), &[]), vec![Bson::String("x".into())]);
+    }
+}
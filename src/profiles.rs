@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+use actix_web::{delete, get, post, web, HttpResponse};
+use mongodb::{bson::doc, Client};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::Mutex;
+
+use crate::state::{shorten_uri, AppInfo};
+use crate::store::JsonStore;
+
+const PROFILES_FILE: &str = "profiles.json";
+/// Name that always refers to the connection globe was started with.
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub uri: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ProfilesFile {
+    profiles: Vec<Profile>,
+}
+
+/// Extra named connections (other clusters, staging, ...) used as copy sources and targets.
+/// URIs are kept as given, credentials included, in `profiles.json` under the data directory;
+/// the API only ever returns them shortened.
+pub struct ProfileStore {
+    store: JsonStore<ProfilesFile>,
+    clients: Mutex<HashMap<String, Client>>,
+}
+
+impl ProfileStore {
+    pub fn new() -> Self {
+        Self {
+            store: JsonStore::open(PROFILES_FILE),
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn find(&self, name: &str) -> Option<Profile> {
+        self.store.read(|file| file.profiles.iter().find(|p| p.name == name).cloned()).await
+    }
+
+    /// Shortened URI of a profile, the key its schemas and masking rules are stored under.
+    pub async fn connection(&self, name: Option<&str>, app_info: &AppInfo) -> Result<String, String> {
+        match name {
+            None | Some(DEFAULT_PROFILE) => Ok(app_info.shortened_uri.clone()),
+            Some(n) => self
+                .find(n)
+                .await
+                .map(|p| shorten_uri(&p.uri))
+                .ok_or_else(|| format!("unknown connection profile: {}", n)),
+        }
+    }
+
+    /// Client for a profile; `None` or `"default"` is the main connection, and so is any saved
+    /// profile pointing at the same URI, so its connection pool is shared.
+    pub async fn client(&self, name: Option<&str>, main: &Client, app_info: &AppInfo) -> Result<Client, String> {
+        let name = match name {
+            None | Some(DEFAULT_PROFILE) => return Ok(main.clone()),
+            Some(n) => n,
+        };
+        let profile = self.find(name).await.ok_or_else(|| format!("unknown connection profile: {}", name))?;
+        if profile.uri == app_info.original_uri {
+            return Ok(main.clone());
+        }
+        let mut clients = self.clients.lock().await;
+        if let Some(client) = clients.get(name) {
+            return Ok(client.clone());
+        }
+        let client = Client::with_uri_str(&profile.uri)
+            .await
+            .map_err(|e| format!("connecting to profile {} failed: {}", name, e))?;
+        clients.insert(name.to_string(), client.clone());
+        Ok(client)
+    }
+}
+
+#[get("/profiles")]
+pub async fn list_profiles(profiles: web::Data<ProfileStore>, app_info: web::Data<AppInfo>) -> actix_web::Result<HttpResponse> {
+    let mut listed = vec![json!({ "name": DEFAULT_PROFILE, "uri": app_info.shortened_uri, "sameAsDefault": true })];
+    profiles
+        .store
+        .read(|file| {
+            for p in &file.profiles {
+                listed.push(json!({
+                    "name": p.name,
+                    "uri": shorten_uri(&p.uri),
+                    "sameAsDefault": p.uri == app_info.original_uri,
+                }));
+            }
+        })
+        .await;
+    Ok(HttpResponse::Ok().json(listed))
+}
+
+/// Saves (or replaces) a profile after checking the cluster answers a ping.
+#[post("/profiles")]
+pub async fn save_profile(body: web::Json<Profile>, profiles: web::Data<ProfileStore>) -> actix_web::Result<HttpResponse> {
+    let profile = body.into_inner();
+    let name = profile.name.trim().to_string();
+    if name.is_empty() || name == DEFAULT_PROFILE {
+        return Ok(HttpResponse::BadRequest().body("profile name must be non-empty and not \"default\""));
+    }
+    let client = match Client::with_uri_str(&profile.uri).await {
+        Ok(c) => c,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(format!("invalid connection string: {}", e))),
+    };
+    if let Err(e) = client.database("admin").run_command(doc! { "ping": 1 }).await {
+        return Ok(HttpResponse::BadRequest().body(format!("cannot reach {}: {}", shorten_uri(&profile.uri), e)));
+    }
+
+    let uri = profile.uri.clone();
+    let saved = profiles
+        .store
+        .update(|file| {
+            file.profiles.retain(|p| p.name != name);
+            file.profiles.push(Profile { name: name.clone(), uri });
+        })
+        .await;
+    if let Err(e) = saved {
+        eprintln!("profile save error: {}", e);
+        return Ok(HttpResponse::InternalServerError().body(format!("failed to save profile: {}", e)));
+    }
+    profiles.clients.lock().await.insert(name.clone(), client);
+    Ok(HttpResponse::Ok().json(json!({ "name": name, "uri": shorten_uri(&profile.uri) })))
+}
+
+#[delete("/profiles/{name}")]
+pub async fn delete_profile(path: web::Path<String>, profiles: web::Data<ProfileStore>) -> actix_web::Result<HttpResponse> {
+    let name = path.into_inner();
+    let removed = profiles
+        .store
+        .update(|file| {
+            let before = file.profiles.len();
+            file.profiles.retain(|p| p.name != name);
+            before != file.profiles.len()
+        })
+        .await;
+    match removed {
+        Ok(true) => {
+            profiles.clients.lock().await.remove(&name);
+            Ok(HttpResponse::Ok().json(json!({ "deleted": name })))
+        }
+        Ok(false) => Ok(HttpResponse::NotFound().body("profile not found")),
+        Err(e) => {
+            eprintln!("profile delete error: {}", e);
+            Ok(HttpResponse::InternalServerError().body(format!("failed to delete profile: {}", e)))
+        }
+    }
+}
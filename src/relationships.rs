@@ -0,0 +1,473 @@
+use std::collections::BTreeMap;
+
+use actix_web::{delete, get, post, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, Document},
+    Client, Database,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::ai::DEFAULT_SAMPLE_SIZE;
+use crate::sampling::{bson_type_name, sample_documents};
+use crate::state::AppInfo;
+use crate::store::JsonStore;
+
+const RELATIONSHIP_FILE: &str = "relationships.json";
+pub const DEFAULT_MIN_COVERAGE: f64 = 50.0;
+// Distinct values per field sent to the target collections in one `$in` probe.
+const MAX_CANDIDATES: usize = 100;
+const MAX_ID_STRING_LEN: usize = 64;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Relationship {
+    // Referencing collection and dotted path of the reference (`items[].productId`).
+    pub from: String,
+    pub field: String,
+    // Collection whose `_id` the field points at.
+    pub to: String,
+    // True when the reference sits inside an array, i.e. one document points at many.
+    #[serde(default)]
+    pub many: bool,
+    // Share of sampled distinct values found in the target, if measured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<f64>,
+}
+
+impl Relationship {
+    pub fn same_edge(&self, other: &Relationship) -> bool {
+        self.from == other.from && self.field == other.field && self.to == other.to
+    }
+}
+
+/// A relationship the user declared by hand, keyed like stored schemas by connection and database.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DeclaredRelationship {
+    pub connection: String,
+    pub database: String,
+    #[serde(flatten)]
+    pub relationship: Relationship,
+    pub declared_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct RelationshipFile {
+    relationships: Vec<DeclaredRelationship>,
+}
+
+pub struct RelationshipStore {
+    store: JsonStore<RelationshipFile>,
+}
+
+impl RelationshipStore {
+    pub fn new() -> Self {
+        Self {
+            store: JsonStore::open(RELATIONSHIP_FILE),
+        }
+    }
+
+    pub async fn list(&self, connection: &str, database: &str) -> Vec<Relationship> {
+        self.store
+            .read(|file| {
+                file.relationships
+                    .iter()
+                    .filter(|r| r.connection == connection && r.database == database)
+                    .map(|r| r.relationship.clone())
+                    .collect()
+            })
+            .await
+    }
+
+    /// Adds or replaces the declaration for the same `from`/`field`/`to` edge.
+    pub async fn declare(&self, connection: &str, database: &str, relationship: Relationship) -> std::io::Result<()> {
+        self.store
+            .update(|file| {
+                file.relationships.retain(|r| {
+                    !(r.connection == connection && r.database == database && r.relationship.same_edge(&relationship))
+                });
+                file.relationships.push(DeclaredRelationship {
+                    connection: connection.to_string(),
+                    database: database.to_string(),
+                    relationship,
+                    declared_at: Utc::now(),
+                });
+            })
+            .await
+    }
+
+    pub async fn remove(&self, connection: &str, database: &str, edge: &Relationship) -> std::io::Result<usize> {
+        self.store
+            .update(|file| {
+                let before = file.relationships.len();
+                file.relationships
+                    .retain(|r| !(r.connection == connection && r.database == database && r.relationship.same_edge(edge)));
+                before - file.relationships.len()
+            })
+            .await
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionNode {
+    pub name: String,
+    pub id_type: Option<String>,
+    pub estimated_documents: u64,
+}
+
+#[derive(Serialize)]
+pub struct RelationshipGraph {
+    pub database: String,
+    pub nodes: Vec<CollectionNode>,
+    pub edges: Vec<Relationship>,
+}
+
+#[derive(Default)]
+struct Candidates {
+    object_ids: Vec<ObjectId>,
+    strings: Vec<String>,
+    many: bool,
+}
+
+fn looks_like_id(s: &str) -> bool {
+    !s.is_empty() && s.len() <= MAX_ID_STRING_LEN && !s.chars().any(char::is_whitespace)
+}
+
+fn collect_value(out: &mut BTreeMap<String, Candidates>, path: &str, value: &Bson, in_array: bool) {
+    match value {
+        Bson::ObjectId(oid) => {
+            let entry = out.entry(path.to_string()).or_default();
+            entry.many |= in_array;
+            if entry.object_ids.len() < MAX_CANDIDATES && !entry.object_ids.contains(oid) {
+                entry.object_ids.push(*oid);
+            }
+        }
+        Bson::String(s) if looks_like_id(s) => {
+            let entry = out.entry(path.to_string()).or_default();
+            entry.many |= in_array;
+            if entry.strings.len() < MAX_CANDIDATES && !entry.strings.contains(s) {
+                entry.strings.push(s.clone());
+            }
+        }
+        Bson::Document(d) => collect_document(out, path, d, in_array),
+        Bson::Array(items) => {
+            let element_path = format!("{}[]", path);
+            for item in items {
+                collect_value(out, &element_path, item, true);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn collect_document(out: &mut BTreeMap<String, Candidates>, prefix: &str, doc: &Document, in_array: bool) {
+    for (key, value) in doc.iter() {
+        if prefix.is_empty() && key == "_id" {
+            continue;
+        }
+        let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        collect_value(out, &path, value, in_array);
+    }
+}
+
+/// Distinct ObjectId and id-like string values per dotted path, excluding the root `_id`.
+fn reference_candidates(docs: &[Document]) -> BTreeMap<String, Candidates> {
+    let mut out = BTreeMap::new();
+    for d in docs {
+        collect_document(&mut out, "", d, false);
+    }
+    out
+}
+
+fn dominant_id_type(docs: &[Document]) -> Option<String> {
+    let mut counts: BTreeMap<&'static str, usize> = BTreeMap::new();
+    for d in docs {
+        if let Some(id) = d.get("_id") {
+            *counts.entry(bson_type_name(id)).or_insert(0) += 1;
+        }
+    }
+    counts.into_iter().max_by_key(|(_, c)| *c).map(|(t, _)| t.to_string())
+}
+
+/// Samples each collection (as `describe_database` does for the AI prompt), then probes every
+/// ObjectId or id-like string field against the `_id`s of collections with a matching `_id` type.
+pub async fn infer_relationships(
+    db: &Database,
+    sample_size: i64,
+    min_coverage: f64,
+) -> mongodb::error::Result<RelationshipGraph> {
+    let names: Vec<String> = db
+        .list_collections()
+        .filter(doc! { "type": "collection" })
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?
+        .into_iter()
+        .map(|spec| spec.name)
+        .filter(|name| !name.starts_with("system."))
+        .collect();
+
+    let mut nodes = Vec::new();
+    let mut samples: Vec<(String, Vec<Document>)> = Vec::new();
+    for name in &names {
+        let coll = db.collection::<Document>(name);
+        let docs = match sample_documents(&coll, sample_size).await {
+            Ok(docs) => docs,
+            Err(err) => {
+                eprintln!("failed to sample collection {}.{}: {}", db.name(), name, err);
+                continue;
+            }
+        };
+        nodes.push(CollectionNode {
+            name: name.clone(),
+            id_type: dominant_id_type(&docs),
+            estimated_documents: coll.estimated_document_count().await.unwrap_or(0),
+        });
+        samples.push((name.clone(), docs));
+    }
+
+    let mut edges = Vec::new();
+    for (source, docs) in &samples {
+        for (field, candidates) in reference_candidates(docs) {
+            let object_ids: Vec<Bson> = candidates.object_ids.iter().map(|o| Bson::ObjectId(*o)).collect();
+            let strings: Vec<Bson> = candidates.strings.iter().map(|s| Bson::String(s.clone())).collect();
+            // only collections whose _id type matches what the field holds are probed at all
+            let targets = nodes.iter().filter_map(|target| match target.id_type.as_deref() {
+                Some("objectId") if !object_ids.is_empty() => Some((target, &object_ids)),
+                Some("string") if !strings.is_empty() => Some((target, &strings)),
+                _ => None,
+            });
+            for (target, probe) in targets {
+                let matched = match db
+                    .collection::<Document>(&target.name)
+                    .count_documents(doc! { "_id": { "$in": probe.clone() } })
+                    .await
+                {
+                    Ok(n) => n,
+                    Err(err) => {
+                        eprintln!("failed to probe {}.{} for {}.{}: {}", db.name(), target.name, source, field, err);
+                        continue;
+                    }
+                };
+                let coverage = (matched as f64 / probe.len() as f64 * 10000.0).round() / 100.0;
+                if matched > 0 && coverage >= min_coverage {
+                    edges.push(Relationship {
+                        from: source.clone(),
+                        field: field.clone(),
+                        to: target.name.clone(),
+                        many: candidates.many,
+                        coverage: Some(coverage),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(RelationshipGraph {
+        database: db.name().to_string(),
+        nodes,
+        edges,
+    })
+}
+
+fn mermaid_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .collect()
+}
+
+pub fn to_mermaid(graph: &RelationshipGraph) -> String {
+    let mut out = String::from("erDiagram\n");
+    for edge in &graph.edges {
+        let cardinality = if edge.many { "}o--o{" } else { "}o--||" };
+        out.push_str(&format!(
+            "    {} {} {} : \"{}\"\n",
+            mermaid_name(&edge.from),
+            cardinality,
+            mermaid_name(&edge.to),
+            edge.field.replace('"', "'")
+        ));
+    }
+    out
+}
+
+fn dot_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+pub fn to_dot(graph: &RelationshipGraph) -> String {
+    let mut out = format!("digraph {} {{\n    rankdir=LR;\n    node [shape=box];\n", dot_quote(&graph.database));
+    for node in &graph.nodes {
+        out.push_str(&format!("    {};\n", dot_quote(&node.name)));
+    }
+    for edge in &graph.edges {
+        let label = match edge.coverage {
+            Some(c) => format!("{} ({}%)", edge.field, c),
+            None => edge.field.clone(),
+        };
+        let style = if edge.many { ", arrowhead=crow" } else { "" };
+        out.push_str(&format!(
+            "    {} -> {} [label={}{}];\n",
+            dot_quote(&edge.from),
+            dot_quote(&edge.to),
+            dot_quote(&label),
+            style
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelationshipQuery {
+    sample: Option<i64>,
+    // Minimum percentage of sampled values that must resolve in the target collection.
+    min_coverage: Option<f64>,
+    // "json" (default), "mermaid" or "dot"
+    format: Option<String>,
+}
+
+#[get("/relationships/{db_name}")]
+pub async fn relationship_graph(
+    path: web::Path<String>,
+    query: web::Query<RelationshipQuery>,
+    data: web::Data<Client>,
+) -> actix_web::Result<HttpResponse> {
+    let db_name = path.into_inner();
+    let format = query.format.clone().unwrap_or_else(|| "json".to_string());
+    if !["json", "mermaid", "dot"].contains(&format.as_str()) {
+        return Ok(HttpResponse::BadRequest().body("format must be json, mermaid or dot"));
+    }
+
+    let db = data.database(&db_name);
+    let graph = match infer_relationships(
+        &db,
+        query.sample.unwrap_or(DEFAULT_SAMPLE_SIZE),
+        query.min_coverage.unwrap_or(DEFAULT_MIN_COVERAGE),
+    )
+    .await
+    {
+        Ok(g) => g,
+        Err(e) => {
+            eprintln!("relationship inference error: {}", e);
+            return Ok(HttpResponse::InternalServerError().body(format!("relationship inference failed: {}", e)));
+        }
+    };
+
+    match format.as_str() {
+        "mermaid" => Ok(HttpResponse::Ok().content_type("text/plain; charset=utf-8").body(to_mermaid(&graph))),
+        "dot" => Ok(HttpResponse::Ok().content_type("text/vnd.graphviz; charset=utf-8").body(to_dot(&graph))),
+        _ => Ok(HttpResponse::Ok().json(json!({
+            "database": graph.database,
+            "nodes": graph.nodes,
+            "edges": graph.edges,
+        }))),
+    }
+}
+
+#[get("/relationships/{db_name}/declared")]
+pub async fn list_declared(
+    path: web::Path<String>,
+    relationships: web::Data<RelationshipStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let db_name = path.into_inner();
+    let declared = relationships.list(&app_info.shortened_uri, &db_name).await;
+    Ok(HttpResponse::Ok().json(json!({ "database": db_name, "relationships": declared })))
+}
+
+#[post("/relationships/{db_name}/declared")]
+pub async fn declare_relationship(
+    path: web::Path<String>,
+    body: web::Json<Relationship>,
+    relationships: web::Data<RelationshipStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let db_name = path.into_inner();
+    let mut relationship = body.into_inner();
+    if relationship.from.is_empty() || relationship.field.is_empty() || relationship.to.is_empty() {
+        return Ok(HttpResponse::BadRequest().body("from, field and to are required"));
+    }
+    relationship.coverage = None;
+    match relationships.declare(&app_info.shortened_uri, &db_name, relationship.clone()).await {
+        Ok(()) => Ok(HttpResponse::Ok().json(relationship)),
+        Err(e) => {
+            eprintln!("relationship store error: {}", e);
+            Ok(HttpResponse::InternalServerError().body(format!("failed to save relationship: {}", e)))
+        }
+    }
+}
+
+#[delete("/relationships/{db_name}/declared")]
+pub async fn remove_relationship(
+    path: web::Path<String>,
+    query: web::Query<Relationship>,
+    relationships: web::Data<RelationshipStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let db_name = path.into_inner();
+    match relationships.remove(&app_info.shortened_uri, &db_name, &query).await {
+        Ok(0) => Ok(HttpResponse::NotFound().body("relationship not declared")),
+        Ok(removed) => Ok(HttpResponse::Ok().json(json!({ "removed": removed }))),
+        Err(e) => {
+            eprintln!("relationship store error: {}", e);
+            Ok(HttpResponse::InternalServerError().body(format!("failed to remove relationship: {}", e)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reference_candidates, to_dot, to_mermaid, CollectionNode, Relationship, RelationshipGraph};
+    use mongodb::bson::{doc, oid::ObjectId};
+
+    #[test]
+    fn collects_reference_candidates() {
+        let user = ObjectId::new();
+        let product = ObjectId::new();
+        let docs = vec![doc! {
+            "_id": ObjectId::new(),
+            "customer": user,
+            "note": "free text with spaces",
+            "sku": "AB-1",
+            "items": [ { "product": product }, { "product": product } ],
+        }];
+        let candidates = reference_candidates(&docs);
+        assert!(!candidates.contains_key("_id"));
+        assert!(!candidates.contains_key("note"));
+        assert_eq!(candidates["customer"].object_ids, vec![user]);
+        assert!(!candidates["customer"].many);
+        assert_eq!(candidates["items[].product"].object_ids, vec![product]);
+        assert!(candidates["items[].product"].many);
+        assert_eq!(candidates["sku"].strings, vec!["AB-1".to_string()]);
+    }
+
+    #[test]
+    fn renders_mermaid_and_dot() {
+        let graph = RelationshipGraph {
+            database: "shop".to_string(),
+            nodes: vec![
+                CollectionNode { name: "orders".to_string(), id_type: Some("objectId".to_string()), estimated_documents: 10 },
+                CollectionNode { name: "users".to_string(), id_type: Some("objectId".to_string()), estimated_documents: 5 },
+                CollectionNode { name: "products".to_string(), id_type: Some("objectId".to_string()), estimated_documents: 3 },
+            ],
+            edges: vec![
+                Relationship { from: "orders".to_string(), field: "customer".to_string(), to: "users".to_string(), many: false, coverage: Some(100.0) },
+                Relationship { from: "orders".to_string(), field: "items[].product".to_string(), to: "products".to_string(), many: true, coverage: Some(87.5) },
+            ],
+        };
+        assert_eq!(
+            to_mermaid(&graph),
+            "erDiagram\n    orders }o--|| users : \"customer\"\n    orders }o--o{ products : \"items[].product\"\n"
+        );
+        let dot = to_dot(&graph);
+        assert!(dot.starts_with("digraph \"shop\" {\n"));
+        assert!(dot.contains("    \"orders\" -> \"users\" [label=\"customer (100%)\"];\n"));
+        assert!(dot.contains("    \"orders\" -> \"products\" [label=\"items[].product (87.5%)\", arrowhead=crow];\n"));
+    }
+}
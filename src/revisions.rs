@@ -0,0 +1,470 @@
+use std::{collections::HashMap, env, time::Duration};
+
+use actix_web::{get, post, web, HttpResponse};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    action::Action,
+    bson::{self, doc, oid::ObjectId, Bson, Document},
+    options::{FindOptions, IndexOptions},
+    Client, Collection, IndexModel,
+};
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+use tokio::sync::OnceCell;
+
+use crate::diff::diff_json;
+
+const DEFAULT_NAMESPACE: &str = "globe.revisions";
+const DEFAULT_RETAIN: i64 = 20;
+const DEFAULT_TTL_DAYS: u64 = 30;
+// Before-images written per insert_many when a bulk operation records many documents.
+const RECORD_BATCH_SIZE: usize = 500;
+const DEFAULT_DELETED_LIMIT: i64 = 50;
+
+/// Prior versions of documents changed through globe, kept in a metadata collection
+/// (`GLOBE_REVISIONS_NAMESPACE`, default `globe.revisions`). Each revision is the document as it
+/// was *before* an update, replace, delete or restore. Retention: a TTL index expires revisions
+/// after `GLOBE_REVISIONS_TTL_DAYS` (0 keeps them forever), and single-document writes keep at
+/// most `GLOBE_REVISIONS_RETAIN` revisions per document.
+pub struct RevisionStore {
+    coll: Collection<Document>,
+    retain: i64,
+    ttl_days: u64,
+    indexes: OnceCell<()>,
+}
+
+impl RevisionStore {
+    pub fn new(client: &Client) -> Self {
+        let namespace = env::var("GLOBE_REVISIONS_NAMESPACE")
+            .ok()
+            .map(|ns| ns.trim().to_string())
+            .filter(|ns| ns.contains('.'))
+            .unwrap_or_else(|| DEFAULT_NAMESPACE.to_string());
+        let (db, coll) = namespace.split_once('.').unwrap_or(("globe", "revisions"));
+        let retain = env::var("GLOBE_REVISIONS_RETAIN")
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .filter(|v| *v >= 1)
+            .unwrap_or(DEFAULT_RETAIN);
+        let ttl_days = env::var("GLOBE_REVISIONS_TTL_DAYS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_TTL_DAYS);
+        Self {
+            coll: client.database(db).collection(coll),
+            retain,
+            ttl_days,
+            indexes: OnceCell::new(),
+        }
+    }
+
+    async fn ensure_indexes(&self) {
+        self.indexes
+            .get_or_init(|| async {
+                let mut models = vec![IndexModel::builder()
+                    .keys(doc! { "database": 1, "collection": 1, "documentId": 1, "recordedAt": -1 })
+                    .build()];
+                if self.ttl_days > 0 {
+                    models.push(
+                        IndexModel::builder()
+                            .keys(doc! { "recordedAt": 1 })
+                            .options(
+                                IndexOptions::builder()
+                                    .expire_after(Duration::from_secs(self.ttl_days * 24 * 3600))
+                                    .build(),
+                            )
+                            .build(),
+                    );
+                }
+                if let Err(e) = self.coll.create_indexes(models).await {
+                    eprintln!("failed to create revision indexes: {}", e);
+                }
+            })
+            .await;
+    }
+
+    fn revision(database: &str, collection: &str, operation: &str, before: &Document) -> Document {
+        doc! {
+            "database": database,
+            "collection": collection,
+            "documentId": before.get("_id").cloned().unwrap_or(Bson::Null),
+            "operation": operation,
+            "document": before.clone(),
+            "recordedAt": bson::DateTime::now(),
+        }
+    }
+
+    /// Records `before` (the document prior to a single-document write) and prunes that document's
+    /// history down to the retention limit. Failures are logged, never surfaced to the write.
+    pub async fn record(&self, database: &str, collection: &str, operation: &str, before: &Document) {
+        self.ensure_indexes().await;
+        let revision = Self::revision(database, collection, operation, before);
+        let id = revision.get("documentId").cloned().unwrap_or(Bson::Null);
+        if let Err(e) = self.coll.insert_one(revision).await {
+            eprintln!("failed to record revision for {}.{}: {}", database, collection, e);
+            return;
+        }
+        if let Err(e) = self.prune(database, collection, id).await {
+            eprintln!("failed to prune revisions for {}.{}: {}", database, collection, e);
+        }
+    }
+
+    async fn prune(&self, database: &str, collection: &str, id: Bson) -> mongodb::error::Result<()> {
+        let stale: Vec<Document> = self
+            .coll
+            .find(doc! { "database": database, "collection": collection, "documentId": id })
+            .sort(doc! { "recordedAt": -1, "_id": -1 })
+            .skip(self.retain as u64)
+            .projection(doc! { "_id": 1 })
+            .await?
+            .try_collect()
+            .await?;
+        if stale.is_empty() {
+            return Ok(());
+        }
+        let ids: Vec<Bson> = stale.iter().filter_map(|d| d.get("_id").cloned()).collect();
+        self.coll.delete_many(doc! { "_id": { "$in": ids } }).await?;
+        Ok(())
+    }
+
+    /// Records the current state of every document matching `filter` (or the first `limit` of
+    /// them) ahead of a bulk write. Bulk revisions rely on the TTL index for retention rather than
+    /// per-document pruning.
+    pub async fn record_matching(
+        &self,
+        source: &Collection<Document>,
+        filter: Document,
+        limit: Option<i64>,
+        operation: &str,
+    ) -> mongodb::error::Result<u64> {
+        self.ensure_indexes().await;
+        let database = source.namespace().db;
+        let collection = source.name().to_string();
+        let mut cursor = source.find(filter).optional(limit, |f, l| f.limit(l)).await?;
+        let mut batch = Vec::new();
+        let mut recorded = 0_u64;
+        while let Some(before) = cursor.try_next().await? {
+            batch.push(Self::revision(&database, &collection, operation, &before));
+            if batch.len() >= RECORD_BATCH_SIZE {
+                recorded += batch.len() as u64;
+                self.coll.insert_many(std::mem::take(&mut batch)).await?;
+            }
+        }
+        if !batch.is_empty() {
+            recorded += batch.len() as u64;
+            self.coll.insert_many(batch).await?;
+        }
+        Ok(recorded)
+    }
+
+    /// Records, like `record_matching`, the current state of the documents `filter` and `options`
+    /// (limit, sort, hint, collation) select, skipping any already in `recorded` (canonical `_id`
+    /// -> revision id) so a document touched by several operations of one request keeps a single
+    /// before-image: its state before the request. Returns the keys of every matching document,
+    /// recorded now or earlier.
+    pub async fn record_matching_once(
+        &self,
+        source: &Collection<Document>,
+        filter: Document,
+        options: FindOptions,
+        operation: &str,
+        recorded: &mut HashMap<String, ObjectId>,
+    ) -> mongodb::error::Result<Vec<String>> {
+        self.ensure_indexes().await;
+        let database = source.namespace().db;
+        let collection = source.name().to_string();
+        let mut cursor = source.find(filter).with_options(options).await?;
+        let mut batch = Vec::new();
+        let mut keys = Vec::new();
+        while let Some(before) = cursor.try_next().await? {
+            let key = before.get("_id").cloned().unwrap_or(Bson::Null).into_canonical_extjson().to_string();
+            if !recorded.contains_key(&key) {
+                let mut revision = Self::revision(&database, &collection, operation, &before);
+                let id = ObjectId::new();
+                revision.insert("_id", id);
+                recorded.insert(key.clone(), id);
+                batch.push(revision);
+            }
+            keys.push(key);
+            if batch.len() >= RECORD_BATCH_SIZE {
+                self.coll.insert_many(std::mem::take(&mut batch)).await?;
+            }
+        }
+        if !batch.is_empty() {
+            self.coll.insert_many(batch).await?;
+        }
+        Ok(keys)
+    }
+
+    /// Removes revisions recorded ahead of writes that then did not happen.
+    pub async fn discard(&self, ids: Vec<ObjectId>) -> mongodb::error::Result<()> {
+        for chunk in ids.chunks(RECORD_BATCH_SIZE) {
+            self.coll.delete_many(doc! { "_id": { "$in": chunk } }).await?;
+        }
+        Ok(())
+    }
+
+    /// Revisions of one document, oldest first.
+    pub async fn history(&self, database: &str, collection: &str, id: &Bson) -> mongodb::error::Result<Vec<Document>> {
+        self.coll
+            .find(doc! { "database": database, "collection": collection, "documentId": id.clone() })
+            .sort(doc! { "recordedAt": 1, "_id": 1 })
+            .await?
+            .try_collect()
+            .await
+    }
+
+    pub async fn get(&self, revision: ObjectId) -> mongodb::error::Result<Option<Document>> {
+        self.coll.find_one(doc! { "_id": revision }).await
+    }
+
+    /// Latest delete revision per document, newest first.
+    pub async fn deleted(&self, database: &str, collection: &str, limit: i64) -> mongodb::error::Result<Vec<Document>> {
+        let pipeline = vec![
+            doc! { "$match": { "database": database, "collection": collection, "operation": "delete" } },
+            doc! { "$sort": { "recordedAt": -1 } },
+            doc! { "$group": { "_id": "$documentId", "revision": { "$first": "$$ROOT" } } },
+            doc! { "$replaceWith": "$revision" },
+            doc! { "$sort": { "recordedAt": -1 } },
+            doc! { "$limit": limit.max(1) },
+        ];
+        self.coll.aggregate(pipeline).await?.try_collect().await
+    }
+}
+
+fn parse_id(id_str: &str) -> Bson {
+    match ObjectId::parse_str(id_str) {
+        Ok(oid) => Bson::ObjectId(oid),
+        Err(_) => Bson::String(id_str.to_string()),
+    }
+}
+
+fn to_json(value: &Bson) -> JsonValue {
+    serde_json::to_value(value).unwrap_or(JsonValue::Null)
+}
+
+fn revision_json(revision: &Document, next: &JsonValue) -> JsonValue {
+    let document = revision.get("document").map(to_json).unwrap_or(JsonValue::Null);
+    json!({
+        "id": revision.get_object_id("_id").map(|o| o.to_hex()).ok(),
+        "operation": revision.get_str("operation").unwrap_or_default(),
+        "recordedAt": revision.get_datetime("recordedAt").ok().and_then(|d| d.try_to_rfc3339_string().ok()),
+        "document": document,
+        // what the write that followed this version changed; a null `next` means it was deleted
+        "changes": diff_json(&document, next),
+    })
+}
+
+#[get("/documents/{db_name}/{coll_name}/{id}/revisions")]
+pub async fn document_history(
+    path: web::Path<(String, String, String)>,
+    data: web::Data<Client>,
+    revisions: web::Data<RevisionStore>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name, id_str) = path.into_inner();
+    let id = parse_id(&id_str);
+    let coll = data.database(&db_name).collection::<Document>(&coll_name);
+
+    let current = match coll.find_one(doc! { "_id": id.clone() }).await {
+        Ok(c) => c,
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("find_one failed: {}", e))),
+    };
+    let history = match revisions.history(&db_name, &coll_name, &id).await {
+        Ok(h) => h,
+        Err(e) => {
+            eprintln!("revision history error: {}", e);
+            return Ok(HttpResponse::InternalServerError().body(format!("revision history failed: {}", e)));
+        }
+    };
+
+    let current_json = current.as_ref().map(|d| to_json(&Bson::Document(d.clone()))).unwrap_or(JsonValue::Null);
+    let mut timeline = Vec::new();
+    for (i, revision) in history.iter().enumerate() {
+        // the state the write after this revision produced: nothing for a delete, otherwise the
+        // next revision's before-image or, for the newest one, the current document
+        let next = if revision.get_str("operation") == Ok("delete") {
+            JsonValue::Null
+        } else {
+            match history.get(i + 1) {
+                Some(n) => n.get("document").map(to_json).unwrap_or(JsonValue::Null),
+                None => current_json.clone(),
+            }
+        };
+        timeline.push(revision_json(revision, &next));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "database": db_name,
+        "collection": coll_name,
+        "documentId": to_json(&id),
+        "deleted": current.is_none(),
+        "current": current_json,
+        "revisions": timeline,
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct RevisionDiffQuery {
+    from: String,
+    // revision id, or "current" (default)
+    to: Option<String>,
+}
+
+async fn revision_document(
+    revisions: &RevisionStore,
+    coll: &Collection<Document>,
+    id: &Bson,
+    which: &str,
+) -> Result<JsonValue, HttpResponse> {
+    if which == "current" {
+        return match coll.find_one(doc! { "_id": id.clone() }).await {
+            Ok(d) => Ok(d.map(|d| to_json(&Bson::Document(d))).unwrap_or(JsonValue::Null)),
+            Err(e) => Err(HttpResponse::InternalServerError().body(format!("find_one failed: {}", e))),
+        };
+    }
+    let oid = ObjectId::parse_str(which).map_err(|_| HttpResponse::BadRequest().body(format!("invalid revision id: {}", which)))?;
+    match revisions.get(oid).await {
+        Ok(Some(r)) if r.get("documentId") == Some(id) => Ok(r.get("document").map(to_json).unwrap_or(JsonValue::Null)),
+        Ok(_) => Err(HttpResponse::NotFound().body(format!("revision {} not found for this document", which))),
+        Err(e) => Err(HttpResponse::InternalServerError().body(format!("revision lookup failed: {}", e))),
+    }
+}
+
+#[get("/documents/{db_name}/{coll_name}/{id}/revisions/diff")]
+pub async fn diff_revisions(
+    path: web::Path<(String, String, String)>,
+    query: web::Query<RevisionDiffQuery>,
+    data: web::Data<Client>,
+    revisions: web::Data<RevisionStore>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name, id_str) = path.into_inner();
+    let id = parse_id(&id_str);
+    let coll = data.database(&db_name).collection::<Document>(&coll_name);
+    let to = query.to.clone().unwrap_or_else(|| "current".to_string());
+
+    let before = match revision_document(&revisions, &coll, &id, &query.from).await {
+        Ok(d) => d,
+        Err(resp) => return Ok(resp),
+    };
+    let after = match revision_document(&revisions, &coll, &id, &to).await {
+        Ok(d) => d,
+        Err(resp) => return Ok(resp),
+    };
+    Ok(HttpResponse::Ok().json(json!({
+        "from": query.from,
+        "to": to,
+        "changes": diff_json(&before, &after),
+    })))
+}
+
+/// Puts a revision back in place, recreating the document if it was deleted. The state being
+/// overwritten is itself recorded, so a restore can be undone.
+#[post("/documents/{db_name}/{coll_name}/{id}/revisions/{revision}/restore")]
+pub async fn restore_revision(
+    path: web::Path<(String, String, String, String)>,
+    data: web::Data<Client>,
+    revisions: web::Data<RevisionStore>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name, id_str, revision_str) = path.into_inner();
+    let id = parse_id(&id_str);
+    let coll = data.database(&db_name).collection::<Document>(&coll_name);
+
+    let revision_id = match ObjectId::parse_str(&revision_str) {
+        Ok(oid) => oid,
+        Err(_) => return Ok(HttpResponse::BadRequest().body(format!("invalid revision id: {}", revision_str))),
+    };
+    let document = match revisions.get(revision_id).await {
+        Ok(Some(r)) if r.get_str("database") == Ok(db_name.as_str())
+            && r.get_str("collection") == Ok(coll_name.as_str())
+            && r.get("documentId") == Some(&id) =>
+        {
+            match r.get_document("document") {
+                Ok(d) => d.clone(),
+                Err(_) => return Ok(HttpResponse::InternalServerError().body("revision has no document")),
+            }
+        }
+        Ok(_) => return Ok(HttpResponse::NotFound().body("revision not found for this document")),
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("revision lookup failed: {}", e))),
+    };
+
+    let filter = doc! { "_id": id };
+    match coll.find_one(filter.clone()).await {
+        Ok(Some(current)) => revisions.record(&db_name, &coll_name, "restore", &current).await,
+        Ok(None) => {}
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("find_one failed: {}", e))),
+    }
+
+    match coll.replace_one(filter, document).upsert(true).await {
+        Ok(r) => Ok(HttpResponse::Ok().json(json!({
+            "restored": revision_str,
+            "undeleted": r.upserted_id.is_some(),
+            "matched": r.matched_count,
+            "modified": r.modified_count,
+        }))),
+        Err(e) => {
+            eprintln!("restore error: {}", e);
+            Ok(HttpResponse::InternalServerError().body(format!("restore failed: {}", e)))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DeletedQuery {
+    limit: Option<i64>,
+}
+
+/// Recently deleted documents of a collection that are still gone, for undelete.
+#[get("/revisions/{db_name}/{coll_name}/deleted")]
+pub async fn deleted_documents(
+    path: web::Path<(String, String)>,
+    query: web::Query<DeletedQuery>,
+    data: web::Data<Client>,
+    revisions: web::Data<RevisionStore>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let coll = data.database(&db_name).collection::<Document>(&coll_name);
+
+    let deleted = match revisions.deleted(&db_name, &coll_name, query.limit.unwrap_or(DEFAULT_DELETED_LIMIT)).await {
+        Ok(d) => d,
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("revision lookup failed: {}", e))),
+    };
+    let ids: Vec<Bson> = deleted.iter().filter_map(|r| r.get("documentId").cloned()).collect();
+    let existing: Vec<Bson> = match coll.find(doc! { "_id": { "$in": ids } }).projection(doc! { "_id": 1 }).await {
+        Ok(cursor) => match cursor.try_collect::<Vec<Document>>().await {
+            Ok(docs) => docs.into_iter().filter_map(|d| d.get("_id").cloned()).collect(),
+            Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("find failed: {}", e))),
+        },
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("find failed: {}", e))),
+    };
+
+    let items: Vec<JsonValue> = deleted
+        .iter()
+        .filter(|r| r.get("documentId").is_some_and(|id| !existing.contains(id)))
+        .map(|r| revision_json(r, &JsonValue::Null))
+        .map(|mut r| {
+            if let Some(obj) = r.as_object_mut() {
+                obj.remove("changes");
+            }
+            r
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(json!({ "database": db_name, "collection": coll_name, "deleted": items })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{revision_json, RevisionStore};
+    use mongodb::bson::doc;
+    use serde_json::json;
+
+    #[test]
+    fn describes_what_followed_a_revision() {
+        let mut revision = RevisionStore::revision("shop", "orders", "update", &doc! { "_id": 1, "status": "new" });
+        revision.insert("_id", mongodb::bson::oid::ObjectId::new());
+        let entry = revision_json(&revision, &json!({ "_id": 1, "status": "paid" }));
+        assert_eq!(entry["operation"], "update");
+        assert_eq!(entry["document"], json!({ "_id": 1, "status": "new" }));
+        assert_eq!(entry["changes"][0]["path"], "status");
+        assert_eq!(entry["changes"][0]["after"], "paid");
+    }
+}
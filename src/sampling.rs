@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, Bson, Document},
+    Collection,
+};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+pub const DEFAULT_SAMPLE_SIZE: i64 = 100;
+pub const DEFAULT_SAMPLE_VALUES: usize = 5;
+
+/// BSON type name as used by `$type` and `$jsonSchema`'s `bsonType`.
+pub fn bson_type_name(value: &Bson) -> &'static str {
+    match value {
+        Bson::Double(_) => "double",
+        Bson::String(_) => "string",
+        Bson::Array(_) => "array",
+        Bson::Document(_) => "object",
+        Bson::Boolean(_) => "bool",
+        Bson::Null => "null",
+        Bson::Int32(_) => "int",
+        Bson::Int64(_) => "long",
+        Bson::DateTime(_) => "date",
+        Bson::Decimal128(_) => "decimal",
+        Bson::ObjectId(_) => "objectId",
+        Bson::Binary(_) => "binData",
+        Bson::Timestamp(_) => "timestamp",
+        Bson::RegularExpression(_) => "regex",
+        Bson::JavaScriptCode(_) => "javascript",
+        Bson::JavaScriptCodeWithScope(_) => "javascriptWithScope",
+        Bson::Symbol(_) => "symbol",
+        Bson::Undefined => "undefined",
+        Bson::MaxKey => "maxKey",
+        Bson::MinKey => "minKey",
+        Bson::DbPointer(_) => "dbPointer",
+    }
+}
+
+/// Random sample of up to `size` documents via `$sample`.
+pub async fn sample_documents(coll: &Collection<Document>, size: i64) -> mongodb::error::Result<Vec<Document>> {
+    let pipeline = vec![doc! { "$sample": { "size": size.max(1) } }];
+    let cursor = coll.aggregate(pipeline).await?;
+    cursor.try_collect().await
+}
+
+/// Accumulated observations for one dotted path (`address.city`, `items[].sku`).
+#[derive(Default)]
+pub struct PathStats {
+    // Values seen at this path; array element paths count every element.
+    pub count: u64,
+    // Parent containers that had this path at least once.
+    pub present: u64,
+    pub types: BTreeMap<&'static str, u64>,
+    pub nulls: u64,
+    pub array_lengths: Vec<u64>,
+    pub samples: Vec<JsonValue>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArrayLengthSummary {
+    pub min: u64,
+    pub max: u64,
+    pub avg: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathSummary {
+    pub count: u64,
+    pub parent_count: u64,
+    pub types: Vec<String>,
+    pub type_percentages: BTreeMap<String, f64>,
+    pub null_percentage: f64,
+    pub missing_percentage: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub array_lengths: Option<ArrayLengthSummary>,
+    pub sample: JsonValue,
+    pub samples: Vec<JsonValue>,
+}
+
+fn percentage(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        return 0.0;
+    }
+    (part as f64 / whole as f64 * 10000.0).round() / 100.0
+}
+
+fn child_path(parent: &str, key: &str) -> String {
+    if parent.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", parent, key)
+    }
+}
+
+/// Path of the container a path lives in: `a.b` -> `a`, `a[]` -> `a`, `a[].b` -> `a[]`.
+fn parent_path(path: &str) -> Option<&str> {
+    if let Some(stripped) = path.strip_suffix("[]") {
+        return Some(stripped);
+    }
+    path.rfind('.').map(|i| &path[..i])
+}
+
+fn observe_value(paths: &mut BTreeMap<String, PathStats>, path: &str, value: &Bson, max_samples: usize) {
+    let entry = paths.entry(path.to_string()).or_default();
+    entry.count += 1;
+    *entry.types.entry(bson_type_name(value)).or_insert(0) += 1;
+    if matches!(value, Bson::Null) {
+        entry.nulls += 1;
+    }
+    if let Bson::Array(items) = value {
+        entry.array_lengths.push(items.len() as u64);
+    }
+    if entry.samples.len() < max_samples && !matches!(value, Bson::Document(_) | Bson::Array(_)) {
+        let json = serde_json::to_value(value).unwrap_or(JsonValue::Null);
+        if !entry.samples.contains(&json) {
+            entry.samples.push(json);
+        }
+    }
+
+    match value {
+        Bson::Document(d) => observe_document(paths, path, d, max_samples),
+        Bson::Array(items) => {
+            let element_path = format!("{}[]", path);
+            if !items.is_empty() {
+                paths.entry(element_path.clone()).or_default().present += 1;
+            }
+            for item in items {
+                observe_value(paths, &element_path, item, max_samples);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn observe_document(paths: &mut BTreeMap<String, PathStats>, prefix: &str, doc: &Document, max_samples: usize) {
+    for (key, value) in doc.iter() {
+        let path = child_path(prefix, key);
+        paths.entry(path.clone()).or_default().present += 1;
+        observe_value(paths, &path, value, max_samples);
+    }
+}
+
+/// Walks every sampled document, including embedded documents and array elements, and
+/// summarises each dotted path. Percentages are relative to the path's parent containers:
+/// the sampled documents for top-level fields, the embedded documents (or arrays) otherwise.
+pub fn analyze_paths(docs: &[Document], max_samples: usize) -> BTreeMap<String, PathSummary> {
+    let mut paths: BTreeMap<String, PathStats> = BTreeMap::new();
+    for doc in docs {
+        observe_document(&mut paths, "", doc, max_samples);
+    }
+
+    let mut out = BTreeMap::new();
+    for (path, stats) in &paths {
+        let parent_count = match parent_path(path) {
+            None => docs.len() as u64,
+            Some(parent) => {
+                let container = if path.ends_with("[]") { "array" } else { "object" };
+                paths
+                    .get(parent)
+                    .and_then(|p| p.types.get(container))
+                    .copied()
+                    .unwrap_or(0)
+            }
+        };
+
+        let mut types: Vec<(&str, u64)> = stats.types.iter().map(|(t, c)| (*t, *c)).collect();
+        types.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        let array_lengths = if stats.array_lengths.is_empty() {
+            None
+        } else {
+            let total: u64 = stats.array_lengths.iter().sum();
+            Some(ArrayLengthSummary {
+                min: stats.array_lengths.iter().copied().min().unwrap_or(0),
+                max: stats.array_lengths.iter().copied().max().unwrap_or(0),
+                avg: (total as f64 / stats.array_lengths.len() as f64 * 100.0).round() / 100.0,
+            })
+        };
+
+        out.insert(
+            path.clone(),
+            PathSummary {
+                count: stats.count,
+                parent_count,
+                types: types.iter().map(|(t, _)| t.to_string()).collect(),
+                type_percentages: types
+                    .iter()
+                    .map(|(t, c)| (t.to_string(), percentage(*c, stats.count)))
+                    .collect(),
+                null_percentage: percentage(stats.nulls, stats.count),
+                missing_percentage: percentage(parent_count.saturating_sub(stats.present), parent_count),
+                array_lengths,
+                sample: stats.samples.first().cloned().unwrap_or(JsonValue::Null),
+                samples: stats.samples.clone(),
+            },
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::analyze_paths;
+    use mongodb::bson::{doc, Bson};
+    use serde_json::json;
+
+    #[test]
+    fn analyzes_nested_paths() {
+        let docs = vec![
+            doc! { "name": "a", "address": { "city": "x" }, "items": [ { "sku": "s1" }, { "sku": "s2", "qty": 2_i32 } ] },
+            doc! { "name": Bson::Null, "address": { "city": "y", "zip": 1_i64 }, "items": [] },
+            doc! { "address": "unknown", "items": [ { "sku": "s1" } ] },
+            doc! { "name": "b", "items": [ { "sku": 7_i32 } ] },
+        ];
+        let paths = analyze_paths(&docs, 5);
+
+        let name = &paths["name"];
+        assert_eq!(name.count, 3);
+        assert_eq!(name.null_percentage, 33.33);
+        assert_eq!(name.missing_percentage, 25.0);
+
+        let address = &paths["address"];
+        assert_eq!(address.type_percentages["object"], 66.67);
+        assert_eq!(address.type_percentages["string"], 33.33);
+
+        // only the two embedded address documents count as parents of address.city
+        let city = &paths["address.city"];
+        assert_eq!(city.parent_count, 2);
+        assert_eq!(city.missing_percentage, 0.0);
+        assert_eq!(city.samples, vec![json!("x"), json!("y")]);
+        assert_eq!(paths["address.zip"].types, vec!["long"]);
+
+        let items = paths["items"].array_lengths.as_ref().unwrap();
+        assert_eq!((items.min, items.max, items.avg), (0, 2, 1.0));
+        assert_eq!(paths["items[]"].missing_percentage, 25.0);
+
+        let sku = &paths["items[].sku"];
+        assert_eq!(sku.count, 4);
+        assert_eq!(sku.parent_count, 4);
+        assert_eq!(sku.type_percentages["string"], 75.0);
+        assert_eq!(sku.samples, vec![json!("s1"), json!("s2"), json!(7)]);
+        assert_eq!(paths["items[].qty"].missing_percentage, 75.0);
+    }
+}
@@ -0,0 +1,805 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, Bson, Document},
+    Client, Collection, Database,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value as JsonValue};
+
+use crate::diff::diff_json;
+use crate::masking::MaskRules;
+use crate::state::AppInfo;
+use crate::store::JsonStore;
+
+const SCHEMA_FILE: &str = "schemas.json";
+const DEFAULT_FAILING_SAMPLE: i64 = 20;
+
+// Keywords MongoDB's $jsonSchema accepts unchanged. Everything else from the upload format
+// (format, default, ...) is either rewritten below or dropped, since collMod rejects it.
+const PASSTHROUGH_KEYWORDS: &[&str] = &[
+    "title",
+    "description",
+    "enum",
+    "minimum",
+    "maximum",
+    "exclusiveMinimum",
+    "exclusiveMaximum",
+    "multipleOf",
+    "minLength",
+    "maxLength",
+    "pattern",
+    "minItems",
+    "maxItems",
+    "uniqueItems",
+    "minProperties",
+    "maxProperties",
+];
+
+/// One version of a collection's schema. Entries are keyed by connection (the shortened URI, so
+/// credentials never land on disk), database and collection; every save appends a new version.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredSchema {
+    pub connection: String,
+    pub database: String,
+    pub collection: String,
+    pub version: u32,
+    pub schema: JsonValue,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl StoredSchema {
+    fn is_for(&self, connection: &str, database: &str, collection: &str) -> bool {
+        self.connection == connection && self.database == database && self.collection == collection
+    }
+}
+
+/// A collection's masking rules (see `masking`), kept with its schema under the same key.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredMasking {
+    pub connection: String,
+    pub database: String,
+    pub collection: String,
+    pub rules: MaskRules,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct SchemaFile {
+    schemas: Vec<StoredSchema>,
+    #[serde(default)]
+    masking: Vec<StoredMasking>,
+}
+
+pub struct SchemaStore {
+    store: JsonStore<SchemaFile>,
+}
+
+impl SchemaStore {
+    pub fn new() -> Self {
+        Self {
+            store: JsonStore::open(SCHEMA_FILE),
+        }
+    }
+
+    pub async fn latest(&self, connection: &str, database: &str, collection: &str) -> Option<StoredSchema> {
+        self.store
+            .read(|file| {
+                file.schemas
+                    .iter()
+                    .filter(|s| s.is_for(connection, database, collection))
+                    .max_by_key(|s| s.version)
+                    .cloned()
+            })
+            .await
+    }
+
+    pub async fn version(&self, connection: &str, database: &str, collection: &str, version: u32) -> Option<StoredSchema> {
+        self.store
+            .read(|file| {
+                file.schemas
+                    .iter()
+                    .find(|s| s.is_for(connection, database, collection) && s.version == version)
+                    .cloned()
+            })
+            .await
+    }
+
+    pub async fn history(&self, connection: &str, database: &str, collection: &str) -> Vec<StoredSchema> {
+        let mut versions: Vec<StoredSchema> = self
+            .store
+            .read(|file| {
+                file.schemas
+                    .iter()
+                    .filter(|s| s.is_for(connection, database, collection))
+                    .cloned()
+                    .collect()
+            })
+            .await;
+        versions.sort_by_key(|s| s.version);
+        versions
+    }
+
+    /// Latest version of every collection schema stored for `database`.
+    pub async fn list(&self, connection: &str, database: &str) -> Vec<StoredSchema> {
+        let mut latest: Vec<StoredSchema> = Vec::new();
+        self.store
+            .read(|file| {
+                for s in file.schemas.iter().filter(|s| s.connection == connection && s.database == database) {
+                    match latest.iter_mut().find(|l| l.collection == s.collection) {
+                        Some(existing) if existing.version >= s.version => {}
+                        Some(existing) => *existing = s.clone(),
+                        None => latest.push(s.clone()),
+                    }
+                }
+            })
+            .await;
+        latest.sort_by(|a, b| a.collection.cmp(&b.collection));
+        latest
+    }
+
+    /// Appends a new version, unless `schema` is identical to the latest one.
+    pub async fn put(&self, connection: &str, database: &str, collection: &str, schema: JsonValue) -> std::io::Result<StoredSchema> {
+        self.store
+            .update(|file| {
+                let latest = file
+                    .schemas
+                    .iter()
+                    .filter(|s| s.is_for(connection, database, collection))
+                    .max_by_key(|s| s.version);
+                if let Some(current) = latest
+                    && current.schema == schema
+                {
+                    return current.clone();
+                }
+                let stored = StoredSchema {
+                    connection: connection.to_string(),
+                    database: database.to_string(),
+                    collection: collection.to_string(),
+                    version: latest.map(|s| s.version + 1).unwrap_or(1),
+                    schema,
+                    updated_at: Utc::now(),
+                };
+                file.schemas.push(stored.clone());
+                stored
+            })
+            .await
+    }
+
+    /// Removes every version; returns how many were dropped.
+    pub async fn delete(&self, connection: &str, database: &str, collection: &str) -> std::io::Result<usize> {
+        self.store
+            .update(|file| {
+                let before = file.schemas.len();
+                file.schemas.retain(|s| !s.is_for(connection, database, collection));
+                before - file.schemas.len()
+            })
+            .await
+    }
+
+    pub async fn masking(&self, connection: &str, database: &str, collection: &str) -> Option<MaskRules> {
+        self.store
+            .read(|file| {
+                file.masking
+                    .iter()
+                    .find(|m| m.connection == connection && m.database == database && m.collection == collection)
+                    .map(|m| m.rules.clone())
+            })
+            .await
+    }
+
+    /// Replaces the rule set; masking rules are not versioned.
+    pub async fn put_masking(&self, connection: &str, database: &str, collection: &str, rules: MaskRules) -> std::io::Result<()> {
+        self.store
+            .update(|file| {
+                file.masking
+                    .retain(|m| !(m.connection == connection && m.database == database && m.collection == collection));
+                file.masking.push(StoredMasking {
+                    connection: connection.to_string(),
+                    database: database.to_string(),
+                    collection: collection.to_string(),
+                    rules,
+                    updated_at: Utc::now(),
+                });
+            })
+            .await
+    }
+
+    pub async fn delete_masking(&self, connection: &str, database: &str, collection: &str) -> std::io::Result<bool> {
+        self.store
+            .update(|file| {
+                let before = file.masking.len();
+                file.masking
+                    .retain(|m| !(m.connection == connection && m.database == database && m.collection == collection));
+                before != file.masking.len()
+            })
+            .await
+    }
+}
+
+fn map_type_name(name: &str) -> Vec<&'static str> {
+    match name {
+        "string" => vec!["string"],
+        "number" => vec!["double", "int", "long", "decimal"],
+        "integer" => vec!["int", "long"],
+        "boolean" | "bool" => vec!["bool"],
+        "object" => vec!["object"],
+        "array" => vec!["array"],
+        "null" => vec!["null"],
+        "date" => vec!["date"],
+        "objectId" | "objectid" => vec!["objectId"],
+        "double" => vec!["double"],
+        "int" => vec!["int"],
+        "long" => vec!["long"],
+        "decimal" => vec!["decimal"],
+        "binData" => vec!["binData"],
+        "timestamp" => vec!["timestamp"],
+        _ => Vec::new(),
+    }
+}
+
+fn map_type(value: &JsonValue) -> Option<JsonValue> {
+    let names: Vec<&str> = match value {
+        JsonValue::String(s) => vec![s.as_str()],
+        JsonValue::Array(items) => items.iter().filter_map(|v| v.as_str()).collect(),
+        _ => return None,
+    };
+    let mut mapped: Vec<&str> = Vec::new();
+    for name in names {
+        for t in map_type_name(name) {
+            if !mapped.contains(&t) {
+                mapped.push(t);
+            }
+        }
+    }
+    match mapped.len() {
+        0 => None,
+        1 => Some(JsonValue::String(mapped[0].to_string())),
+        _ => Some(JsonValue::Array(mapped.into_iter().map(|t| JsonValue::String(t.to_string())).collect())),
+    }
+}
+
+/// Converts one entry of the upload format (`{"type": ..., "properties": ...}` with per-property
+/// `"required": true`) into a MongoDB `$jsonSchema` document using `bsonType`.
+pub fn to_json_schema(spec: &JsonValue) -> JsonValue {
+    let obj = match spec.as_object() {
+        Some(o) => o,
+        None => return json!({}),
+    };
+    let mut out = Map::new();
+
+    if let Some(bson_type) = obj.get("bsonType") {
+        out.insert("bsonType".to_string(), bson_type.clone());
+    } else if let Some(mapped) = obj.get("type").and_then(map_type) {
+        out.insert("bsonType".to_string(), mapped);
+    } else if obj.contains_key("properties") {
+        out.insert("bsonType".to_string(), json!("object"));
+    }
+
+    for key in PASSTHROUGH_KEYWORDS {
+        if let Some(v) = obj.get(*key) {
+            out.insert(key.to_string(), v.clone());
+        }
+    }
+
+    let mut required: Vec<JsonValue> = match obj.get("required") {
+        Some(JsonValue::Array(names)) => names.clone(),
+        _ => Vec::new(),
+    };
+    if let Some(props) = obj.get("properties").and_then(|p| p.as_object()) {
+        let mut converted = Map::new();
+        for (name, prop) in props {
+            let name_value = JsonValue::String(name.clone());
+            if prop.get("required") == Some(&JsonValue::Bool(true)) && !required.contains(&name_value) {
+                required.push(name_value);
+            }
+            converted.insert(name.clone(), to_json_schema(prop));
+        }
+        out.insert("properties".to_string(), JsonValue::Object(converted));
+    }
+    if !required.is_empty() {
+        out.insert("required".to_string(), JsonValue::Array(required));
+    }
+
+    match obj.get("items") {
+        Some(JsonValue::Array(items)) => {
+            out.insert("items".to_string(), JsonValue::Array(items.iter().map(to_json_schema).collect()));
+        }
+        Some(items @ JsonValue::Object(_)) => {
+            out.insert("items".to_string(), to_json_schema(items));
+        }
+        _ => {}
+    }
+
+    match obj.get("additionalProperties") {
+        Some(JsonValue::Bool(b)) => {
+            out.insert("additionalProperties".to_string(), JsonValue::Bool(*b));
+        }
+        Some(extra @ JsonValue::Object(_)) => {
+            out.insert("additionalProperties".to_string(), to_json_schema(extra));
+        }
+        _ => {}
+    }
+
+    JsonValue::Object(out)
+}
+
+/// Like `to_json_schema`, but guarantees an object at the root as collMod requires.
+pub fn collection_validator(spec: &JsonValue) -> JsonValue {
+    let mut schema = to_json_schema(spec);
+    if let JsonValue::Object(map) = &mut schema {
+        map.insert("bsonType".to_string(), json!("object"));
+    }
+    schema
+}
+
+pub fn json_to_document(value: &JsonValue) -> Result<Document, String> {
+    match bson::to_bson(value) {
+        Ok(Bson::Document(d)) => Ok(d),
+        Ok(_) => Err("$jsonSchema must be an object".to_string()),
+        Err(e) => Err(format!("invalid $jsonSchema: {}", e)),
+    }
+}
+
+fn stored_json(stored: &StoredSchema) -> JsonValue {
+    json!({
+        "connection": stored.connection,
+        "database": stored.database,
+        "collection": stored.collection,
+        "version": stored.version,
+        "schema": stored.schema,
+        "jsonSchema": collection_validator(&stored.schema),
+        "updatedAt": stored.updated_at.to_rfc3339(),
+    })
+}
+
+#[derive(Deserialize)]
+pub struct SchemaVersionQuery {
+    version: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct SchemaDiffQuery {
+    // Defaults to the version before `to`.
+    from: Option<u32>,
+    // Defaults to the latest version.
+    to: Option<u32>,
+}
+
+#[post("/schemas/{db_name}")]
+pub async fn upload_schemas(
+    path: web::Path<String>,
+    body: web::Json<JsonValue>,
+    schemas: web::Data<SchemaStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let db_name = path.into_inner();
+    let entries = match body.into_inner() {
+        JsonValue::Object(map) if !map.is_empty() => map,
+        _ => return Ok(HttpResponse::BadRequest().body("expected an object keyed by collection name")),
+    };
+    if let Some((name, _)) = entries.iter().find(|(_, spec)| !spec.is_object()) {
+        return Ok(HttpResponse::BadRequest().body(format!("schema for {} must be an object", name)));
+    }
+
+    let mut saved = Vec::new();
+    for (collection, spec) in entries {
+        match schemas.put(&app_info.shortened_uri, &db_name, &collection, spec).await {
+            Ok(stored) => saved.push(stored_json(&stored)),
+            Err(e) => {
+                eprintln!("schema store error: {}", e);
+                return Ok(HttpResponse::InternalServerError().body("failed to store schema"));
+            }
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(json!({"database": db_name, "schemas": saved})))
+}
+
+#[get("/schemas/{db_name}")]
+pub async fn list_schemas(
+    path: web::Path<String>,
+    schemas: web::Data<SchemaStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let db_name = path.into_inner();
+    let list: Vec<JsonValue> = schemas
+        .list(&app_info.shortened_uri, &db_name)
+        .await
+        .iter()
+        .map(stored_json)
+        .collect();
+    Ok(HttpResponse::Ok().json(json!({"database": db_name, "schemas": list})))
+}
+
+#[get("/schemas/{db_name}/{coll_name}")]
+pub async fn get_schema(
+    path: web::Path<(String, String)>,
+    query: web::Query<SchemaVersionQuery>,
+    schemas: web::Data<SchemaStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let connection = &app_info.shortened_uri;
+    let stored = match query.version {
+        Some(v) => schemas.version(connection, &db_name, &coll_name, v).await,
+        None => schemas.latest(connection, &db_name, &coll_name).await,
+    };
+    match stored {
+        Some(stored) => Ok(HttpResponse::Ok().json(stored_json(&stored))),
+        None => Ok(HttpResponse::NotFound().body("schema not found")),
+    }
+}
+
+#[put("/schemas/{db_name}/{coll_name}")]
+pub async fn put_schema(
+    path: web::Path<(String, String)>,
+    body: web::Json<JsonValue>,
+    schemas: web::Data<SchemaStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let spec = body.into_inner();
+    if !spec.is_object() {
+        return Ok(HttpResponse::BadRequest().body("schema must be an object"));
+    }
+    match schemas.put(&app_info.shortened_uri, &db_name, &coll_name, spec).await {
+        Ok(stored) => Ok(HttpResponse::Ok().json(stored_json(&stored))),
+        Err(e) => {
+            eprintln!("schema store error: {}", e);
+            Ok(HttpResponse::InternalServerError().body("failed to store schema"))
+        }
+    }
+}
+
+#[delete("/schemas/{db_name}/{coll_name}")]
+pub async fn delete_schema(
+    path: web::Path<(String, String)>,
+    schemas: web::Data<SchemaStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    match schemas.delete(&app_info.shortened_uri, &db_name, &coll_name).await {
+        Ok(0) => Ok(HttpResponse::NotFound().body("schema not found")),
+        Ok(removed) => Ok(HttpResponse::Ok().json(json!({"deletedVersions": removed}))),
+        Err(e) => {
+            eprintln!("schema store error: {}", e);
+            Ok(HttpResponse::InternalServerError().body("failed to delete schema"))
+        }
+    }
+}
+
+#[get("/schemas/{db_name}/{coll_name}/versions")]
+pub async fn schema_history(
+    path: web::Path<(String, String)>,
+    schemas: web::Data<SchemaStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let versions: Vec<JsonValue> = schemas
+        .history(&app_info.shortened_uri, &db_name, &coll_name)
+        .await
+        .iter()
+        .map(|s| json!({"version": s.version, "updatedAt": s.updated_at.to_rfc3339()}))
+        .collect();
+    if versions.is_empty() {
+        return Ok(HttpResponse::NotFound().body("schema not found"));
+    }
+    Ok(HttpResponse::Ok().json(json!({
+        "database": db_name,
+        "collection": coll_name,
+        "versions": versions,
+    })))
+}
+
+#[get("/schemas/{db_name}/{coll_name}/diff")]
+pub async fn diff_schema_versions(
+    path: web::Path<(String, String)>,
+    query: web::Query<SchemaDiffQuery>,
+    schemas: web::Data<SchemaStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let connection = &app_info.shortened_uri;
+
+    let to = match query.to {
+        Some(v) => schemas.version(connection, &db_name, &coll_name, v).await,
+        None => schemas.latest(connection, &db_name, &coll_name).await,
+    };
+    let to = match to {
+        Some(s) => s,
+        None => return Ok(HttpResponse::NotFound().body("schema version not found")),
+    };
+    // the first version has nothing before it, so it is diffed against an empty schema
+    // (from: null) and every field shows up as added
+    let (from_version, from_schema) = match query.from {
+        None if to.version <= 1 => (None, json!({})),
+        requested => {
+            let version = requested.unwrap_or(to.version - 1);
+            match schemas.version(connection, &db_name, &coll_name, version).await {
+                Some(s) => (Some(s.version), s.schema),
+                None => return Ok(HttpResponse::NotFound().body("schema version not found")),
+            }
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(json!({
+        "database": db_name,
+        "collection": coll_name,
+        "from": from_version,
+        "to": to.version,
+        "changes": diff_json(&from_schema, &to.schema),
+    })))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidatorRequest {
+    // Edited $jsonSchema; defaults to the one derived from the stored upload.
+    json_schema: Option<JsonValue>,
+    validation_level: Option<String>,
+    validation_action: Option<String>,
+    // How many failing _ids to return from the dry run.
+    sample: Option<i64>,
+    // install even though existing documents would fail an erroring validator
+    force: Option<bool>,
+}
+
+async fn resolve_validator(
+    schemas: &SchemaStore,
+    connection: &str,
+    db_name: &str,
+    coll_name: &str,
+    override_schema: Option<JsonValue>,
+) -> Result<Document, HttpResponse> {
+    let schema = match override_schema {
+        Some(s) => s,
+        None => match schemas.latest(connection, db_name, coll_name).await {
+            Some(stored) => collection_validator(&stored.schema),
+            None => {
+                return Err(HttpResponse::NotFound().body(format!(
+                    "no schema stored for {}.{}",
+                    db_name, coll_name
+                )))
+            }
+        },
+    };
+    json_to_document(&schema)
+        .map(|s| doc! { "$jsonSchema": s })
+        .map_err(|e| HttpResponse::BadRequest().body(e))
+}
+
+/// Counts documents that would fail `validator`, with a sample of their `_id`s.
+async fn dry_run(coll: &Collection<Document>, validator: &Document, sample: i64) -> mongodb::error::Result<JsonValue> {
+    let failing_filter = doc! { "$nor": [validator.clone()] };
+    // exact, like the failing count beside it; the metadata estimate can be off after crashes
+    // or on sharded clusters with orphaned documents
+    let total = coll.count_documents(doc! {}).await?;
+    let failing = coll.count_documents(failing_filter.clone()).await?;
+    let cursor = coll
+        .find(failing_filter)
+        .projection(doc! { "_id": 1 })
+        .limit(sample)
+        .await?;
+    let ids: Vec<Document> = cursor.try_collect().await?;
+    let sample_ids: Vec<JsonValue> = ids
+        .iter()
+        .filter_map(|d| d.get("_id"))
+        .map(|id| serde_json::to_value(id).unwrap_or(JsonValue::Null))
+        .collect();
+
+    Ok(json!({
+        "totalDocuments": total,
+        "failingDocuments": failing,
+        "failingSample": sample_ids,
+    }))
+}
+
+#[get("/collections/{db_name}/{coll_name}/validator")]
+pub async fn current_validator(path: web::Path<(String, String)>, data: web::Data<Client>) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let db = data.database(&db_name);
+    let mut cursor = match db.list_collections().filter(doc! { "name": &coll_name }).await {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("list collections error: {}", e);
+            return Ok(HttpResponse::InternalServerError().body("failed to read collection options"));
+        }
+    };
+    match cursor.try_next().await {
+        Ok(Some(spec)) => {
+            let validator = spec
+                .options
+                .validator
+                .map(|v| serde_json::to_value(&v).unwrap_or(JsonValue::Null))
+                .unwrap_or(JsonValue::Null);
+            Ok(HttpResponse::Ok().json(json!({
+                "database": db_name,
+                "collection": coll_name,
+                "validator": validator,
+                "validationLevel": serde_json::to_value(&spec.options.validation_level).unwrap_or(JsonValue::Null),
+                "validationAction": serde_json::to_value(&spec.options.validation_action).unwrap_or(JsonValue::Null),
+            })))
+        }
+        Ok(None) => Ok(HttpResponse::NotFound().body("collection not found")),
+        Err(e) => {
+            eprintln!("list collections cursor error: {}", e);
+            Ok(HttpResponse::InternalServerError().body("failed to read collection options"))
+        }
+    }
+}
+
+#[post("/collections/{db_name}/{coll_name}/validator/dry-run")]
+pub async fn dry_run_validator(
+    path: web::Path<(String, String)>,
+    body: web::Json<ValidatorRequest>,
+    data: web::Data<Client>,
+    schemas: web::Data<SchemaStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let req = body.into_inner();
+    let validator = match resolve_validator(&schemas, &app_info.shortened_uri, &db_name, &coll_name, req.json_schema).await {
+        Ok(v) => v,
+        Err(resp) => return Ok(resp),
+    };
+    let coll = data.database(&db_name).collection::<Document>(&coll_name);
+    match dry_run(&coll, &validator, req.sample.unwrap_or(DEFAULT_FAILING_SAMPLE)).await {
+        Ok(report) => Ok(HttpResponse::Ok().json(json!({
+            "database": db_name,
+            "collection": coll_name,
+            "validator": serde_json::to_value(&validator).unwrap_or(JsonValue::Null),
+            "dryRun": report,
+        }))),
+        Err(e) => {
+            eprintln!("validator dry run error: {}", e);
+            Ok(HttpResponse::InternalServerError().body(format!("dry run failed: {}", e)))
+        }
+    }
+}
+
+/// Dry-runs `validator` against the existing documents and then installs it with collMod.
+/// When documents would fail a validator that rejects writes (`validationAction: "error"`), it is
+/// only installed with `force`; otherwise the answer is a 409 carrying the dry-run report.
+pub async fn install_validator(
+    db: &Database,
+    coll_name: &str,
+    validator: Document,
+    validation_level: Option<String>,
+    validation_action: Option<String>,
+    sample: Option<i64>,
+    force: bool,
+) -> Result<JsonValue, HttpResponse> {
+    let level = validation_level.unwrap_or_else(|| "strict".to_string());
+    if !["off", "strict", "moderate"].contains(&level.as_str()) {
+        return Err(HttpResponse::BadRequest().body("validationLevel must be off, strict or moderate"));
+    }
+    let action = validation_action.unwrap_or_else(|| "error".to_string());
+    if !["error", "warn"].contains(&action.as_str()) {
+        return Err(HttpResponse::BadRequest().body("validationAction must be error or warn"));
+    }
+
+    let coll = db.collection::<Document>(coll_name);
+    let report = match dry_run(&coll, &validator, sample.unwrap_or(DEFAULT_FAILING_SAMPLE)).await {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("validator dry run error: {}", e);
+            return Err(HttpResponse::InternalServerError().body(format!("dry run failed: {}", e)));
+        }
+    };
+    let failing = report.get("failingDocuments").and_then(JsonValue::as_u64).unwrap_or(0);
+    if failing > 0 && action == "error" && level != "off" && !force {
+        return Err(HttpResponse::Conflict().json(json!({
+            "applied": false,
+            "error": format!("{} existing documents fail this validator; set force to install it anyway", failing),
+            "database": db.name(),
+            "collection": coll_name,
+            "validator": serde_json::to_value(&validator).unwrap_or(JsonValue::Null),
+            "dryRun": report,
+        })));
+    }
+
+    let command = doc! {
+        "collMod": coll_name,
+        "validator": validator.clone(),
+        "validationLevel": &level,
+        "validationAction": &action,
+    };
+    match db.run_command(command).await {
+        Ok(_) => Ok(json!({
+            "applied": true,
+            "validationLevel": level,
+            "validationAction": action,
+            "validator": serde_json::to_value(&validator).unwrap_or(JsonValue::Null),
+            "dryRun": report,
+        })),
+        Err(e) => {
+            eprintln!("collMod validator error: {}", e);
+            Err(HttpResponse::InternalServerError().body(format!("apply validator failed: {}", e)))
+        }
+    }
+}
+
+#[post("/collections/{db_name}/{coll_name}/validator")]
+pub async fn apply_validator(
+    path: web::Path<(String, String)>,
+    body: web::Json<ValidatorRequest>,
+    data: web::Data<Client>,
+    schemas: web::Data<SchemaStore>,
+    app_info: web::Data<AppInfo>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let req = body.into_inner();
+
+    let validator = match resolve_validator(&schemas, &app_info.shortened_uri, &db_name, &coll_name, req.json_schema).await {
+        Ok(v) => v,
+        Err(resp) => return Ok(resp),
+    };
+    let db = data.database(&db_name);
+    match install_validator(
+        &db,
+        &coll_name,
+        validator,
+        req.validation_level,
+        req.validation_action,
+        req.sample,
+        req.force.unwrap_or(false),
+    )
+    .await {
+        Ok(mut result) => {
+            result["database"] = json!(db_name);
+            result["collection"] = json!(coll_name);
+            Ok(HttpResponse::Ok().json(result))
+        }
+        Err(resp) => Ok(resp),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collection_validator, to_json_schema};
+    use serde_json::json;
+
+    #[test]
+    fn converts_readme_format() {
+        let spec = json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "required": true },
+                "email": { "type": "string", "format": "email" },
+                "age": { "type": "number", "minimum": 0 },
+                "status": { "type": "string", "enum": ["active", "inactive"], "default": "active" }
+            }
+        });
+        assert_eq!(
+            to_json_schema(&spec),
+            json!({
+                "bsonType": "object",
+                "required": ["name"],
+                "properties": {
+                    "name": { "bsonType": "string" },
+                    "email": { "bsonType": "string" },
+                    "age": { "bsonType": ["double", "int", "long", "decimal"], "minimum": 0 },
+                    "status": { "bsonType": "string", "enum": ["active", "inactive"] }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn converts_nested_objects_and_arrays() {
+        let spec = json!({
+            "properties": {
+                "tags": { "type": "array", "items": { "type": "string" } },
+                "prefs": { "type": "object", "properties": { "theme": { "type": "string", "required": true } } }
+            }
+        });
+        let schema = collection_validator(&spec);
+        assert_eq!(schema["bsonType"], json!("object"));
+        assert_eq!(schema["properties"]["tags"]["items"], json!({ "bsonType": "string" }));
+        assert_eq!(schema["properties"]["prefs"]["required"], json!(["theme"]));
+    }
+}
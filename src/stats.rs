@@ -1 +1,505 @@
-use mongodb::*;
\ No newline at end of file
+use actix_web::{get, web, HttpResponse};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, Bson, Document},
+    Client, Collection,
+};
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+
+const DEFAULT_STATS_SAMPLE: i64 = 1000;
+const DEFAULT_TOP_N: i64 = 10;
+const DEFAULT_BUCKETS: i32 = 10;
+const PERCENTILES: [f64; 6] = [0.25, 0.5, 0.75, 0.9, 0.95, 0.99];
+const DEFAULT_TOP_FIELDS: i64 = 20;
+const MAX_BSON_SIZE: i64 = 16 * 1024 * 1024;
+// Documents at or above this share of the 16MB limit are flagged as near the limit.
+const NEAR_LIMIT_PERCENTAGE: f64 = 50.0;
+// maxWireVersion of MongoDB 7.0, the first server with `$percentile`
+const PERCENTILE_WIRE_VERSION: i32 = 21;
+
+#[derive(Deserialize)]
+pub struct FieldStatsQuery {
+    // "sampled" (default) runs over a $sample, "full" scans the whole collection
+    mode: Option<String>,
+    sample: Option<i64>,
+    top: Option<i64>,
+    buckets: Option<i32>,
+}
+
+/// Turns a dotted path that may step into arrays (`items[].sku`) into the `$unwind` stages
+/// needed to reach it and the plain field path to read afterwards (`items.sku`).
+pub fn unwind_path(path: &str) -> (Vec<Document>, String) {
+    let mut stages = Vec::new();
+    let mut plain = String::new();
+    for (i, segment) in path.split('.').enumerate() {
+        if i > 0 {
+            plain.push('.');
+        }
+        let name = segment.trim_end_matches("[]");
+        plain.push_str(name);
+        for _ in 0..(segment.len() - name.len()) / 2 {
+            stages.push(doc! { "$unwind": format!("${}", plain) });
+        }
+    }
+    (stages, plain)
+}
+
+/// Goodman/GEE distinct-value estimate from a uniform sample: values seen once in the sample
+/// are scaled up by sqrt(population / sample), values seen more than once are counted as-is.
+pub fn estimate_cardinality(distinct: i64, singletons: i64, scale: f64) -> i64 {
+    if scale <= 1.0 {
+        return distinct;
+    }
+    (scale.sqrt() * singletons as f64 + (distinct - singletons) as f64).round() as i64
+}
+
+fn as_f64(value: Option<&Bson>) -> Option<f64> {
+    match value {
+        Some(Bson::Double(v)) => Some(*v),
+        Some(Bson::Int32(v)) => Some(*v as f64),
+        Some(Bson::Int64(v)) => Some(*v as f64),
+        Some(Bson::Decimal128(v)) => v.to_string().parse::<f64>().ok(),
+        _ => None,
+    }
+}
+
+fn as_i64(value: Option<&Bson>) -> i64 {
+    as_f64(value).map(|v| v as i64).unwrap_or(0)
+}
+
+fn to_json(value: &Bson) -> JsonValue {
+    serde_json::to_value(value).unwrap_or(JsonValue::Null)
+}
+
+fn first_doc<'a>(facets: &'a Document, key: &str) -> Option<&'a Document> {
+    facets
+        .get_array(key)
+        .ok()
+        .and_then(|a| a.first())
+        .and_then(|b| b.as_document())
+}
+
+fn buckets_json(facets: &Document, key: &str) -> Vec<JsonValue> {
+    facets
+        .get_array(key)
+        .map(|buckets| {
+            buckets
+                .iter()
+                .filter_map(|b| b.as_document())
+                .map(|b| {
+                    let range = b.get_document("_id").ok();
+                    json!({
+                        "from": range.and_then(|r| r.get("min")).map(to_json).unwrap_or(JsonValue::Null),
+                        "to": range.and_then(|r| r.get("max")).map(to_json).unwrap_or(JsonValue::Null),
+                        "count": as_i64(b.get("count")),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+async fn run_single(
+    coll: &Collection<Document>,
+    pipeline: Vec<Document>,
+    allow_disk_use: bool,
+) -> mongodb::error::Result<Option<Document>> {
+    let mut cursor = coll.aggregate(pipeline).allow_disk_use(allow_disk_use).await?;
+    cursor.try_next().await
+}
+
+async fn supports_percentile(client: &Client) -> bool {
+    match client.database("admin").run_command(doc! { "hello": 1 }).await {
+        Ok(hello) => hello.get_i32("maxWireVersion").unwrap_or(0) >= PERCENTILE_WIRE_VERSION,
+        Err(e) => {
+            eprintln!("hello failed: {}", e);
+            false
+        }
+    }
+}
+
+#[get("/collections/{db_name}/{coll_name}/fields/{field}/stats")]
+pub async fn field_stats(
+    path: web::Path<(String, String, String)>,
+    query: web::Query<FieldStatsQuery>,
+    data: web::Data<Client>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name, field) = path.into_inner();
+    let coll = data.database(&db_name).collection::<Document>(&coll_name);
+
+    let full_scan = match query.mode.as_deref() {
+        None | Some("sampled") => false,
+        Some("full") => true,
+        Some(other) => return Ok(HttpResponse::BadRequest().body(format!("unknown mode: {}", other))),
+    };
+    let sample_size = query.sample.unwrap_or(DEFAULT_STATS_SAMPLE).max(1);
+    let top = query.top.unwrap_or(DEFAULT_TOP_N).max(1);
+    let buckets = query.buckets.unwrap_or(DEFAULT_BUCKETS).max(1);
+
+    let total_documents = match coll.estimated_document_count().await {
+        Ok(n) => n as i64,
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("count failed: {}", e))),
+    };
+    // In sampled mode the sample stands in for scale x as many documents.
+    let scale = if full_scan || total_documents <= sample_size {
+        1.0
+    } else {
+        total_documents as f64 / sample_size as f64
+    };
+
+    let (unwinds, plain_path) = unwind_path(&field);
+    let mut base: Vec<Document> = Vec::new();
+    if !full_scan {
+        base.push(doc! { "$sample": { "size": sample_size } });
+    }
+    base.extend(unwinds);
+    base.push(doc! { "$project": { "_id": 0, "v": format!("${}", plain_path) } });
+    base.push(doc! { "$match": { "v": { "$exists": true } } });
+
+    let mut pipeline = base;
+    let mut facet = doc! {
+        "summary": [
+            { "$group": { "_id": Bson::Null, "count": { "$sum": 1 }, "nulls": { "$sum": { "$cond": [ { "$eq": [ "$v", Bson::Null ] }, 1, 0 ] } } } },
+        ],
+        "types": [
+            { "$group": { "_id": { "$type": "$v" }, "count": { "$sum": 1 } } },
+            { "$sort": { "count": -1 } },
+        ],
+        "top": [
+            { "$group": { "_id": "$v", "count": { "$sum": 1 } } },
+            { "$sort": { "count": -1, "_id": 1 } },
+            { "$limit": top },
+        ],
+        "cardinality": [
+            { "$group": { "_id": "$v", "c": { "$sum": 1 } } },
+            { "$group": { "_id": Bson::Null, "distinct": { "$sum": 1 }, "singletons": { "$sum": { "$cond": [ { "$eq": [ "$c", 1 ] }, 1, 0 ] } } } },
+        ],
+        "numeric": [
+            { "$match": { "v": { "$type": "number" } } },
+            { "$group": { "_id": Bson::Null, "count": { "$sum": 1 }, "min": { "$min": "$v" }, "max": { "$max": "$v" }, "mean": { "$avg": "$v" }, "stdDev": { "$stdDevPop": "$v" } } },
+        ],
+        "dates": [
+            { "$match": { "v": { "$type": "date" } } },
+            { "$group": { "_id": Bson::Null, "count": { "$sum": 1 }, "min": { "$min": "$v" }, "max": { "$max": "$v" } } },
+        ],
+        "dateHistogram": [
+            { "$match": { "v": { "$type": "date" } } },
+            { "$bucketAuto": { "groupBy": "$v", "buckets": buckets } },
+        ],
+        "strings": [
+            { "$match": { "v": { "$type": "string" } } },
+            { "$project": { "len": { "$strLenCP": "$v" } } },
+            { "$group": { "_id": Bson::Null, "count": { "$sum": 1 }, "min": { "$min": "$len" }, "max": { "$max": "$len" }, "mean": { "$avg": "$len" } } },
+        ],
+        "stringLengthHistogram": [
+            { "$match": { "v": { "$type": "string" } } },
+            { "$project": { "len": { "$strLenCP": "$v" } } },
+            { "$bucketAuto": { "groupBy": "$len", "buckets": buckets } },
+        ],
+    };
+    // in the same $facet so percentiles describe the same sample; older servers just go without
+    if supports_percentile(&data).await {
+        facet.insert("percentiles", vec![
+            doc! { "$match": { "v": { "$type": "number" } } },
+            doc! { "$group": { "_id": Bson::Null, "p": { "$percentile": { "input": "$v", "p": PERCENTILES.to_vec(), "method": "approximate" } } } },
+        ]);
+    }
+    pipeline.push(doc! { "$facet": facet });
+
+    let facets = match run_single(&coll, pipeline, full_scan).await {
+        Ok(Some(d)) => d,
+        Ok(None) => Document::new(),
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("aggregate failed: {}", e))),
+    };
+
+    let summary = first_doc(&facets, "summary");
+    let count = as_i64(summary.and_then(|s| s.get("count")));
+    let nulls = as_i64(summary.and_then(|s| s.get("nulls")));
+
+    let types: Vec<JsonValue> = facets
+        .get_array("types")
+        .map(|a| {
+            a.iter()
+                .filter_map(|t| t.as_document())
+                .map(|t| json!({"type": t.get("_id").map(to_json).unwrap_or(JsonValue::Null), "count": as_i64(t.get("count"))}))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let top_values: Vec<JsonValue> = facets
+        .get_array("top")
+        .map(|a| {
+            a.iter()
+                .filter_map(|t| t.as_document())
+                .map(|t| {
+                    let c = as_i64(t.get("count"));
+                    json!({
+                        "value": t.get("_id").map(to_json).unwrap_or(JsonValue::Null),
+                        "count": c,
+                        "percentage": if count > 0 { (c as f64 / count as f64 * 10000.0).round() / 100.0 } else { 0.0 },
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let card = first_doc(&facets, "cardinality");
+    let distinct = as_i64(card.and_then(|c| c.get("distinct")));
+    let singletons = as_i64(card.and_then(|c| c.get("singletons")));
+    let cardinality = if scale <= 1.0 {
+        json!({"value": distinct, "exact": true})
+    } else {
+        json!({
+            "value": estimate_cardinality(distinct, singletons, scale),
+            "exact": false,
+            "distinctInSample": distinct,
+        })
+    };
+
+    let numeric = match first_doc(&facets, "numeric") {
+        Some(n) => {
+            let percentiles = match first_doc(&facets, "percentiles").and_then(|d| d.get_array("p").ok()) {
+                Some(values) => {
+                    let mut out = serde_json::Map::new();
+                    for (p, v) in PERCENTILES.iter().zip(values.iter()) {
+                        out.insert(format!("p{}", (p * 100.0) as i64), json!(as_f64(Some(v))));
+                    }
+                    JsonValue::Object(out)
+                }
+                None => JsonValue::Null,
+            };
+            json!({
+                "count": as_i64(n.get("count")),
+                "min": as_f64(n.get("min")),
+                "max": as_f64(n.get("max")),
+                "mean": as_f64(n.get("mean")),
+                "stdDev": as_f64(n.get("stdDev")),
+                "percentiles": percentiles,
+            })
+        }
+        None => JsonValue::Null,
+    };
+
+    let dates = match first_doc(&facets, "dates") {
+        Some(d) => json!({
+            "count": as_i64(d.get("count")),
+            "min": d.get("min").map(to_json).unwrap_or(JsonValue::Null),
+            "max": d.get("max").map(to_json).unwrap_or(JsonValue::Null),
+            "histogram": buckets_json(&facets, "dateHistogram"),
+        }),
+        None => JsonValue::Null,
+    };
+
+    let strings = match first_doc(&facets, "strings") {
+        Some(s) => json!({
+            "count": as_i64(s.get("count")),
+            "minLength": as_i64(s.get("min")),
+            "maxLength": as_i64(s.get("max")),
+            "meanLength": as_f64(s.get("mean")),
+            "lengthHistogram": buckets_json(&facets, "stringLengthHistogram"),
+        }),
+        None => JsonValue::Null,
+    };
+
+    Ok(HttpResponse::Ok().json(json!({
+        "database": db_name,
+        "collection": coll_name,
+        "field": field,
+        "mode": if full_scan { "full" } else { "sampled" },
+        "sampleSize": if full_scan { JsonValue::Null } else { json!(sample_size) },
+        "count": count,
+        "nulls": nulls,
+        "types": types,
+        "cardinality": cardinality,
+        "topValues": top_values,
+        "numeric": numeric,
+        "dates": dates,
+        "strings": strings,
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct SizeStatsQuery {
+    // "sampled" (default) runs over a $sample, "full" scans the whole collection
+    mode: Option<String>,
+    sample: Option<i64>,
+    top: Option<i64>,
+    buckets: Option<i32>,
+    fields: Option<i64>,
+}
+
+fn limit_percentage(size: i64) -> f64 {
+    (size as f64 / MAX_BSON_SIZE as f64 * 10000.0).round() / 100.0
+}
+
+#[get("/collections/{db_name}/{coll_name}/sizes")]
+pub async fn document_sizes(
+    path: web::Path<(String, String)>,
+    query: web::Query<SizeStatsQuery>,
+    data: web::Data<Client>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    let coll = data.database(&db_name).collection::<Document>(&coll_name);
+
+    let full_scan = match query.mode.as_deref() {
+        None | Some("sampled") => false,
+        Some("full") => true,
+        Some(other) => return Ok(HttpResponse::BadRequest().body(format!("unknown mode: {}", other))),
+    };
+    let sample_size = query.sample.unwrap_or(DEFAULT_STATS_SAMPLE).max(1);
+    let top = query.top.unwrap_or(DEFAULT_TOP_N).max(1);
+    let buckets = query.buckets.unwrap_or(DEFAULT_BUCKETS).max(1);
+    let top_fields = query.fields.unwrap_or(DEFAULT_TOP_FIELDS).max(1);
+    let near_limit = (MAX_BSON_SIZE as f64 * NEAR_LIMIT_PERCENTAGE / 100.0) as i64;
+
+    let mut pipeline: Vec<Document> = Vec::new();
+    if !full_scan {
+        pipeline.push(doc! { "$sample": { "size": sample_size } });
+    }
+    let size = doc! { "$project": { "size": { "$bsonSize": "$$ROOT" } } };
+    pipeline.push(doc! { "$facet": {
+        "summary": [
+            size.clone(),
+            { "$group": {
+                "_id": Bson::Null,
+                "count": { "$sum": 1 },
+                "total": { "$sum": "$size" },
+                "min": { "$min": "$size" },
+                "max": { "$max": "$size" },
+                "mean": { "$avg": "$size" },
+                "nearLimit": { "$sum": { "$cond": [ { "$gte": [ "$size", near_limit ] }, 1, 0 ] } },
+            } },
+        ],
+        "top": [
+            size.clone(),
+            { "$sort": { "size": -1 } },
+            { "$limit": top },
+        ],
+        "histogram": [
+            size,
+            { "$bucketAuto": { "groupBy": "$size", "buckets": buckets } },
+        ],
+        // Bytes per top-level field: the size of `{k: v}` minus the 5 bytes of document framing.
+        "fields": [
+            { "$project": { "_id": 0, "f": { "$objectToArray": "$$ROOT" } } },
+            { "$unwind": "$f" },
+            { "$project": {
+                "k": "$f.k",
+                "bytes": { "$subtract": [ { "$bsonSize": { "$arrayToObject": [ [ "$f" ] ] } }, 5 ] },
+                "len": { "$cond": [ { "$isArray": "$f.v" }, { "$size": "$f.v" }, Bson::Null ] },
+            } },
+            { "$group": {
+                "_id": "$k",
+                "documents": { "$sum": 1 },
+                "total": { "$sum": "$bytes" },
+                "max": { "$max": "$bytes" },
+                "mean": { "$avg": "$bytes" },
+                "maxArrayLength": { "$max": "$len" },
+                "meanArrayLength": { "$avg": "$len" },
+            } },
+            { "$sort": { "total": -1, "_id": 1 } },
+            { "$limit": top_fields },
+        ],
+    } });
+
+    let facets = match coll.aggregate(pipeline).allow_disk_use(full_scan).await {
+        Ok(mut cursor) => match cursor.try_next().await {
+            Ok(d) => d.unwrap_or_default(),
+            Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("aggregate failed: {}", e))),
+        },
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(format!("aggregate failed: {}", e))),
+    };
+
+    let summary = first_doc(&facets, "summary");
+    let total_bytes = as_i64(summary.and_then(|s| s.get("total")));
+    let max = as_i64(summary.and_then(|s| s.get("max")));
+
+    let largest: Vec<JsonValue> = facets
+        .get_array("top")
+        .map(|a| {
+            a.iter()
+                .filter_map(|d| d.as_document())
+                .map(|d| {
+                    let bytes = as_i64(d.get("size"));
+                    json!({
+                        "_id": d.get("_id").map(to_json).unwrap_or(JsonValue::Null),
+                        "size": bytes,
+                        "limitPercentage": limit_percentage(bytes),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let fields: Vec<JsonValue> = facets
+        .get_array("fields")
+        .map(|a| {
+            a.iter()
+                .filter_map(|d| d.as_document())
+                .map(|d| {
+                    let bytes = as_i64(d.get("total"));
+                    json!({
+                        "field": d.get("_id").map(to_json).unwrap_or(JsonValue::Null),
+                        "documents": as_i64(d.get("documents")),
+                        "totalBytes": bytes,
+                        "maxBytes": as_i64(d.get("max")),
+                        "meanBytes": as_f64(d.get("mean")),
+                        "percentage": if total_bytes > 0 { (bytes as f64 / total_bytes as f64 * 10000.0).round() / 100.0 } else { 0.0 },
+                        "maxArrayLength": as_f64(d.get("maxArrayLength")).map(|v| v as i64),
+                        "meanArrayLength": as_f64(d.get("meanArrayLength")),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(HttpResponse::Ok().json(json!({
+        "database": db_name,
+        "collection": coll_name,
+        "mode": if full_scan { "full" } else { "sampled" },
+        "sampleSize": if full_scan { JsonValue::Null } else { json!(sample_size) },
+        "count": as_i64(summary.and_then(|s| s.get("count"))),
+        "totalBytes": total_bytes,
+        "minBytes": as_i64(summary.and_then(|s| s.get("min"))),
+        "maxBytes": max,
+        "meanBytes": as_f64(summary.and_then(|s| s.get("mean"))),
+        "maxLimitPercentage": limit_percentage(max),
+        "nearLimit": as_i64(summary.and_then(|s| s.get("nearLimit"))),
+        "largest": largest,
+        "histogram": buckets_json(&facets, "histogram"),
+        "fields": fields,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimate_cardinality, limit_percentage, unwind_path, MAX_BSON_SIZE};
+    use mongodb::bson::doc;
+
+    #[test]
+    fn unwinds_array_segments() {
+        assert_eq!(unwind_path("address.city"), (vec![], "address.city".to_string()));
+        assert_eq!(
+            unwind_path("orders[].items[].sku"),
+            (
+                vec![doc! { "$unwind": "$orders" }, doc! { "$unwind": "$orders.items" }],
+                "orders.items.sku".to_string()
+            )
+        );
+        assert_eq!(unwind_path("tags[]"), (vec![doc! { "$unwind": "$tags" }], "tags".to_string()));
+    }
+
+    #[test]
+    fn scales_singletons_only() {
+        assert_eq!(estimate_cardinality(40, 10, 1.0), 40);
+        assert_eq!(estimate_cardinality(40, 10, 100.0), 130);
+    }
+
+    #[test]
+    fn measures_against_the_document_limit() {
+        assert_eq!(limit_percentage(MAX_BSON_SIZE), 100.0);
+        assert_eq!(limit_percentage(MAX_BSON_SIZE / 4), 25.0);
+        assert_eq!(limit_percentage(0), 0.0);
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::Mutex;
+
+const DEFAULT_DATA_DIR: &str = ".globe";
+
+/// Directory for globe's own metadata (schemas, snapshots, jobs). Override with `GLOBE_DATA_DIR`.
+pub fn data_dir() -> PathBuf {
+    env::var("GLOBE_DATA_DIR")
+        .ok()
+        .map(|dir| dir.trim().to_string())
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR))
+}
+
+/// A value persisted as a pretty-printed JSON file under `data_dir()`.
+/// Every `update` rewrites the whole file, so keep the contents small. A file that does not
+/// parse is moved aside to `<name>.corrupt-<unix time>` before the store starts empty, so the
+/// first write cannot destroy it.
+pub struct JsonStore<T> {
+    path: PathBuf,
+    data: Mutex<T>,
+}
+
+impl<T: Serialize + DeserializeOwned + Default + Clone> JsonStore<T> {
+    pub fn open(file_name: &str) -> Self {
+        Self::open_path(data_dir().join(file_name))
+    }
+
+    fn open_path(path: PathBuf) -> Self {
+        let data = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+                let aside = path.with_extension(format!("json.corrupt-{}", secs));
+                if let Err(move_err) = fs::rename(&path, &aside) {
+                    // starting empty would overwrite the file on the first update
+                    panic!("{} does not parse ({}) and could not be moved aside: {}", path.display(), e, move_err);
+                }
+                eprintln!("failed to parse {}: {}; moved it to {}", path.display(), e, aside.display());
+                T::default()
+            }),
+            Err(_) => T::default(),
+        };
+        Self {
+            path,
+            data: Mutex::new(data),
+        }
+    }
+
+    pub async fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        let data = self.data.lock().await;
+        f(&data)
+    }
+
+    /// Applies `f` to a copy and keeps it only once it is on disk, so a failed write leaves
+    /// memory matching the file.
+    pub async fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> std::io::Result<R> {
+        let mut data = self.data.lock().await;
+        let mut next = data.clone();
+        let result = f(&mut next);
+        write_json(&self.path, &next)?;
+        *data = next;
+        Ok(result)
+    }
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let bytes = serde_json::to_vec_pretty(value).map_err(std::io::Error::other)?;
+    // write-then-rename so a crash mid-write never leaves a truncated file behind
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonStore;
+    use std::{collections::BTreeMap, fs};
+
+    #[tokio::test]
+    async fn moves_unparseable_files_aside_before_writing() {
+        let dir = std::env::temp_dir().join(format!("globe-store-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("things.json");
+        fs::write(&path, b"{ not json").unwrap();
+
+        let store: JsonStore<BTreeMap<String, u32>> = JsonStore::open_path(path.clone());
+        store.update(|m| m.insert("a".to_string(), 1)).await.unwrap();
+
+        let aside: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().starts_with("things.json.corrupt-"))
+            .collect();
+        assert_eq!(aside.len(), 1);
+        assert_eq!(fs::read(aside[0].path()).unwrap(), b"{ not json");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\n  \"a\": 1\n}");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,360 @@
+use std::{
+    collections::HashMap,
+    env,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use actix_web::{get, post, web, HttpResponse};
+use mongodb::{
+    bson::{doc, oid::ObjectId, Document},
+    options::{ReadConcern, WriteConcern},
+    Client, ClientSession,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::revisions::RevisionStore;
+
+// Transactions untouched for this long are aborted by the expiry task.
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 60;
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+struct PendingRevision {
+    database: String,
+    collection: String,
+    operation: String,
+    before: Document,
+}
+
+/// An open session with a transaction in progress. Revisions for writes made inside it are held
+/// back until commit so an abort leaves no history behind. Generic over the session only so the
+/// locking can be tested without a server.
+pub struct Transaction<S = ClientSession> {
+    session: S,
+    started: Instant,
+    last_used: Instant,
+    operations: u64,
+    pending_revisions: Vec<PendingRevision>,
+    // set under the lock by commit, abort and expiry; requests queued behind them must not run
+    // their write on the ended session, where it would commit on its own
+    closed: bool,
+}
+
+impl<S> Transaction<S> {
+    pub fn session(&mut self) -> &mut S {
+        &mut self.session
+    }
+
+    pub fn defer_revision(&mut self, database: &str, collection: &str, operation: &str, before: &Document) {
+        self.pending_revisions.push(PendingRevision {
+            database: database.to_string(),
+            collection: collection.to_string(),
+            operation: operation.to_string(),
+            before: before.clone(),
+        });
+    }
+}
+
+/// Open transactions keyed by id. Each is locked for the duration of one operation, so
+/// concurrent requests against the same transaction run one after another.
+pub struct TransactionStore<S = ClientSession> {
+    active: Mutex<HashMap<String, Arc<Mutex<Transaction<S>>>>>,
+    idle_timeout: Duration,
+}
+
+impl TransactionStore {
+    pub fn new() -> Self {
+        let secs = env::var("GLOBE_TRANSACTION_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(DEFAULT_IDLE_TIMEOUT_SECS);
+        Self::with_idle_timeout(Duration::from_secs(secs))
+    }
+
+    async fn begin(&self, client: &Client) -> mongodb::error::Result<String> {
+        let mut session = client.start_session().await?;
+        session
+            .start_transaction()
+            .read_concern(ReadConcern::snapshot())
+            .write_concern(WriteConcern::majority())
+            .await?;
+        let id = ObjectId::new().to_hex();
+        self.insert(id.clone(), session).await;
+        Ok(id)
+    }
+
+    /// Aborts transactions idle for longer than the timeout. Ones busy with an operation are
+    /// skipped until the next check.
+    async fn expire_idle(&self) {
+        for (id, mut transaction) in self.take_idle().await {
+            if let Err(e) = transaction.session.abort_transaction().await {
+                eprintln!("failed to abort expired transaction {}: {}", id, e);
+            } else {
+                eprintln!("aborted transaction {} after {}s idle", id, self.idle_timeout.as_secs());
+            }
+        }
+    }
+}
+
+impl<S> TransactionStore<S> {
+    fn with_idle_timeout(idle_timeout: Duration) -> Self {
+        Self {
+            active: Mutex::new(HashMap::new()),
+            idle_timeout,
+        }
+    }
+
+    async fn insert(&self, id: String, session: S) {
+        let now = Instant::now();
+        let transaction = Transaction {
+            session,
+            started: now,
+            last_used: now,
+            operations: 0,
+            pending_revisions: Vec::new(),
+            closed: false,
+        };
+        self.active.lock().await.insert(id, Arc::new(Mutex::new(transaction)));
+    }
+
+    /// Locks the transaction for one operation and marks it as used. `None` when it is unknown,
+    /// or was committed, aborted or expired while this request waited for the lock.
+    pub async fn acquire(&self, id: &str) -> Option<OwnedMutexGuard<Transaction<S>>> {
+        let entry = self.active.lock().await.get(id).cloned()?;
+        let mut transaction = entry.lock_owned().await;
+        if transaction.closed || !self.active.lock().await.contains_key(id) {
+            return None;
+        }
+        transaction.last_used = Instant::now();
+        transaction.operations += 1;
+        Some(transaction)
+    }
+
+    /// Removes the transaction for commit or abort, once operations ahead of it have finished.
+    async fn take(&self, id: &str) -> Option<OwnedMutexGuard<Transaction<S>>> {
+        let entry = self.active.lock().await.remove(id)?;
+        let mut transaction = entry.lock_owned().await;
+        transaction.closed = true;
+        Some(transaction)
+    }
+
+    async fn take_idle(&self) -> Vec<(String, OwnedMutexGuard<Transaction<S>>)> {
+        let mut expired = Vec::new();
+        let mut active = self.active.lock().await;
+        let ids: Vec<String> = active.keys().cloned().collect();
+        for id in ids {
+            let Some(mut guard) = active.get(&id).and_then(|t| t.clone().try_lock_owned().ok()) else {
+                continue;
+            };
+            if guard.last_used.elapsed() >= self.idle_timeout {
+                active.remove(&id);
+                guard.closed = true;
+                expired.push((id, guard));
+            }
+        }
+        expired
+    }
+}
+
+pub async fn run_expiry(transactions: web::Data<TransactionStore>) {
+    let mut interval = tokio::time::interval(EXPIRY_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        transactions.expire_idle().await;
+    }
+}
+
+fn version_at_least(version: &str, major: u32, minor: u32) -> bool {
+    let mut parts = version.split('.').map(|p| p.parse::<u32>().unwrap_or(0));
+    let found = (parts.next().unwrap_or(0), parts.next().unwrap_or(0));
+    found >= (major, minor)
+}
+
+/// Whether a deployment can run multi-document transactions, judged from its `hello` reply and
+/// `buildInfo` version: replica sets from 4.0, sharded clusters (mongos) from 4.2.
+fn transaction_support(hello: &Document, version: &str) -> Result<(), String> {
+    if hello.get_str("msg") == Ok("isdbgrid") {
+        if version_at_least(version, 4, 2) {
+            return Ok(());
+        }
+        return Err(format!("transactions on sharded clusters need MongoDB 4.2 or later (server is {})", version));
+    }
+    if hello.contains_key("setName") {
+        if version_at_least(version, 4, 0) {
+            return Ok(());
+        }
+        return Err(format!("transactions need MongoDB 4.0 or later (server is {})", version));
+    }
+    Err("transactions need a replica set or sharded cluster; this server is standalone".to_string())
+}
+
+async fn check_support(client: &Client) -> mongodb::error::Result<Result<(), String>> {
+    let admin = client.database("admin");
+    let hello = admin.run_command(doc! { "hello": 1 }).await?;
+    let build = admin.run_command(doc! { "buildInfo": 1 }).await?;
+    Ok(transaction_support(&hello, build.get_str("version").unwrap_or("")))
+}
+
+#[post("/transactions")]
+pub async fn start_transaction(
+    data: web::Data<Client>,
+    transactions: web::Data<TransactionStore>,
+) -> actix_web::Result<HttpResponse> {
+    match check_support(&data).await {
+        Ok(Ok(())) => {}
+        Ok(Err(reason)) => return Ok(HttpResponse::BadRequest().body(reason)),
+        Err(e) => {
+            eprintln!("transaction support check error: {}", e);
+            return Ok(HttpResponse::InternalServerError().body(format!("failed to query server topology: {}", e)));
+        }
+    }
+    match transactions.begin(&data).await {
+        Ok(id) => Ok(HttpResponse::Ok().json(json!({
+            "id": id,
+            "idleTimeoutSecs": transactions.idle_timeout.as_secs(),
+        }))),
+        Err(e) => {
+            eprintln!("start transaction error: {}", e);
+            Ok(HttpResponse::InternalServerError().body(format!("failed to start transaction: {}", e)))
+        }
+    }
+}
+
+#[get("/transactions")]
+pub async fn list_transactions(transactions: web::Data<TransactionStore>) -> actix_web::Result<HttpResponse> {
+    let entries: Vec<(String, Arc<Mutex<Transaction>>)> = transactions
+        .active
+        .lock()
+        .await
+        .iter()
+        .map(|(id, t)| (id.clone(), t.clone()))
+        .collect();
+    let mut listed = Vec::new();
+    for (id, entry) in entries {
+        // an operation in progress holds the lock; report it as busy rather than waiting
+        let item = match entry.try_lock() {
+            Ok(t) => json!({
+                "id": id,
+                "ageSecs": t.started.elapsed().as_secs(),
+                "idleSecs": t.last_used.elapsed().as_secs(),
+                "operations": t.operations,
+                "busy": false,
+            }),
+            Err(_) => json!({ "id": id, "busy": true }),
+        };
+        listed.push(item);
+    }
+    Ok(HttpResponse::Ok().json(listed))
+}
+
+#[post("/transactions/{id}/commit")]
+pub async fn commit_transaction(
+    path: web::Path<String>,
+    transactions: web::Data<TransactionStore>,
+    revisions: web::Data<RevisionStore>,
+) -> actix_web::Result<HttpResponse> {
+    let id = path.into_inner();
+    let Some(mut transaction) = transactions.take(&id).await else {
+        return Ok(HttpResponse::NotFound().body("transaction not found or expired"));
+    };
+    if let Err(e) = transaction.session.commit_transaction().await {
+        eprintln!("commit transaction error: {}", e);
+        return Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("commit failed: {}", e),
+            "labels": e.labels(),
+        })));
+    }
+    for pending in &transaction.pending_revisions {
+        revisions
+            .record(&pending.database, &pending.collection, &pending.operation, &pending.before)
+            .await;
+    }
+    Ok(HttpResponse::Ok().json(json!({ "committed": true, "operations": transaction.operations })))
+}
+
+#[post("/transactions/{id}/abort")]
+pub async fn abort_transaction(
+    path: web::Path<String>,
+    transactions: web::Data<TransactionStore>,
+) -> actix_web::Result<HttpResponse> {
+    let id = path.into_inner();
+    let Some(mut transaction) = transactions.take(&id).await else {
+        return Ok(HttpResponse::NotFound().body("transaction not found or expired"));
+    };
+    match transaction.session.abort_transaction().await {
+        Ok(()) => Ok(HttpResponse::Ok().json(json!({ "aborted": true, "operations": transaction.operations }))),
+        Err(e) => {
+            eprintln!("abort transaction error: {}", e);
+            Ok(HttpResponse::InternalServerError().body(format!("abort failed: {}", e)))
+        }
+    }
+}
+
+/// `?txn=<id>` on the document CRUD endpoints.
+#[derive(Deserialize)]
+pub struct TransactionQuery {
+    pub txn: Option<String>,
+}
+
+/// The transaction a CRUD request runs in, if it named one; 404 when the id is unknown.
+pub async fn join(
+    transactions: &TransactionStore,
+    id: Option<&str>,
+) -> Result<Option<OwnedMutexGuard<Transaction>>, HttpResponse> {
+    match id {
+        None => Ok(None),
+        Some(id) => match transactions.acquire(id).await {
+            Some(t) => Ok(Some(t)),
+            None => Err(HttpResponse::NotFound().body("transaction not found or expired")),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{transaction_support, TransactionStore};
+    use mongodb::bson::doc;
+    use std::{sync::Arc, time::Duration};
+
+    #[test]
+    fn requires_replica_set_or_mongos() {
+        assert!(transaction_support(&doc! { "isWritablePrimary": true }, "7.0.2").is_err());
+        assert!(transaction_support(&doc! { "setName": "rs0" }, "4.0.0").is_ok());
+        assert!(transaction_support(&doc! { "setName": "rs0" }, "3.6.8").is_err());
+        assert!(transaction_support(&doc! { "msg": "isdbgrid" }, "4.0.10").is_err());
+        assert!(transaction_support(&doc! { "msg": "isdbgrid" }, "6.0.1").is_ok());
+    }
+
+    #[tokio::test]
+    async fn operations_queued_behind_a_commit_do_not_run() {
+        let store = Arc::new(TransactionStore::<()>::with_idle_timeout(Duration::from_secs(60)));
+        store.insert("t".to_string(), ()).await;
+        let running = store.acquire("t").await.unwrap();
+
+        // a write queued before the commit, the commit, and a write queued after it
+        let early = tokio::spawn({
+            let store = store.clone();
+            async move { store.acquire("t").await.is_some() }
+        });
+        tokio::task::yield_now().await;
+        let commit = tokio::spawn({
+            let store = store.clone();
+            async move { store.take("t").await.map(|t| t.operations) }
+        });
+        tokio::task::yield_now().await;
+        let late = tokio::spawn({
+            let store = store.clone();
+            async move { store.acquire("t").await.is_some() }
+        });
+        tokio::task::yield_now().await;
+
+        drop(running);
+        assert!(!early.await.unwrap());
+        assert_eq!(commit.await.unwrap(), Some(1));
+        assert!(!late.await.unwrap());
+        assert!(store.acquire("t").await.is_none());
+    }
+}
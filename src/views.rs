@@ -0,0 +1,337 @@
+use std::collections::{HashSet, VecDeque};
+
+use actix_web::{get, post, put, web, HttpResponse};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, Bson, Document},
+    results::CollectionType,
+    Client, Database,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateViewRequest {
+    name: String,
+    view_on: String,
+    pipeline: Vec<JsonValue>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateViewRequest {
+    // Falls back to the view's current source collection when omitted.
+    view_on: Option<String>,
+    pipeline: Vec<JsonValue>,
+}
+
+pub struct ViewSpec {
+    pub name: String,
+    pub view_on: String,
+    pub pipeline: Vec<Document>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ViewResponse {
+    name: String,
+    view_on: String,
+    pipeline: Vec<JsonValue>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ViewDependent {
+    name: String,
+    view_on: String,
+    // Either "viewOn" or the pipeline stage ($lookup, $unionWith, ...) that reads the namespace.
+    via: String,
+    // 1 for views that read the collection directly, 2+ for views stacked on those views.
+    depth: usize,
+}
+
+impl From<&ViewSpec> for ViewResponse {
+    fn from(spec: &ViewSpec) -> Self {
+        Self {
+            name: spec.name.clone(),
+            view_on: spec.view_on.clone(),
+            pipeline: spec.pipeline.iter().map(document_to_json).collect(),
+        }
+    }
+}
+
+fn document_to_json(doc: &Document) -> JsonValue {
+    bson::to_bson(doc)
+        .ok()
+        .and_then(|b| serde_json::to_value(&b).ok())
+        .unwrap_or(JsonValue::Null)
+}
+
+pub fn parse_pipeline(stages: &[JsonValue]) -> Result<Vec<Document>, String> {
+    stages
+        .iter()
+        .enumerate()
+        .map(|(index, stage)| match bson::to_bson(stage) {
+            Ok(Bson::Document(d)) => Ok(d),
+            Ok(_) => Err(format!("pipeline stage {} must be an object", index)),
+            Err(e) => Err(format!("invalid pipeline stage {}: {}", index, e)),
+        })
+        .collect()
+}
+
+pub async fn list_view_specs(db: &Database) -> mongodb::error::Result<Vec<ViewSpec>> {
+    let mut cursor = db.list_collections().filter(doc! { "type": "view" }).await?;
+    let mut views = Vec::new();
+    while let Some(spec) = cursor.try_next().await? {
+        if spec.collection_type != CollectionType::View {
+            continue;
+        }
+        views.push(ViewSpec {
+            name: spec.name,
+            view_on: spec.options.view_on.unwrap_or_default(),
+            pipeline: spec.options.pipeline.unwrap_or_default(),
+        });
+    }
+    views.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(views)
+}
+
+/// Collects every namespace a pipeline reads besides its source: `$lookup`, `$graphLookup`
+/// and `$unionWith` targets, including those nested in sub-pipelines and `$facet` branches.
+pub fn pipeline_references(pipeline: &[Document]) -> Vec<(String, String)> {
+    let mut refs = Vec::new();
+    for stage in pipeline {
+        collect_stage_references(stage, &mut refs);
+    }
+    refs
+}
+
+fn collect_stage_references(stage: &Document, refs: &mut Vec<(String, String)>) {
+    for (op, spec) in stage.iter() {
+        match (op.as_str(), spec) {
+            ("$lookup", Bson::Document(lookup)) | ("$graphLookup", Bson::Document(lookup)) => {
+                if let Ok(from) = lookup.get_str("from") {
+                    refs.push((op.clone(), from.to_string()));
+                }
+                if let Ok(inner) = lookup.get_array("pipeline") {
+                    collect_array_references(inner, refs);
+                }
+            }
+            ("$unionWith", Bson::String(coll)) => refs.push((op.clone(), coll.clone())),
+            ("$unionWith", Bson::Document(union)) => {
+                if let Ok(coll) = union.get_str("coll") {
+                    refs.push((op.clone(), coll.to_string()));
+                }
+                if let Ok(inner) = union.get_array("pipeline") {
+                    collect_array_references(inner, refs);
+                }
+            }
+            ("$facet", Bson::Document(facets)) => {
+                for (_, branch) in facets.iter() {
+                    if let Bson::Array(inner) = branch {
+                        collect_array_references(inner, refs);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collect_array_references(stages: &[Bson], refs: &mut Vec<(String, String)>) {
+    for stage in stages {
+        if let Bson::Document(d) = stage {
+            collect_stage_references(d, refs);
+        }
+    }
+}
+
+fn find_dependents(views: &[ViewSpec], collection: &str) -> Vec<ViewDependent> {
+    let mut out = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut queue: VecDeque<(String, usize)> = VecDeque::new();
+    queue.push_back((collection.to_string(), 0));
+    seen.insert(collection.to_string());
+
+    while let Some((target, depth)) = queue.pop_front() {
+        for view in views {
+            if seen.contains(&view.name) {
+                continue;
+            }
+            let via = if view.view_on == target {
+                Some("viewOn".to_string())
+            } else {
+                pipeline_references(&view.pipeline)
+                    .into_iter()
+                    .find(|(_, coll)| coll == &target)
+                    .map(|(stage, _)| stage)
+            };
+            if let Some(via) = via {
+                seen.insert(view.name.clone());
+                out.push(ViewDependent {
+                    name: view.name.clone(),
+                    view_on: view.view_on.clone(),
+                    via,
+                    depth: depth + 1,
+                });
+                queue.push_back((view.name.clone(), depth + 1));
+            }
+        }
+    }
+
+    out
+}
+
+#[get("/views/{db_name}")]
+pub async fn list_views(path: web::Path<String>, data: web::Data<Client>) -> actix_web::Result<HttpResponse> {
+    let db_name = path.into_inner();
+    match list_view_specs(&data.database(&db_name)).await {
+        Ok(views) => {
+            let views: Vec<ViewResponse> = views.iter().map(ViewResponse::from).collect();
+            Ok(HttpResponse::Ok().json(serde_json::json!({"database": db_name, "views": views})))
+        }
+        Err(e) => {
+            eprintln!("list views error: {}", e);
+            Ok(HttpResponse::InternalServerError().body("failed to list views"))
+        }
+    }
+}
+
+#[post("/views/{db_name}")]
+pub async fn create_view(
+    path: web::Path<String>,
+    body: web::Json<CreateViewRequest>,
+    data: web::Data<Client>,
+) -> actix_web::Result<HttpResponse> {
+    let db_name = path.into_inner();
+    let req = body.into_inner();
+    let pipeline = match parse_pipeline(&req.pipeline) {
+        Ok(p) => p,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+
+    let command = doc! {
+        "create": &req.name,
+        "viewOn": &req.view_on,
+        "pipeline": pipeline,
+    };
+    match data.database(&db_name).run_command(command).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "database": db_name,
+            "view": req.name,
+            "viewOn": req.view_on,
+        }))),
+        Err(e) => {
+            eprintln!("create view error: {}", e);
+            Ok(HttpResponse::InternalServerError().body(format!("create view failed: {}", e)))
+        }
+    }
+}
+
+#[put("/views/{db_name}/{view_name}")]
+pub async fn update_view(
+    path: web::Path<(String, String)>,
+    body: web::Json<UpdateViewRequest>,
+    data: web::Data<Client>,
+) -> actix_web::Result<HttpResponse> {
+    let (db_name, view_name) = path.into_inner();
+    let req = body.into_inner();
+    let db = data.database(&db_name);
+    let pipeline = match parse_pipeline(&req.pipeline) {
+        Ok(p) => p,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+
+    // collMod requires viewOn alongside pipeline, so reuse the current source when not given.
+    let view_on = match req.view_on {
+        Some(v) => v,
+        None => match list_view_specs(&db).await {
+            Ok(views) => match views.into_iter().find(|v| v.name == view_name) {
+                Some(v) => v.view_on,
+                None => return Ok(HttpResponse::NotFound().body("view not found")),
+            },
+            Err(e) => {
+                eprintln!("list views error: {}", e);
+                return Ok(HttpResponse::InternalServerError().body("failed to list views"));
+            }
+        },
+    };
+
+    let command = doc! {
+        "collMod": &view_name,
+        "viewOn": &view_on,
+        "pipeline": pipeline,
+    };
+    match db.run_command(command).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "database": db_name,
+            "view": view_name,
+            "viewOn": view_on,
+        }))),
+        Err(e) => {
+            eprintln!("collMod view error: {}", e);
+            Ok(HttpResponse::InternalServerError().body(format!("update view failed: {}", e)))
+        }
+    }
+}
+
+#[get("/collections/{db_name}/{coll_name}/views")]
+pub async fn dependent_views(path: web::Path<(String, String)>, data: web::Data<Client>) -> actix_web::Result<HttpResponse> {
+    let (db_name, coll_name) = path.into_inner();
+    match list_view_specs(&data.database(&db_name)).await {
+        Ok(views) => {
+            let dependents = find_dependents(&views, &coll_name);
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "database": db_name,
+                "collection": coll_name,
+                "dependents": dependents,
+            })))
+        }
+        Err(e) => {
+            eprintln!("list views error: {}", e);
+            Ok(HttpResponse::InternalServerError().body("failed to list views"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_dependents, pipeline_references, ViewSpec};
+    use mongodb::bson::doc;
+
+    fn view(name: &str, view_on: &str, pipeline: Vec<mongodb::bson::Document>) -> ViewSpec {
+        ViewSpec { name: name.to_string(), view_on: view_on.to_string(), pipeline }
+    }
+
+    #[test]
+    fn finds_nested_pipeline_references() {
+        let pipeline = vec![
+            doc! { "$lookup": { "from": "users", "as": "u", "pipeline": [ { "$unionWith": "archived_users" } ] } },
+            doc! { "$facet": { "a": [ { "$graphLookup": { "from": "orgs", "startWith": "$org", "connectFromField": "parent", "connectToField": "_id", "as": "tree" } } ] } },
+            doc! { "$unionWith": { "coll": "legacy", "pipeline": [] } },
+        ];
+        let refs: Vec<String> = pipeline_references(&pipeline).into_iter().map(|(_, c)| c).collect();
+        assert_eq!(refs, vec!["users", "archived_users", "orgs", "legacy"]);
+    }
+
+    #[test]
+    fn walks_stacked_views() {
+        let views = vec![
+            view("active_orders", "orders", vec![doc! { "$match": { "active": true } }]),
+            view("active_orders_eu", "active_orders", vec![]),
+            view("customers_with_orders", "customers", vec![doc! { "$lookup": { "from": "orders", "localField": "_id", "foreignField": "customer", "as": "orders" } }]),
+            view("unrelated", "products", vec![]),
+        ];
+        let deps = find_dependents(&views, "orders");
+        let names: Vec<(&str, &str, usize)> = deps.iter().map(|d| (d.name.as_str(), d.via.as_str(), d.depth)).collect();
+        assert_eq!(
+            names,
+            vec![
+                ("active_orders", "viewOn", 1),
+                ("customers_with_orders", "$lookup", 1),
+                ("active_orders_eu", "viewOn", 2),
+            ]
+        );
+    }
+}